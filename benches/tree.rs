@@ -1,5 +1,9 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, PlotConfiguration};
-use lustre::{hittables::Hittable, render, scenes, tree::Tree};
+use lustre::{
+    hittables::Hittable,
+    render, scenes,
+    tree::{Tree, TreeConfig},
+};
 use rand::SeedableRng;
 
 fn bench_gen(c: &mut Criterion) {
@@ -51,7 +55,9 @@ fn bench_hit(c: &mut Criterion) {
 
         // ray configuration
         // viewport@ (0.5,0.5) is center of the image. should be guaranteed to hit with specific scenes
-        let ray = lustre::scenes::get_camera(scene).get_ray(0.5, 0.5, &mut rng);
+        let ray = lustre::scenes::get_camera(scene)
+            .get_ray(0.5, 0.5, &mut rng)
+            .unwrap();
 
         // correctness check
         assert!(
@@ -83,7 +89,11 @@ fn bench_multi_hit(c: &mut Criterion) {
 
     // ray configuration
     // viewport@ (0.5,0.5) is center of the image. should be guaranteed to hit with specific scenes
-    let mut ray_gen = || lustre::scenes::get_camera(scene).get_ray(0.5, 0.5, &mut rng);
+    let mut ray_gen = || {
+        lustre::scenes::get_camera(scene)
+            .get_ray(0.5, 0.5, &mut rng)
+            .unwrap()
+    };
 
     // correctness check
     assert!(
@@ -130,12 +140,32 @@ fn bench_full_scenes(c: &mut Criterion) {
                 image_width: 100,
                 samples_per_pixel: 100,
                 bounce_depth: 50,
+                roulette_depth: 5,
+                checkpoint: None,
+                time_limit: None,
+                save_state: None,
+                resume: None,
+                noise_threshold: None,
+                sample_map: None,
+                tile_size: 32,
+                spectral: false,
+                spatial_splits: None,
+                environment: None,
+                environment_rotation: 0.0,
+                environment_intensity: 1.0,
+                sky: false,
+                sun_elevation: 45.0,
+                turbidity: 3.0,
                 scene,
+                scene_file: None,
+                model: None,
+                projection: None,
                 seed: Some(0),
                 verbosity: lustre::cli::Verbosity::new(0, 1),
             },
             &mut rng,
-        );
+        )
+        .unwrap();
 
         let scene_name = format!("{scene:?}");
         bench_group.bench_function(BenchmarkId::from_parameter(scene_name), |b| {
@@ -161,7 +191,9 @@ fn bench_miss(c: &mut Criterion) {
         let tree = Tree::new(geo, 0.0, 1.0);
 
         // ray configuration
-        let mut ray = lustre::scenes::get_camera(scene).get_ray(0.5, 0.5, &mut rng);
+        let mut ray = lustre::scenes::get_camera(scene)
+            .get_ray(0.5, 0.5, &mut rng)
+            .unwrap();
         // negating all components "flips" the vector
         ray.direction *= -1.0;
 
@@ -179,5 +211,94 @@ fn bench_miss(c: &mut Criterion) {
     }
 }
 
-criterion_group! {benches, bench_gen, bench_hit, bench_miss, bench_multi_hit, bench_full_scenes}
+fn bench_model(c: &mut Criterion) {
+    // set LUSTRE_BENCH_MODEL to the path of an OBJ, PLY, or glTF model, like the Stanford bunny, to run these
+    let Some(path) = std::env::var_os("LUSTRE_BENCH_MODEL") else {
+        return;
+    };
+    let scenes::Scene {
+        geometry: geo,
+        camera,
+        ..
+    } = scenes::get_scene_from_model(100, path.as_ref()).unwrap();
+    let model_name = std::path::Path::new(&path)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+
+    // configuration of criterion
+    let mut bench_group = c.benchmark_group("model");
+    bench_group.sample_size(10);
+
+    bench_group.bench_with_input(BenchmarkId::new("tree_gen", &model_name), &geo, |b, s| {
+        b.iter(|| Tree::new(s.clone(), 0.0, 1.0))
+    });
+
+    // rays spread over the whole image
+    let tree = Tree::new(geo, 0.0, 1.0);
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0);
+    let rays: Vec<_> = (0..100)
+        .flat_map(|y| (0..100).map(move |x| (x as f32 / 99.0, y as f32 / 99.0)))
+        .filter_map(|(u, v)| camera.get_ray(u, v, &mut rng))
+        .collect();
+    bench_group.throughput(criterion::Throughput::Elements(rays.len() as u64));
+    bench_group.bench_function(BenchmarkId::new("multi_hit", &model_name), |b| {
+        b.iter(|| {
+            for ray in &rays {
+                tree.hit(ray, 0.001, f32::INFINITY);
+            }
+        })
+    });
+}
+
+fn bench_spatial_splits(c: &mut Criterion) {
+    // configuration of criterion
+    let mut bench_group = c.benchmark_group("spatial_splits");
+    bench_group.sample_size(50);
+
+    // modify scene selection here
+    let scenes_to_check = [scenes::SceneType::CornellBox, scenes::SceneType::DebugFinal];
+    let configs = [
+        ("sah", TreeConfig::default()),
+        ("sbvh", TreeConfig::default().with_spatial_splits(1e-5)),
+    ];
+
+    for scene in scenes_to_check {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0);
+        let geo = scenes::get_geometry(scene, &mut rng, 0.0..1.0);
+        let camera = scenes::get_camera(scene);
+        let scene_name = format!("{scene:?}");
+
+        // rays spread over the whole image
+        let rays: Vec<_> = (0..100)
+            .flat_map(|y| (0..100).map(move |x| (x as f32 / 99.0, y as f32 / 99.0)))
+            .filter_map(|(u, v)| camera.get_ray(u, v, &mut rng))
+            .collect();
+
+        for (config_name, config) in configs {
+            bench_group.throughput(criterion::Throughput::Elements(1));
+            bench_group.bench_with_input(
+                BenchmarkId::new(format!("tree_gen_{config_name}"), &scene_name),
+                &geo,
+                |b, s| b.iter(|| Tree::with_config(s.clone(), 0.0, 1.0, config)),
+            );
+
+            let tree = Tree::with_config(geo.clone(), 0.0, 1.0, config);
+            bench_group.throughput(criterion::Throughput::Elements(rays.len() as u64));
+            bench_group.bench_function(
+                BenchmarkId::new(format!("multi_hit_{config_name}"), &scene_name),
+                |b| {
+                    b.iter(|| {
+                        for ray in &rays {
+                            tree.hit(ray, 0.001, f32::INFINITY);
+                        }
+                    })
+                },
+            );
+        }
+    }
+}
+
+criterion_group! {benches, bench_gen, bench_hit, bench_miss, bench_multi_hit, bench_full_scenes, bench_model, bench_spatial_splits}
 criterion_main!(benches);
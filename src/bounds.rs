@@ -1,6 +1,6 @@
 //! Implementation of bounding volumes
 
-use glam::Vec3A;
+use glam::{Affine3A, BVec3A, Vec3A};
 
 use crate::{ray::Ray, utils::Axis};
 
@@ -99,6 +99,51 @@ impl BoundingBox {
         }
     }
 
+    /// Returns the bounding box of the region inside both this and the other box, if they overlap
+    pub fn intersection(&self, other: &BoundingBox) -> Option<BoundingBox> {
+        let min = self.min.max(other.min);
+        let max = self.max.min(other.max);
+        min.cmple(max).all().then_some(Self { min, max })
+    }
+
+    /// Returns the bounding box of the part of the convex planar `polygon` inside this box, if any
+    ///
+    /// Clips the polygon against each of the box's 6 planes, as in the Sutherland–Hodgman algorithm.
+    pub fn clip_polygon(&self, polygon: &[Vec3A]) -> Option<BoundingBox> {
+        let mut clipped = polygon.to_vec();
+        let mut next = Vec::with_capacity(polygon.len() + 6);
+        for axis_idx in Axis::AXES {
+            // keep the side of each plane facing into the box
+            for (plane, sign) in [(self.min[axis_idx], 1.0), (self.max[axis_idx], -1.0)] {
+                let distance = |point: Vec3A| sign * (point[axis_idx] - plane);
+                next.clear();
+                for (idx, &start) in clipped.iter().enumerate() {
+                    let end = clipped[(idx + 1) % clipped.len()];
+                    let (d_start, d_end) = (distance(start), distance(end));
+                    if d_start >= 0.0 {
+                        next.push(start);
+                    }
+                    if (d_start >= 0.0) != (d_end >= 0.0) {
+                        let mut crossing = start.lerp(end, d_start / (d_start - d_end));
+                        // pin the crossing onto the plane, despite rounding
+                        crossing[axis_idx] = plane;
+                        next.push(crossing);
+                    }
+                }
+                std::mem::swap(&mut clipped, &mut next);
+                if clipped.is_empty() {
+                    return None;
+                }
+            }
+        }
+
+        Some(
+            clipped
+                .into_iter()
+                .fold(BoundingBox::default(), |bbox, point| bbox.add_point(point)),
+        )
+    }
+
     /// Returns a bounding box enclosing this and the given point
     pub fn add_point(&self, point: Vec3A) -> BoundingBox {
         Self {
@@ -107,9 +152,24 @@ impl BoundingBox {
         }
     }
 
+    /// Returns a bounding box enclosing this box after the given affine `transform`
+    ///
+    /// Under rotation, the transformed min and max points no longer span the box,
+    /// so all 8 corners are transformed and enclosed.
+    pub fn transform(&self, transform: &Affine3A) -> BoundingBox {
+        (0..8)
+            .map(|corner| {
+                let use_max = BVec3A::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+                transform.transform_point3a(Vec3A::select(use_max, self.max, self.min))
+            })
+            .fold(BoundingBox::default(), |bbox, corner| {
+                bbox.add_point(corner)
+            })
+    }
+
     /// Returns the vector along the box diagonal (from min point to max point)
     pub fn diagonal(&self) -> Vec3A {
-        debug_assert!(self.min.cmplt(self.max).all());
+        debug_assert!(self.min.cmple(self.max).all());
         self.max - self.min
     }
 
@@ -181,4 +241,37 @@ mod tests {
             "The union of the default bbox with another bbox should be equal to the other bbox"
         )
     }
+
+    #[test]
+    fn clipped_triangle() {
+        let triangle = [
+            Vec3A::ZERO,
+            Vec3A::new(2.0, 0.0, 0.0),
+            Vec3A::new(0.0, 2.0, 0.0),
+        ];
+        let clip = BoundingBox::new(Vec3A::new(1.0, -1.0, -1.0), Vec3A::splat(3.0));
+
+        // only the corner past x = 1 remains
+        let bbox = clip.clip_polygon(&triangle).unwrap();
+        assert!(bbox.min.abs_diff_eq(Vec3A::new(1.0, 0.0, 0.0), 1e-6));
+        assert!(bbox.max.abs_diff_eq(Vec3A::new(2.0, 1.0, 0.0), 1e-6));
+
+        let away = BoundingBox::new(Vec3A::splat(5.0), Vec3A::splat(6.0));
+        assert_eq!(away.clip_polygon(&triangle), None);
+    }
+
+    #[test]
+    fn rotated_transform() {
+        let unit = BoundingBox::new(Vec3A::ZERO, Vec3A::ONE);
+        let rotated = unit.transform(&Affine3A::from_rotation_y(std::f32::consts::FRAC_PI_4));
+
+        // the box's diagonal in the xz plane now lies along x
+        let half_diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(rotated
+            .min
+            .abs_diff_eq(Vec3A::new(0.0, 0.0, -half_diagonal), 1e-5));
+        assert!(rotated
+            .max
+            .abs_diff_eq(Vec3A::new(2.0 * half_diagonal, 1.0, half_diagonal), 1e-5));
+    }
 }
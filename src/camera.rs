@@ -4,8 +4,12 @@
 //! * positionable and orientable - Using the `look_from`, `look_at`, and `view_up` triplet of vectors
 //! * resizable film - Using `aspect_ratio`
 //! * depth of field (aka defocus blur) - Using the `aperture` and `focus_dist` data
+//! * several projections - See [Projection]
 
-use std::ops::Range;
+use std::{
+    f32::consts::{PI, TAU},
+    ops::Range,
+};
 
 use glam::Vec3A;
 use rand::Rng;
@@ -16,6 +20,68 @@ use crate::{
     utils::random::rand_vec3_in_unit_disk,
 };
 
+/// How a fisheye lens maps angles from its axis onto the image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    /// The distance from the image's center is proportional to the angle
+    #[default]
+    Equidistant,
+    /// Areas on the image are proportional to solid angles, like most real fisheye lenses
+    Equisolid,
+}
+
+/// How a [Camera] maps points on the image to rays
+///
+/// Only the [Projection::Perspective] and [Projection::Orthographic] projections have depth of field;
+/// the others see everything in focus from a single point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// A pinhole or thin lens camera, seeing `vert_fov` degrees across the image's height
+    Perspective { vert_fov: f32 },
+    /// Parallel rays from a viewport `height` units tall, without any perspective
+    Orthographic { height: f32 },
+    /// A circular fisheye, seeing `fov` degrees across a circle as tall as the image
+    ///
+    /// Outside the circle, the view continues up to 180 degrees from the camera's axis, then is black.
+    Fisheye { fov: f32, mapping: FisheyeMapping },
+    /// A 360 by 180 degree panorama, with the view direction in the image's center; best with an aspect ratio of 2
+    Equirectangular,
+    /// The six 90 degree faces of a cube map, best with an aspect ratio of 1.5
+    ///
+    /// The top row holds the views to the right, left, and up; the bottom row those down, forward, and backward.
+    CubeMap,
+}
+
+/// Projections to choose from on the commandline, see [Projection]
+#[derive(Debug, Clone, Copy, clap::clap_derive::ValueEnum)]
+pub enum ProjectionType {
+    /// Parallel rays from a viewport as tall as the camera's view on its plane in focus
+    Orthographic,
+    /// A 180 degree equidistant fisheye
+    Fisheye,
+    /// A 360 by 180 degree panorama
+    Equirectangular,
+    /// The six faces of a cube map
+    CubeMap,
+}
+
+impl ProjectionType {
+    /// Returns the [Projection] of this type replacing that of `camera`
+    pub fn projection(self, camera: &Camera) -> Projection {
+        match self {
+            ProjectionType::Orthographic => Projection::Orthographic {
+                height: camera.view_height(),
+            },
+            ProjectionType::Fisheye => Projection::Fisheye {
+                fov: 180.0,
+                mapping: FisheyeMapping::Equidistant,
+            },
+            ProjectionType::Equirectangular => Projection::Equirectangular,
+            ProjectionType::CubeMap => Projection::CubeMap,
+        }
+    }
+}
+
 /// A Camera that generates rays
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -32,10 +98,13 @@ pub struct Camera {
     /// Orthonormal base 2
     v: Vec3A,
     /// Orthonormal base 3, works like focal length
-    #[allow(dead_code/* , reason = "Surely there's gonna be a use for this right?" */)]
     w: Vec3A,
     /// Radius of the approximated camera lens
     lens_radius: f32,
+    /// Distance to the plane in focus
+    focus_dist: f32,
+    /// How points on the image map to rays
+    projection: Projection,
     /// Shutter open time,
     pub shutter_open_time: f32,
     /// Shutter close time
@@ -47,7 +116,7 @@ pub struct Camera {
 }
 
 impl Camera {
-    /// Creates a new Camera
+    /// Creates a new Camera with a perspective projection
     ///
     /// # Arguments
     /// * look_from - A [Vec3A] holding the position of the camera
@@ -57,6 +126,8 @@ impl Camera {
     /// * aspect_ratio - The aspect ratio of the viewport
     /// * aperture - How "big" the approximated lens is
     /// * focus_dist - The distance to the plane in space where objects are "in focus"
+    /// * shutter_time - The times while the shutter is open; an empty range captures a single instant
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3A,
         look_at: Vec3A,
@@ -68,50 +139,156 @@ impl Camera {
         shutter_time: Range<f32>,
         bg_color: Color,
     ) -> Self {
-        // Set up viewport
-        let theta = vert_fov.to_radians();
-        let viewport_h = 2.0 * (theta / 2.0).tan();
-        let viewport_w = aspect_ratio * viewport_h;
-
         // Set up position
         let w = (look_from - look_at).normalize();
         let u = view_up.cross(w).normalize();
         let v = w.cross(u);
 
-        let origin = look_from;
-        let horizontal = viewport_w * focus_dist * u;
-        let vertical = viewport_h * focus_dist * v;
-        let ll_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
-
         let lens_radius = aperture / 2.0;
         Self {
-            origin,
-            ll_corner,
-            horizontal,
-            vertical,
+            origin: look_from,
+            ll_corner: look_from,
+            horizontal: Vec3A::ZERO,
+            vertical: Vec3A::ZERO,
             u,
             v,
             w,
             lens_radius,
+            focus_dist,
+            projection: Projection::Perspective { vert_fov },
             shutter_open_time: shutter_time.start,
             shutter_close_time: shutter_time.end,
             bg_color,
             aspect_ratio,
         }
+        .with_projection(Projection::Perspective { vert_fov })
     }
 
-    /// Returns a ray from the camera for the normalized pixel (u,v)
-    pub fn get_ray(&self, u: f32, v: f32, rng: &mut impl Rng) -> Ray {
-        let rd = self.lens_radius * rand_vec3_in_unit_disk(rng);
-        let offset = self.u * rd.x + self.v * rd.y;
-        Ray {
-            origin: self.origin + offset,
-            direction: self.ll_corner + u * self.horizontal + v * self.vertical
-                - self.origin
-                - offset,
-            time: rng.gen_range(self.shutter_open_time..self.shutter_close_time),
+    /// Returns this camera with the given `projection`
+    pub fn with_projection(self, projection: Projection) -> Self {
+        // Set up viewport, on the plane in focus for perspective projections
+        let (viewport_h, distance) = match projection {
+            Projection::Perspective { vert_fov } => {
+                let theta = vert_fov.to_radians();
+                (2.0 * (theta / 2.0).tan() * self.focus_dist, self.focus_dist)
+            }
+            Projection::Orthographic { height } => (height, 0.0),
+            _ => (0.0, 0.0),
+        };
+        let viewport_w = self.aspect_ratio * viewport_h;
+
+        let horizontal = viewport_w * self.u;
+        let vertical = viewport_h * self.v;
+        let ll_corner = self.origin - horizontal / 2.0 - vertical / 2.0 - distance * self.w;
+        Self {
+            ll_corner,
+            horizontal,
+            vertical,
+            projection,
+            ..self
         }
     }
+
+    /// Returns how this camera maps points on the image to rays
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Returns the height of the view on the plane in focus, or of the viewport of orthographic projections
+    ///
+    /// Projections without a viewport count as seeing 90 degrees across the image's height.
+    pub fn view_height(&self) -> f32 {
+        match self.projection {
+            Projection::Perspective { .. } | Projection::Orthographic { .. } => {
+                self.vertical.length()
+            }
+            _ => 2.0 * self.focus_dist,
+        }
+    }
+
+    /// Returns a ray from the camera for the normalized pixel (u,v)
+    ///
+    /// Returns `None` for pixels that the camera doesn't see through, like those outside a fisheye's view.
+    pub fn get_ray(&self, u: f32, v: f32, rng: &mut impl Rng) -> Option<Ray> {
+        let (origin, direction) = match self.projection {
+            Projection::Perspective { .. } => {
+                let rd = self.lens_radius * rand_vec3_in_unit_disk(rng);
+                let offset = self.u * rd.x + self.v * rd.y;
+                let origin = self.origin + offset;
+                let target = self.ll_corner + u * self.horizontal + v * self.vertical;
+                (origin, target - origin)
+            }
+            Projection::Orthographic { .. } => {
+                let rd = self.lens_radius * rand_vec3_in_unit_disk(rng);
+                let offset = self.u * rd.x + self.v * rd.y;
+                let pixel = self.ll_corner + u * self.horizontal + v * self.vertical;
+                let target = pixel - self.focus_dist * self.w;
+                (pixel + offset, target - pixel - offset)
+            }
+            Projection::Fisheye { fov, mapping } => {
+                // distance from the center, in units of half the image's height
+                let x = 2.0 * (u - 0.5) * self.aspect_ratio;
+                let y = 2.0 * (v - 0.5);
+                let radius = x.hypot(y);
+                let half_fov = 0.5 * fov.to_radians();
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => radius * half_fov,
+                    FisheyeMapping::Equisolid => {
+                        let sin_half_theta = radius * (0.5 * half_fov).sin();
+                        if sin_half_theta > 1.0 {
+                            return None;
+                        }
+                        2.0 * sin_half_theta.asin()
+                    }
+                };
+                if theta > PI {
+                    return None;
+                }
+
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                let (sin_phi, cos_phi) = match radius > 0.0 {
+                    true => (y / radius, x / radius),
+                    false => (0.0, 1.0),
+                };
+                let direction =
+                    sin_theta * (cos_phi * self.u + sin_phi * self.v) - cos_theta * self.w;
+                (self.origin, direction)
+            }
+            Projection::Equirectangular => {
+                let phi = TAU * (u.clamp(0.0, 1.0) - 0.5);
+                let theta = PI * (1.0 - v.clamp(0.0, 1.0));
+                let (sin_phi, cos_phi) = phi.sin_cos();
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                let direction =
+                    sin_theta * (sin_phi * self.u - cos_phi * self.w) + cos_theta * self.v;
+                (self.origin, direction)
+            }
+            Projection::CubeMap => {
+                let column = (3.0 * u).clamp(0.0, 2.999);
+                let row = (2.0 * (1.0 - v)).clamp(0.0, 1.999);
+                // the face's view direction and up direction
+                let (forward, up) = match (row as usize, column as usize) {
+                    (0, 0) => (self.u, self.v),
+                    (0, 1) => (-self.u, self.v),
+                    (0, _) => (self.v, self.w),
+                    (_, 0) => (-self.v, -self.w),
+                    (_, 1) => (-self.w, self.v),
+                    (_, _) => (self.w, self.v),
+                };
+                let right = forward.cross(up);
+                let a = 2.0 * column.fract() - 1.0;
+                let b = 1.0 - 2.0 * row.fract();
+                (self.origin, forward + a * right + b * up)
+            }
+        };
+
+        let time = if self.shutter_open_time < self.shutter_close_time {
+            rng.gen_range(self.shutter_open_time..self.shutter_close_time)
+        } else {
+            self.shutter_open_time
+        };
+        Some(Ray::new(origin, direction, time))
+    }
 }
 
 impl Default for Camera {
@@ -129,3 +306,113 @@ impl Default for Camera {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+
+    fn camera(projection: Projection, aspect_ratio: f32) -> Camera {
+        Camera::new(
+            Vec3A::ZERO,
+            -Vec3A::Z,
+            Vec3A::Y,
+            90.0,
+            aspect_ratio,
+            0.0,
+            1.0,
+            0.0..1.0,
+            colors::BLACK,
+        )
+        .with_projection(projection)
+    }
+
+    /// Returns the normalized direction of the ray through the normalized pixel (u, v), if any
+    fn direction(camera: &Camera, u: f32, v: f32) -> Option<Vec3A> {
+        let mut rng = SmallRng::seed_from_u64(0);
+        camera
+            .get_ray(u, v, &mut rng)
+            .map(|ray| ray.direction.normalize())
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = camera(Projection::Orthographic { height: 2.0 }, 2.0);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let corner = camera.get_ray(0.0, 0.0, &mut rng).unwrap();
+        assert!(corner.origin.abs_diff_eq(Vec3A::new(-2.0, -1.0, 0.0), 1e-6));
+        assert!(corner.direction.normalize().abs_diff_eq(-Vec3A::Z, 1e-6));
+    }
+
+    #[test]
+    fn orthographic_keeps_focus_plane() {
+        // a 90 degree perspective sees 2 units across at the focus distance of 1
+        let perspective = camera(Projection::Perspective { vert_fov: 90.0 }, 2.0);
+        let projection = ProjectionType::Orthographic.projection(&perspective);
+        assert!(
+            matches!(projection, Projection::Orthographic { height } if (height - 2.0).abs() < 1e-6)
+        );
+    }
+
+    #[test]
+    fn fisheye_mappings() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = camera(
+                Projection::Fisheye {
+                    fov: 180.0,
+                    mapping,
+                },
+                1.0,
+            );
+            assert!(direction(&camera, 0.5, 0.5)
+                .unwrap()
+                .abs_diff_eq(-Vec3A::Z, 1e-6));
+            // the image circle's edge is 90 degrees from the axis
+            assert!(direction(&camera, 0.5, 1.0)
+                .unwrap()
+                .abs_diff_eq(Vec3A::Y, 1e-6));
+            assert!(direction(&camera, 0.0, 0.5)
+                .unwrap()
+                .abs_diff_eq(-Vec3A::X, 1e-6));
+        }
+        // nothing is seen beyond the equisolid lens' limit
+        let equisolid = Projection::Fisheye {
+            fov: 180.0,
+            mapping: FisheyeMapping::Equisolid,
+        };
+        assert!(direction(&camera(equisolid, 2.0), 0.0, 0.5).is_none());
+    }
+
+    #[test]
+    fn panoramas_cover_every_direction() {
+        let equirect = camera(Projection::Equirectangular, 2.0);
+        assert!(direction(&equirect, 0.5, 0.5)
+            .unwrap()
+            .abs_diff_eq(-Vec3A::Z, 1e-6));
+        assert!(direction(&equirect, 0.75, 0.5)
+            .unwrap()
+            .abs_diff_eq(Vec3A::X, 1e-6));
+        assert!(direction(&equirect, 0.3, 1.0)
+            .unwrap()
+            .abs_diff_eq(Vec3A::Y, 1e-6));
+
+        // the center of each face of the cube map looks along an axis
+        let cube = camera(Projection::CubeMap, 1.5);
+        let centers = [
+            (1.0 / 6.0, 0.75, Vec3A::X),
+            (0.5, 0.75, -Vec3A::X),
+            (5.0 / 6.0, 0.75, Vec3A::Y),
+            (1.0 / 6.0, 0.25, -Vec3A::Y),
+            (0.5, 0.25, -Vec3A::Z),
+            (5.0 / 6.0, 0.25, Vec3A::Z),
+        ];
+        for (u, v, expected) in centers {
+            let direction = direction(&cube, u, v).unwrap();
+            assert!(
+                direction.abs_diff_eq(expected, 1e-5),
+                "expected {expected}, got {direction}"
+            );
+        }
+    }
+}
@@ -4,7 +4,7 @@ use clap::Parser;
 
 pub use clap_verbosity_flag::Verbosity;
 
-use crate::scenes::SceneType;
+use crate::{camera::ProjectionType, scenes::SceneType};
 
 /// Parses the commandline arguments into an [Arguments] struct
 pub fn parse_args() -> Arguments {
@@ -59,10 +59,143 @@ pub struct Arguments {
     )]
     pub bounce_depth: u16,
 
+    /// number of bounces before paths may end early by Russian roulette
+    ///
+    /// Lower values speed up rendering at the cost of more noise.
+    /// Paths never bounce more often than the number of light contribution bounces
+    #[clap(long, default_value_t = 5, value_name = "NUM")]
+    pub roulette_depth: u16,
+
+    /// The path to write an intermediate image into after each pass of samples
+    ///
+    /// Samples are taken in passes that double the total, to 1, 2, 4, ... samples per pixel.
+    /// Pass the output path to watch the image improve in place
+    #[clap(long, value_parser = valid_image_file, value_name = "FILE")]
+    pub checkpoint: Option<std::path::PathBuf>,
+
+    /// Stop rendering after the last pass of samples that completes within the given number of seconds
+    #[clap(long, value_parser = valid_count::<u64>, value_name = "SECS")]
+    pub time_limit: Option<u64>,
+
+    /// The path to write the render's state into after each pass of samples
+    ///
+    /// The state holds all samples taken so far, to continue the render later with `--resume`
+    #[clap(long, value_name = "FILE")]
+    pub save_state: Option<std::path::PathBuf>,
+
+    /// Continue the render saved in the given state file, adding samples to it
+    ///
+    /// The scene and width must match the saved render, whose seed is used instead of `--seed`.
+    /// Unless `--save-state` is given, the state file keeps being updated
+    #[clap(long, value_name = "FILE")]
+    pub resume: Option<std::path::PathBuf>,
+
+    /// The noise level below which pixels stop taking samples
+    ///
+    /// The noise is estimated as the relative standard error of a pixel's luminance.
+    /// Pixels take at most the given number of samples per pixel regardless
+    #[clap(long, value_parser = positive_f32("noise threshold"), value_name = "ERROR")]
+    pub noise_threshold: Option<f32>,
+
+    /// The path to write a grayscale image into, showing the number of samples each pixel took
+    #[clap(long, value_parser = valid_image_file, value_name = "FILE")]
+    pub sample_map: Option<std::path::PathBuf>,
+
+    /// The side length of the square tiles that the image is rendered in
+    ///
+    /// Tiles are the unit of work when rendering in parallel; the image does not depend on their size
+    #[clap(
+        long,
+        value_parser = valid_count::<u32>,
+        default_value_t = 32,
+        value_name = "NUM"
+    )]
+    pub tile_size: u32,
+
+    /// Build the scene's BVH with spatial splits, for scenes with large or overlapping primitives
+    ///
+    /// Nodes whose children overlap by more than the given fraction of the scene's surface area also try splitting
+    /// space itself, at the cost of a slower build. Values around 1e-5 work well
+    #[clap(
+        long,
+        value_parser = positive_f32("spatial split threshold"),
+        value_name = "ALPHA"
+    )]
+    pub spatial_splits: Option<f32>,
+
+    /// Render spectrally, tracing light at sampled wavelengths instead of in RGB
+    ///
+    /// Lets dielectrics with wavelength-dependent refractive indices disperse light into its colors
+    #[clap(long)]
+    pub spectral: bool,
+
+    /// An equirectangular image lighting the scene from all around, replacing the scene's background or environment
+    ///
+    /// HDR and EXR images need the `hdr` feature
+    #[clap(long, value_name = "FILE")]
+    pub environment: Option<std::path::PathBuf>,
+
+    /// Light the scene with a procedural daylight sky and sun, replacing the scene's background or environment
+    #[clap(long, conflicts_with = "environment")]
+    pub sky: bool,
+
+    /// How many degrees the sun of `--sky` is above the horizon
+    #[clap(long, value_parser = valid_elevation, default_value_t = 45.0, value_name = "DEGREES")]
+    pub sun_elevation: f32,
+
+    /// The haziness of the sky of `--sky`, from 2 for a very clear sky to 10 for a hazy one
+    #[clap(
+        long,
+        value_parser = positive_f32("turbidity"),
+        default_value_t = 3.0,
+        value_name = "TURBIDITY"
+    )]
+    pub turbidity: f32,
+
+    /// How many degrees to turn the environment counterclockwise around the up axis
+    ///
+    /// With `--sky`, the sun faces the -z axis before turning
+    #[clap(
+        long,
+        default_value_t = 0.0,
+        value_name = "DEGREES",
+        allow_negative_numbers = true
+    )]
+    pub environment_rotation: f32,
+
+    /// The factor to scale the environment's brightness by
+    #[clap(
+        long,
+        value_parser = positive_f32("environment intensity"),
+        default_value_t = 1.0,
+        value_name = "SCALE"
+    )]
+    pub environment_intensity: f32,
+
     /// The hardcoded scene to use
     #[clap(short, long, value_enum, default_value_t = SceneType::CoverPhoto)]
     pub scene: SceneType,
 
+    /// A scene description file to render instead of a hardcoded scene
+    ///
+    /// See the `resources/scenes` directory for examples of the format
+    #[clap(long, value_name = "FILE", conflicts_with = "scene")]
+    pub scene_file: Option<std::path::PathBuf>,
+
+    /// An OBJ, PLY, glTF, or GLB model to render instead of a hardcoded scene
+    ///
+    /// The model is lit by the sky, and seen through its first camera, if it has one;
+    /// otherwise, the camera frames it automatically
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["scene", "scene_file"])]
+    pub model: Option<std::path::PathBuf>,
+
+    /// The projection to render with, replacing the perspective of hardcoded scenes and models, or that of the scene file
+    ///
+    /// Scene files can also set a projection's parameters, like a fisheye's field of view.
+    /// The image keeps the scene's aspect ratio; panoramas look best at 2, cube maps at 1.5
+    #[clap(long, value_enum, value_name = "PROJECTION")]
+    pub projection: Option<ProjectionType>,
+
     /// The seed used for psuedorandom number generation
     #[clap(long)]
     pub seed: Option<u64>,
@@ -89,6 +222,24 @@ where
     }
 }
 
+/// Returns a parser checking whether a value is a positive, finite number, naming the value as `name` in errors
+fn positive_f32(name: &'static str) -> impl Fn(&str) -> Result<f32, String> + Clone + Send + Sync {
+    move |s| match s.parse::<f32>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        Ok(_) => Err(format!("{name} must be a positive number")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Checks whether the given elevation is an angle between the horizon and the zenith
+fn valid_elevation(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(degrees) if (0.0..=90.0).contains(&degrees) => Ok(degrees),
+        Ok(_) => Err("elevation must be between 0 and 90 degrees".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 /// Checks whether the given output file is valid
 ///
 /// Checks the following properties:
@@ -142,4 +293,22 @@ mod tests {
             "Expected an unrecognized image format error"
         );
     }
+
+    #[test]
+    fn positive_numbers() {
+        use clap::CommandFactory;
+        for (flag, name) in [
+            ("--noise-threshold", "noise threshold"),
+            ("--spatial-splits", "spatial split threshold"),
+            ("--turbidity", "turbidity"),
+            ("--environment-intensity", "environment intensity"),
+        ] {
+            let res = Arguments::command().try_get_matches_from(["lustre", flag, "0"]);
+            let message = res.unwrap_err().to_string();
+            assert!(
+                message.contains(&format!("{name} must be a positive number")),
+                "unexpected error for {flag}: {message}"
+            );
+        }
+    }
 }
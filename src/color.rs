@@ -10,6 +10,12 @@ pub mod colors {
     pub const BLACK: super::Color = super::Vec3A::ZERO;
 }
 
+/// Returns the relative luminance of a linear RGB color, using the Rec. 709 coefficients
+#[inline]
+pub fn luminance(color: Color) -> f32 {
+    color.dot(Vec3A::new(0.2126, 0.7152, 0.0722))
+}
+
 /// [VecExt] serves to extend [glam]'s vector types to support conversion for [image::Pixel] implementations
 pub trait VecExt<P: image::Pixel> {
     /// Convert from a [glam] vector to an [image::Pixel]
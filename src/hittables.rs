@@ -4,14 +4,16 @@
 use std::sync::Arc;
 
 use glam::Vec3A;
+use rand::RngCore;
 
-use crate::{bounds::BoundingBox, material::Material, ray::Ray};
+use crate::{bounds::BoundingBox, color::Color, material::Material, ray::Ray};
 
 pub mod list;
 pub mod quad;
 pub mod quadbox;
 pub mod sphere;
 pub mod transform;
+pub mod triangle;
 pub mod volume;
 
 pub use list::*;
@@ -19,6 +21,7 @@ pub use quad::*;
 pub use quadbox::*;
 pub use sphere::*;
 pub use transform::*;
+pub use triangle::*;
 pub use volume::*;
 
 /// Defines a set of data returned upon a successful intersection
@@ -38,6 +41,8 @@ pub struct HitRecord {
     pub v: f32,
     /// Whether or not the ray hit the object's inside or outside face
     pub front_face: bool,
+    /// Color interpolated from the vertices of meshes that have vertex colors, see [crate::textures::VertexColors]
+    pub vertex_color: Option<Color>,
 }
 
 impl HitRecord {
@@ -54,6 +59,17 @@ impl HitRecord {
     }
 }
 
+/// Defines a direction sampled towards a point on an object's surface
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    /// Normalized direction from the sampling origin towards the sampled point
+    pub direction: Vec3A,
+    /// Distance from the sampling origin to the sampled point, infinite for directions towards the environment
+    pub distance: f32,
+    /// Probability density of sampling `direction`, with respect to solid angle
+    pub pdf: f32,
+}
+
 impl PartialOrd for HitRecord {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         self.t.partial_cmp(&other.t)
@@ -78,6 +94,50 @@ pub trait Hittable: Send + Sync {
     /// Returns a `Some(Aabb)` if the object has a bounding box (like spheres), otherwise `None` (like planes)
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<BoundingBox>;
 
+    /// Returns the bounding box of the part of the object inside `clip`, if any
+    ///
+    /// Used by [crate::tree::Tree]s with spatial splits. Defaults to the overlap of `clip` and the object's bounding box;
+    /// flat objects can bound their clipped surface more tightly.
+    fn clipped_bounding_box(
+        &self,
+        clip: &BoundingBox,
+        time0: f32,
+        time1: f32,
+    ) -> Option<BoundingBox> {
+        self.bounding_box(time0, time1)
+            .and_then(|bbox| bbox.intersection(clip))
+    }
+
+    /// Returns whether the object emits light and supports [Hittable::sample_towards]
+    ///
+    /// Such objects are used as lights for direct light sampling.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Samples a direction from `origin` towards a random point on the object's surface
+    ///
+    /// Returns `None` if the object does not support sampling or no direction could be sampled.
+    fn sample_towards(&self, _origin: Vec3A, _rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// Returns the probability density, with respect to solid angle,
+    /// of [Hittable::sample_towards] choosing the direction of `ray` from the ray's origin
+    ///
+    /// Returns zero if the object does not support sampling or the ray misses it.
+    fn pdf_value(&self, _ray: &Ray) -> f32 {
+        0.0
+    }
+
+    /// Returns the lights among the object's parts, for direct light sampling, see [crate::lights::LightList]
+    ///
+    /// Containers like [crate::tree::Tree]s and [Transform]s return the lights nested inside them.
+    /// An object that is a light itself is returned by the `Arc<dyn Hittable>` holding it, see [Hittable::is_light].
+    fn lights(&self) -> HittableList {
+        Vec::new()
+    }
+
     fn wrap(self) -> Arc<Self>
     where
         Self: Sized,
@@ -94,4 +154,33 @@ impl Hittable for Arc<dyn Hittable> {
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<BoundingBox> {
         self.as_ref().bounding_box(time0, time1)
     }
+
+    fn clipped_bounding_box(
+        &self,
+        clip: &BoundingBox,
+        time0: f32,
+        time1: f32,
+    ) -> Option<BoundingBox> {
+        self.as_ref().clipped_bounding_box(clip, time0, time1)
+    }
+
+    fn is_light(&self) -> bool {
+        self.as_ref().is_light()
+    }
+
+    fn sample_towards(&self, origin: Vec3A, rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        self.as_ref().sample_towards(origin, rng)
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f32 {
+        self.as_ref().pdf_value(ray)
+    }
+
+    fn lights(&self) -> HittableList {
+        if self.is_light() {
+            vec![Arc::clone(self)]
+        } else {
+            self.as_ref().lights()
+        }
+    }
 }
@@ -39,4 +39,8 @@ impl<T: Hittable> Hittable for Vec<T> {
             .filter_map(|hittable| hittable.bounding_box(time0, time1))
             .reduce(|acc, bbox| acc.union(bbox))
     }
+
+    fn lights(&self) -> HittableList {
+        self.iter().flat_map(Hittable::lights).collect()
+    }
 }
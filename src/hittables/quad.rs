@@ -3,11 +3,13 @@
 use std::sync::Arc;
 
 use glam::{Vec2, Vec3A};
+use rand::{Rng, RngCore};
 
 use crate::{
     bounds::BoundingBox,
-    hittables::{HitRecord, Hittable},
+    hittables::{HitRecord, Hittable, SurfaceSample},
     material::Material,
+    ray::Ray,
 };
 
 /// A quadrilateral defined by four points in space
@@ -15,6 +17,8 @@ use crate::{
 /// Based on Inigo Quilez's quad intersector:
 /// * [Intersection ShaderToy example](https://www.shadertoy.com/view/XtlBDs)
 /// * [Surface Ooords ShaderToy example](https://www.shadertoy.com/view/lsBSDm)
+///
+/// The quad's front face points along `(p1 - p0) × (p3 - p0)`, see [HitRecord::front_face].
 #[derive(Debug)]
 pub struct Quad {
     p0: Vec3A,
@@ -79,6 +83,15 @@ impl Quad {
         Self::new(p0, p1, p2, p3, m)
     }
 
+    /// Returns this quad with its front face on the other side
+    pub fn flipped(self) -> Self {
+        Self {
+            p1: self.p3,
+            p3: self.p1,
+            ..self
+        }
+    }
+
     /// Creates a new axis-aligned Quad based on 2 points on a plane + the plane's k value.
     ///
     /// Requires one dimension in each point to be zero-ed out to work.
@@ -115,6 +128,28 @@ impl Quad {
     fn cross(a: Vec2, b: Vec2) -> f32 {
         a.x * b.y - a.y * b.x
     }
+
+    /// Returns the areas of the triangles `(p0, p1, p2)` and `(p0, p2, p3)` making up the quad
+    #[inline]
+    fn triangle_areas(&self) -> (f32, f32) {
+        let area0 = 0.5 * (self.p1 - self.p0).cross(self.p2 - self.p0).length();
+        let area1 = 0.5 * (self.p2 - self.p0).cross(self.p3 - self.p0).length();
+        (area0, area1)
+    }
+
+    /// Returns the probability density, with respect to solid angle, of sampling a point on the quad
+    /// at `distance` along the normalized `direction`
+    #[inline]
+    fn solid_angle_pdf(&self, direction: Vec3A, distance: f32) -> f32 {
+        let (area0, area1) = self.triangle_areas();
+        let normal = (self.p1 - self.p0).cross(self.p3 - self.p0).normalize();
+        let cosine = normal.dot(direction).abs();
+        if cosine < 1e-6 {
+            return 0.0;
+        }
+        // convert from the uniform density over the area to a density over solid angle
+        distance * distance / (cosine * (area0 + area1))
+    }
 }
 
 impl Hittable for Quad {
@@ -125,7 +160,21 @@ impl Hittable for Quad {
         Some(BoundingBox::new_unchecked(min, max))
     }
 
-    fn hit(&self, ray: &crate::ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn clipped_bounding_box(
+        &self,
+        clip: &BoundingBox,
+        _time0: f32,
+        _time1: f32,
+    ) -> Option<BoundingBox> {
+        // clip both triangles of the quad separately, in case it's not convex
+        [[self.p0, self.p1, self.p2], [self.p0, self.p2, self.p3]]
+            .iter()
+            .filter_map(|triangle| clip.clip_polygon(triangle))
+            .reduce(|acc, bbox| acc.union(bbox))
+            .map(|bbox| BoundingBox::new_unchecked(bbox.min - 0.0001, bbox.max + 0.0001))
+    }
+
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         // see https://www.shadertoy.com/view/XtlBDs
         // 0--b--3
         // |\
@@ -202,7 +251,7 @@ impl Hittable for Quad {
             return None;
         }
 
-        let normal = abs_normal.normalize();
+        let normal = plane_normal.normalize();
         let mut rec = HitRecord {
             point: ray.at(t),
             normal,
@@ -211,9 +260,49 @@ impl Hittable for Quad {
             u,
             v,
             front_face: true,
+            vertex_color: None,
         };
         rec.set_face_normal(ray, normal);
 
         Some(rec)
     }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_towards(&self, origin: Vec3A, rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        // pick one of the two triangles by area, then a uniform point on it
+        let (area0, area1) = self.triangle_areas();
+        let (a, b, c) = if rng.gen::<f32>() * (area0 + area1) < area0 {
+            (self.p0, self.p1, self.p2)
+        } else {
+            (self.p0, self.p2, self.p3)
+        };
+
+        let sqrt_r1 = rng.gen::<f32>().sqrt();
+        let r2 = rng.gen::<f32>();
+        let point = a * (1.0 - sqrt_r1) + b * (sqrt_r1 * (1.0 - r2)) + c * (sqrt_r1 * r2);
+
+        let to_point = point - origin;
+        let distance = to_point.length();
+        let direction = to_point / distance;
+        let pdf = self.solid_angle_pdf(direction, distance);
+
+        (pdf > 0.0).then_some(SurfaceSample {
+            direction,
+            distance,
+            pdf,
+        })
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f32 {
+        match self.hit(ray, 0.001, f32::INFINITY) {
+            Some(rec) => {
+                let dir_length = ray.direction.length();
+                self.solid_angle_pdf(ray.direction / dir_length, rec.t * dir_length)
+            }
+            None => 0.0,
+        }
+    }
 }
@@ -5,16 +5,15 @@ use glam::Vec3A;
 
 use crate::{
     bounds::BoundingBox,
-    hittables::{HitRecord, Hittable, Quad},
+    hittables::{HitRecord, Hittable, HittableList, Quad},
     material::Material,
 };
 
 /// A box defined by a set of [Quad] faces
-#[derive(Debug)]
 pub struct QuadBox {
     min: Vec3A,
     max: Vec3A,
-    sides: Vec<Quad>,
+    sides: HittableList,
 }
 
 impl QuadBox {
@@ -38,20 +37,25 @@ impl QuadBox {
     /// Creates a new Box comprised of 6 sides stored as [Quad]s
     ///
     /// uses the rather expensive Quad::from_two_points_z fn, determining
+    ///
+    /// The sides' front faces point out of the box.
     pub fn new(min: Vec3A, max: Vec3A, m: &Arc<Material>) -> Self {
         let min = min.min(max);
         let max = min.max(max);
 
         let side0 = Quad::from_bounds_k(min.x, max.x, min.y, max.y, max.z, 2, m);
-        let side1 = Quad::from_bounds_k(min.x, max.x, min.y, max.y, min.z, 2, m);
+        let side1 = Quad::from_bounds_k(min.x, max.x, min.y, max.y, min.z, 2, m).flipped();
 
-        let side2 = Quad::from_bounds_k(min.x, max.x, min.z, max.z, max.y, 1, m);
+        let side2 = Quad::from_bounds_k(min.x, max.x, min.z, max.z, max.y, 1, m).flipped();
         let side3 = Quad::from_bounds_k(min.x, max.x, min.z, max.z, min.y, 1, m);
 
         let side4 = Quad::from_bounds_k(min.y, max.y, min.z, max.z, max.x, 0, m);
-        let side5 = Quad::from_bounds_k(min.y, max.y, min.z, max.z, min.x, 0, m);
+        let side5 = Quad::from_bounds_k(min.y, max.y, min.z, max.z, min.x, 0, m).flipped();
 
-        let sides: Vec<Quad> = vec![side0, side1, side2, side3, side4, side5];
+        let sides = [side0, side1, side2, side3, side4, side5]
+            .into_iter()
+            .map(|side| side.wrap() as Arc<dyn Hittable>)
+            .collect();
 
         Self { min, max, sides }
     }
@@ -65,4 +69,36 @@ impl Hittable for QuadBox {
     fn hit(&self, ray: &crate::ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         self.sides.hit(ray, t_min, t_max)
     }
+
+    fn lights(&self) -> HittableList {
+        self.sides.lights()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::colors, ray::Ray};
+
+    #[test]
+    fn sides_face_outwards() {
+        let material = Arc::new(Material::Lambertian {
+            albedo: Arc::new(colors::WHITE),
+        });
+        let quad_box = QuadBox::new(Vec3A::ZERO, Vec3A::ONE, &material);
+
+        for axis in [Vec3A::X, Vec3A::Y, Vec3A::Z] {
+            for side in [axis, -axis] {
+                let origin = Vec3A::splat(0.5) + 2.0 * side;
+                let rec = quad_box
+                    .hit(&Ray::new(origin, -side, 0.0), 0.001, f32::INFINITY)
+                    .unwrap();
+                assert!(
+                    rec.front_face,
+                    "the side facing {side} should face outwards"
+                );
+                assert_eq!(rec.normal, side);
+            }
+        }
+    }
 }
@@ -6,12 +6,14 @@ use std::{
 };
 
 use glam::Vec3A;
+use rand::RngCore;
 
 use crate::{
     bounds::BoundingBox,
-    hittables::{HitRecord, Hittable},
+    hittables::{HitRecord, Hittable, SurfaceSample},
     material::Material,
     ray::Ray,
+    utils::random::rand_vec3_in_cone,
 };
 
 /// A Sphere object
@@ -41,6 +43,22 @@ impl Sphere {
         let v = theta / PI;
         (u, v)
     }
+
+    /// Returns the cosine of the half-angle of the cone that the sphere subtends from `origin`
+    ///
+    /// Returns `None` if `origin` is inside the sphere.
+    #[inline]
+    fn cone_cos_theta_max(&self, origin: Vec3A) -> Option<f32> {
+        let dist_squared = self.center.distance_squared(origin);
+        let radius_squared = self.radius * self.radius;
+        (dist_squared > radius_squared).then(|| (1.0 - radius_squared / dist_squared).sqrt())
+    }
+
+    /// Returns the probability density of uniformly sampling a direction in a cone with the given half-angle
+    #[inline]
+    fn cone_pdf(cos_theta_max: f32) -> f32 {
+        (TAU * (1.0 - cos_theta_max)).recip()
+    }
 }
 
 impl Hittable for Sphere {
@@ -86,6 +104,7 @@ impl Hittable for Sphere {
             u,
             v,
             front_face,
+            vertex_color: None,
         })
     }
 
@@ -95,6 +114,33 @@ impl Hittable for Sphere {
             self.center + Vec3A::splat(self.radius),
         ))
     }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_towards(&self, origin: Vec3A, mut rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        // sample the cone of directions that the sphere covers as seen from the origin
+        let cos_theta_max = self.cone_cos_theta_max(origin)?;
+        let axis = (self.center - origin).normalize();
+        let direction = rand_vec3_in_cone(&mut rng, axis, cos_theta_max);
+
+        let rec = self.hit(&Ray::new(origin, direction, 0.0), 0.001, f32::INFINITY)?;
+        Some(SurfaceSample {
+            direction,
+            distance: rec.t,
+            pdf: Self::cone_pdf(cos_theta_max),
+        })
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f32 {
+        match self.cone_cos_theta_max(ray.origin) {
+            Some(cos_theta_max) if self.hit(ray, 0.001, f32::INFINITY).is_some() => {
+                Self::cone_pdf(cos_theta_max)
+            }
+            _ => 0.0,
+        }
+    }
 }
 
 /// Like [Sphere], but it moves.
@@ -180,6 +226,7 @@ impl Hittable for MovingSphere {
             u,
             v,
             front_face,
+            vertex_color: None,
         })
     }
 
@@ -2,14 +2,20 @@
 
 use std::sync::Arc;
 
-use glam::{Affine3A, Vec3};
+use glam::{Affine3A, Vec3, Vec3A};
+use rand::RngCore;
 
 use crate::{
     bounds::BoundingBox,
-    hittables::{HitRecord, Hittable},
+    hittables::{HitRecord, Hittable, HittableList, SurfaceSample},
+    ray::Ray,
 };
 
 /// A hittable undergoes a transform before and after being hit.
+///
+/// Rays are moved into the object's space at the instance boundary, so many [Transform]s can share one object.
+/// Wrapping a [crate::tree::Tree] makes a bottom-level acceleration structure
+/// that the scene's top-level tree reaches through each instance's bounding box.
 pub struct Transform {
     transform: Affine3A,
     inv_transform: Affine3A,
@@ -30,6 +36,15 @@ impl Transform {
         }
     }
 
+    /// Creates an instance of the object placed by an arbitrary affine `transform`.
+    pub fn from_affine(transform: Affine3A, o: &Arc<dyn Hittable>) -> Self {
+        Self {
+            transform,
+            inv_transform: transform.inverse(),
+            object: Arc::clone(o),
+        }
+    }
+
     /// Creates an affine transform that changes the size of the object.
     pub fn from_scale_factor(scale: Vec3, o: &Arc<dyn Hittable>) -> Self {
         let transform = Affine3A::from_scale(scale);
@@ -108,37 +123,153 @@ impl Transform {
             object: self.object.to_owned(),
         }
     }
+
+    /// Returns `ray` moved into the object's space
+    ///
+    /// The direction isn't normalized, so distances along both rays share the same `t`.
+    #[inline]
+    fn to_object(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.inv_transform.transform_point3a(ray.origin),
+            direction: self.inv_transform.transform_vector3a(ray.direction),
+            ..*ray
+        }
+    }
+
+    /// Converts `object_pdf`, the density with respect to solid angle of the object choosing the direction of `object_ray`,
+    /// into the density of choosing the direction of the same ray in world space, `ray`
+    ///
+    /// `rec` is where the object-space ray hits the object. The density goes through one with respect to surface area,
+    /// which shrinks as the transform stretches the surface around the hit, see
+    /// [Nanson's formula](https://en.wikipedia.org/wiki/Finite_strain_theory#Transformation_of_a_surface_and_volume_element).
+    fn world_pdf(&self, object_pdf: f32, object_ray: &Ray, ray: &Ray, rec: &HitRecord) -> f32 {
+        // cosine of the angle between the ray and the surface, over the squared distance to the hit
+        let falloff = |direction: Vec3A, normal: Vec3A| {
+            let length = direction.length();
+            normal.normalize().dot(direction / length).abs() / (rec.t * length).powi(2)
+        };
+
+        let normal = self.inv_transform.matrix3.transpose() * rec.normal;
+        let area_scale = self.transform.matrix3.determinant().abs() * normal.length();
+        let world_falloff = falloff(ray.direction, normal);
+        if world_falloff <= 0.0 {
+            return 0.0;
+        }
+
+        let area_pdf = object_pdf * falloff(object_ray.direction, rec.normal);
+        area_pdf / (area_scale * world_falloff)
+    }
 }
 
 impl Hittable for Transform {
     fn hit(&self, ray: &crate::ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let transformed_ray = crate::ray::Ray::new(
-            self.inv_transform.transform_point3a(ray.origin),
-            self.inv_transform.transform_vector3a(ray.direction),
-            ray.time,
-        );
+        let transformed_ray = self.to_object(ray);
 
-        match self.object.hit(&transformed_ray, t_min, t_max) {
-            Some(rec) => {
-                let mut transformed_rec = HitRecord {
-                    point: self.transform.transform_point3a(rec.point),
-                    normal: self.transform.transform_vector3a(rec.normal),
-                    ..rec
-                };
-                transformed_rec.set_face_normal(&transformed_ray, rec.normal);
-                Some(transformed_rec)
-            }
-            None => None,
-        }
+        self.object
+            .hit(&transformed_ray, t_min, t_max)
+            .map(|rec| HitRecord {
+                point: self.transform.transform_point3a(rec.point),
+                // normals transform by the inverse transpose to stay perpendicular under non-uniform scaling;
+                // the object already faced its normal against the ray, which this preserves
+                normal: (self.inv_transform.matrix3.transpose() * rec.normal).normalize(),
+                ..rec
+            })
     }
 
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<BoundingBox> {
         self.object
             .bounding_box(time0, time1)
-            .map(|BoundingBox { min, max }| {
-                let new_min = self.transform.transform_point3a(min);
-                let new_max = self.transform.transform_point3a(max);
-                BoundingBox::new(new_min, new_max)
-            })
+            .map(|bbox| bbox.transform(&self.transform))
+    }
+
+    fn is_light(&self) -> bool {
+        self.object.is_light()
+    }
+
+    fn sample_towards(&self, origin: Vec3A, rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        let object_origin = self.inv_transform.transform_point3a(origin);
+        let sample = self.object.sample_towards(object_origin, rng)?;
+
+        // the sampled point moves with the object, and so does the density of sampling it
+        let object_ray = Ray::new(object_origin, sample.direction, 0.0);
+        let rec = self.object.hit(&object_ray, 0.001, f32::INFINITY)?;
+        let ray = Ray::new(
+            origin,
+            self.transform.transform_vector3a(sample.direction),
+            0.0,
+        );
+        let pdf = self.world_pdf(sample.pdf, &object_ray, &ray, &rec);
+
+        let length = ray.direction.length();
+        (pdf > 0.0).then_some(SurfaceSample {
+            direction: ray.direction / length,
+            distance: rec.t * length,
+            pdf,
+        })
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f32 {
+        let object_ray = self.to_object(ray);
+        match self.object.hit(&object_ray, 0.001, f32::INFINITY) {
+            Some(rec) => self.world_pdf(self.object.pdf_value(&object_ray), &object_ray, ray, &rec),
+            None => 0.0,
+        }
+    }
+
+    fn lights(&self) -> HittableList {
+        // each light nested in the object is placed like the object
+        self.object
+            .lights()
+            .iter()
+            .map(|light| Self::from_affine(self.transform, light).wrap() as Arc<dyn Hittable>)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::colors, hittables::Quad, material::Material};
+    use glam::Quat;
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    #[test]
+    fn samples_match_placed_light() {
+        let light = Arc::new(Material::DiffuseLight {
+            albedo: Arc::new(colors::WHITE),
+            brightness: 1.0,
+            two_sided: true,
+            profile: None,
+        });
+        let corners = [Vec3A::ZERO, Vec3A::X, Vec3A::new(1.0, 0.0, 1.0), Vec3A::Z];
+        let quad: Arc<dyn Hittable> =
+            Quad::new(corners[0], corners[1], corners[2], corners[3], &light).wrap();
+
+        // stretching and rotating the quad changes its area and the angles it's seen at
+        let transform = Affine3A::from_scale_rotation_translation(
+            Vec3::new(2.0, 1.0, 0.5),
+            Quat::from_rotation_z(0.3),
+            Vec3::new(-1.0, 3.0, 0.0),
+        );
+        let instance = Transform::from_affine(transform, &quad);
+        let [p0, p1, p2, p3] = corners.map(|corner| transform.transform_point3a(corner));
+        let placed = Quad::new(p0, p1, p2, p3, &light);
+
+        let rng = &mut SmallRng::seed_from_u64(0);
+        let origin = Vec3A::new(0.2, 0.0, 0.4);
+        for _ in 0..100 {
+            let sample = instance.sample_towards(origin, rng).unwrap();
+            let ray = Ray::new(origin, sample.direction, 0.0);
+            let rec = placed.hit(&ray, 0.001, f32::INFINITY).unwrap();
+            assert!((rec.t - sample.distance).abs() < 1e-4 * rec.t);
+
+            let expected = placed.pdf_value(&ray);
+            assert!(
+                (sample.pdf - expected).abs() < 1e-3 * expected,
+                "sampled with density {}, expected {expected}",
+                sample.pdf
+            );
+            assert!((instance.pdf_value(&ray) - expected).abs() < 1e-3 * expected);
+        }
     }
 }
@@ -0,0 +1,539 @@
+//! Triangle and indexed triangle mesh implementations
+
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3A};
+use rand::{Rng, RngCore};
+
+use crate::{
+    bounds::BoundingBox,
+    color::Color,
+    hittables::{HitRecord, Hittable, HittableList, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+
+/// Intersects the ray with the triangle `(p0, p1, p2)`.
+///
+/// Uses the [Möller–Trumbore algorithm](https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm).
+/// Returns the ray parameter `t` and the barycentric coordinates `(b1, b2)` of `p1` and `p2` on a hit.
+#[inline]
+fn intersect(
+    p0: Vec3A,
+    p1: Vec3A,
+    p2: Vec3A,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+
+    let p_vec = ray.direction.cross(edge2);
+    let det = edge1.dot(p_vec);
+
+    // ray is parallel to the triangle's plane
+    // det scales with the triangle's area, so any cutoff above zero would lose hits on tiny triangles
+    if det == 0.0 {
+        return None;
+    }
+
+    let inv_det = det.recip();
+    let t_vec = ray.origin - p0;
+    let b1 = t_vec.dot(p_vec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let q_vec = t_vec.cross(edge1);
+    let b2 = ray.direction.dot(q_vec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q_vec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Returns the bounding box of the triangle `(p0, p1, p2)`, padded for axis-aligned triangles
+#[inline]
+fn bounds(p0: Vec3A, p1: Vec3A, p2: Vec3A) -> BoundingBox {
+    padded(p0.min(p1).min(p2), p0.max(p1).max(p2))
+}
+
+/// Returns the bounding box of the part of the triangle `points` inside `clip`, padded like [bounds]
+#[inline]
+fn clipped_bounds(points: [Vec3A; 3], clip: &BoundingBox) -> Option<BoundingBox> {
+    clip.clip_polygon(&points)
+        .map(|bbox| padded(bbox.min, bbox.max))
+}
+
+/// Returns the box from `min` to `max`, padded relative to its size and position
+///
+/// Flat boxes get some thickness, without swallowing their neighbors in dense meshes of tiny triangles.
+#[inline]
+fn padded(min: Vec3A, max: Vec3A) -> BoundingBox {
+    let size = (max - min).max_element();
+    let magnitude = min.abs().max(max.abs()).max_element();
+    let pad = (size * 1e-4).max(magnitude * 4.0 * f32::EPSILON);
+    BoundingBox::new_unchecked(min - pad, max + pad)
+}
+
+/// Returns the probability density, with respect to solid angle, of sampling a point on the triangle `points`
+/// at `distance` along the normalized `direction`
+#[inline]
+fn solid_angle_pdf(points: [Vec3A; 3], direction: Vec3A, distance: f32) -> f32 {
+    let [p0, p1, p2] = points;
+    let normal = (p1 - p0).cross(p2 - p0);
+    let area = 0.5 * normal.length();
+    let cosine = normal.normalize_or_zero().dot(direction).abs();
+    if cosine < 1e-6 {
+        return 0.0;
+    }
+    // convert from the uniform density over the area to a density over solid angle
+    distance * distance / (cosine * area)
+}
+
+/// Samples a direction from `origin` towards a uniformly chosen point on the triangle `points`
+fn sample_towards(
+    points: [Vec3A; 3],
+    origin: Vec3A,
+    rng: &mut dyn RngCore,
+) -> Option<SurfaceSample> {
+    let [p0, p1, p2] = points;
+    let sqrt_r1 = rng.gen::<f32>().sqrt();
+    let r2 = rng.gen::<f32>();
+    let point = p0 * (1.0 - sqrt_r1) + p1 * (sqrt_r1 * (1.0 - r2)) + p2 * (sqrt_r1 * r2);
+
+    let to_point = point - origin;
+    let distance = to_point.length();
+    let direction = to_point / distance;
+    let pdf = solid_angle_pdf(points, direction, distance);
+
+    (pdf > 0.0).then_some(SurfaceSample {
+        direction,
+        distance,
+        pdf,
+    })
+}
+
+/// Returns the probability density, with respect to solid angle, of [sample_towards] choosing the direction of `ray`
+fn pdf_value(points: [Vec3A; 3], ray: &Ray) -> f32 {
+    let [p0, p1, p2] = points;
+    match intersect(p0, p1, p2, ray, 0.001, f32::INFINITY) {
+        Some((t, ..)) => {
+            let length = ray.direction.length();
+            solid_angle_pdf(points, ray.direction / length, t * length)
+        }
+        None => 0.0,
+    }
+}
+
+/// Creates the [HitRecord] for a triangle intersection
+///
+/// `normals`, `uvs`, and `colors` are the optional per-vertex attributes,
+/// interpolated using the barycentric coordinates `(b1, b2)`.
+#[allow(clippy::too_many_arguments)]
+fn hit_record(
+    points: [Vec3A; 3],
+    normals: Option<[Vec3A; 3]>,
+    uvs: Option<[Vec2; 3]>,
+    colors: Option<[Color; 3]>,
+    material: &Arc<Material>,
+    ray: &Ray,
+    t: f32,
+    b1: f32,
+    b2: f32,
+) -> HitRecord {
+    let b0 = 1.0 - b1 - b2;
+    let [p0, p1, p2] = points;
+    let geometric_n = (p1 - p0).cross(p2 - p0).normalize();
+
+    // without texture coordinates, the barycentric coordinates serve as uvs
+    let uv = match uvs {
+        Some([uv0, uv1, uv2]) => b0 * uv0 + b1 * uv1 + b2 * uv2,
+        None => Vec2::new(b1, b2),
+    };
+
+    let mut rec = HitRecord {
+        point: ray.at(t),
+        normal: geometric_n,
+        material: Arc::clone(material),
+        t,
+        u: uv.x,
+        v: uv.y,
+        front_face: true,
+        vertex_color: colors.map(|[c0, c1, c2]| b0 * c0 + b1 * c1 + b2 * c2),
+    };
+    // the geometric normal decides the face, the shading normal only smooths the result
+    rec.set_face_normal(ray, geometric_n);
+
+    if let Some([n0, n1, n2]) = normals {
+        let shading_n = (b0 * n0 + b1 * n1 + b2 * n2).normalize_or_zero();
+        if shading_n != Vec3A::ZERO {
+            rec.normal = if rec.front_face {
+                shading_n
+            } else {
+                -shading_n
+            };
+        }
+    }
+
+    rec
+}
+
+/// A single triangle defined by three points in space
+///
+/// Vertex normals and texture coordinates are optional;
+/// without them, the triangle is flat-shaded and uses barycentric coordinates as uvs.
+#[derive(Debug)]
+pub struct Triangle {
+    points: [Vec3A; 3],
+    normals: Option<[Vec3A; 3]>,
+    uvs: Option<[Vec2; 3]>,
+    pub material: Arc<Material>,
+}
+
+impl Triangle {
+    /// Creates a new flat-shaded Triangle.
+    pub fn new(p0: Vec3A, p1: Vec3A, p2: Vec3A, m: &Arc<Material>) -> Self {
+        Self {
+            points: [p0, p1, p2],
+            normals: None,
+            uvs: None,
+            material: Arc::clone(m),
+        }
+    }
+
+    /// Adds per-vertex normals, used to smoothly interpolate the shading normal
+    pub fn with_normals(mut self, normals: [Vec3A; 3]) -> Self {
+        self.normals = Some(normals.map(Vec3A::normalize));
+        self
+    }
+
+    /// Adds per-vertex texture coordinates
+    pub fn with_uvs(mut self, uvs: [Vec2; 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [p0, p1, p2] = self.points;
+        let (t, b1, b2) = intersect(p0, p1, p2, ray, t_min, t_max)?;
+        Some(hit_record(
+            self.points,
+            self.normals,
+            self.uvs,
+            None,
+            &self.material,
+            ray,
+            t,
+            b1,
+            b2,
+        ))
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<BoundingBox> {
+        let [p0, p1, p2] = self.points;
+        Some(bounds(p0, p1, p2))
+    }
+
+    fn clipped_bounding_box(
+        &self,
+        clip: &BoundingBox,
+        _time0: f32,
+        _time1: f32,
+    ) -> Option<BoundingBox> {
+        clipped_bounds(self.points, clip)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn sample_towards(&self, origin: Vec3A, rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        sample_towards(self.points, origin, rng)
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f32 {
+        pdf_value(self.points, ray)
+    }
+}
+
+/// An indexed triangle mesh
+///
+/// The vertex attributes are stored once and shared by all triangles of the mesh.
+/// Use [TriangleMesh::triangles] to get the individual triangles for use in a [crate::tree::Tree].
+#[derive(Debug)]
+pub struct TriangleMesh {
+    /// Vertex positions
+    positions: Vec<Vec3A>,
+    /// Optional vertex normals, one per position
+    normals: Option<Vec<Vec3A>>,
+    /// Optional texture coordinates, one per position
+    uvs: Option<Vec<Vec2>>,
+    /// Optional vertex colors, one per position
+    colors: Option<Vec<Color>>,
+    /// Indices into the vertex attributes, three per triangle
+    indices: Vec<[u32; 3]>,
+    pub material: Arc<Material>,
+}
+
+impl TriangleMesh {
+    /// Creates a new TriangleMesh from vertex positions and triangle indices.
+    ///
+    /// Panics if an index is out of bounds of `positions`.
+    pub fn new(positions: Vec<Vec3A>, indices: Vec<[u32; 3]>, m: &Arc<Material>) -> Self {
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "Mesh index out of bounds of {} positions",
+            positions.len()
+        );
+
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+            colors: None,
+            indices,
+            material: Arc::clone(m),
+        }
+    }
+
+    /// Adds per-vertex normals to the mesh.
+    ///
+    /// Panics if there isn't one normal per position.
+    pub fn with_normals(mut self, normals: Vec<Vec3A>) -> Self {
+        assert_eq!(
+            normals.len(),
+            self.positions.len(),
+            "Expected one normal per position"
+        );
+        self.normals = Some(normals.into_iter().map(Vec3A::normalize).collect());
+        self
+    }
+
+    /// Adds per-vertex texture coordinates to the mesh.
+    ///
+    /// Panics if there aren't one set of coordinates per position.
+    pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+        assert_eq!(
+            uvs.len(),
+            self.positions.len(),
+            "Expected one uv per position"
+        );
+        self.uvs = Some(uvs);
+        self
+    }
+
+    /// Adds per-vertex colors to the mesh, which [VertexColors](crate::textures::VertexColors) textures interpolate.
+    ///
+    /// Panics if there isn't one color per position.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        assert_eq!(
+            colors.len(),
+            self.positions.len(),
+            "Expected one color per position"
+        );
+        self.colors = Some(colors);
+        self
+    }
+
+    /// Returns the number of triangles in the mesh
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns whether the mesh has no triangles
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns each triangle of the mesh as a separate [Hittable], all sharing this mesh's data
+    pub fn triangles(self: &Arc<Self>) -> HittableList {
+        (0..self.indices.len())
+            .map(|index| {
+                MeshTriangle {
+                    mesh: Arc::clone(self),
+                    index,
+                }
+                .wrap() as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    /// Returns the vertex attribute `attr` at each of the triangle's indices
+    #[inline]
+    fn gather<T: Copy>(attr: &[T], [i0, i1, i2]: [u32; 3]) -> [T; 3] {
+        [attr[i0 as usize], attr[i1 as usize], attr[i2 as usize]]
+    }
+}
+
+/// A triangle of a [TriangleMesh], referring to its vertex data by index
+#[derive(Debug)]
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    /// Returns the three positions of this triangle
+    #[inline]
+    fn points(&self) -> [Vec3A; 3] {
+        TriangleMesh::gather(&self.mesh.positions, self.mesh.indices[self.index])
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let points = self.points();
+        let [p0, p1, p2] = points;
+        let (t, b1, b2) = intersect(p0, p1, p2, ray, t_min, t_max)?;
+
+        let indices = self.mesh.indices[self.index];
+        let normals = self
+            .mesh
+            .normals
+            .as_deref()
+            .map(|normals| TriangleMesh::gather(normals, indices));
+        let uvs = self
+            .mesh
+            .uvs
+            .as_deref()
+            .map(|uvs| TriangleMesh::gather(uvs, indices));
+        let colors = self
+            .mesh
+            .colors
+            .as_deref()
+            .map(|colors| TriangleMesh::gather(colors, indices));
+
+        Some(hit_record(
+            points,
+            normals,
+            uvs,
+            colors,
+            &self.mesh.material,
+            ray,
+            t,
+            b1,
+            b2,
+        ))
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<BoundingBox> {
+        let [p0, p1, p2] = self.points();
+        Some(bounds(p0, p1, p2))
+    }
+
+    fn clipped_bounding_box(
+        &self,
+        clip: &BoundingBox,
+        _time0: f32,
+        _time1: f32,
+    ) -> Option<BoundingBox> {
+        clipped_bounds(self.points(), clip)
+    }
+
+    fn is_light(&self) -> bool {
+        self.mesh.material.is_emissive()
+    }
+
+    fn sample_towards(&self, origin: Vec3A, rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        sample_towards(self.points(), origin, rng)
+    }
+
+    fn pdf_value(&self, ray: &Ray) -> f32 {
+        pdf_value(self.points(), ray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::colors;
+
+    fn material() -> Arc<Material> {
+        Arc::new(Material::Lambertian {
+            albedo: Arc::new(colors::WHITE),
+        })
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let tri = Triangle::new(Vec3A::ZERO, Vec3A::X, Vec3A::Y, &material());
+        let ray = Ray::new(Vec3A::new(0.25, 0.25, 1.0), -Vec3A::Z, 0.0);
+        let rec = tri
+            .hit(&ray, 0.001, f32::INFINITY)
+            .expect("ray should hit the triangle");
+
+        assert!((rec.t - 1.0).abs() < 1e-5, "unexpected t {}", rec.t);
+        assert!(rec.front_face, "ray should hit the front face");
+        assert_eq!(rec.normal, Vec3A::Z);
+        assert!((rec.u - 0.25).abs() < 1e-5 && (rec.v - 0.25).abs() < 1e-5);
+
+        let miss = Ray::new(Vec3A::new(0.75, 0.75, 1.0), -Vec3A::Z, 0.0);
+        assert!(tri.hit(&miss, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn tiny_triangle_hit() {
+        // a scanned mesh's triangles can be much smaller than a unit, far from the origin
+        let scale = 1e-4;
+        let offset = Vec3A::new(10.0, 10.0, 0.0);
+        let tri = Triangle::new(
+            offset,
+            offset + scale * Vec3A::X,
+            offset + scale * Vec3A::Y,
+            &material(),
+        );
+        let ray = Ray::new(
+            offset + Vec3A::new(0.25 * scale, 0.25 * scale, 1.0),
+            -Vec3A::Z,
+            0.0,
+        );
+        let rec = tri
+            .hit(&ray, 0.001, f32::INFINITY)
+            .expect("ray should hit the tiny triangle");
+        assert!((rec.t - 1.0).abs() < 1e-5, "unexpected t {}", rec.t);
+
+        let bbox = tri.bounding_box(0.0, 1.0).unwrap();
+        assert!(
+            bbox.min.z < 0.0 && bbox.max.z > 0.0,
+            "flat box should be padded"
+        );
+        assert!(
+            bbox.max.x - bbox.min.x < 2.0 * scale,
+            "padding should be relative"
+        );
+    }
+
+    #[test]
+    fn mesh_shares_vertices() {
+        // a unit square made of two triangles
+        let positions = vec![Vec3A::ZERO, Vec3A::X, Vec3A::new(1.0, 1.0, 0.0), Vec3A::Y];
+        let mesh = Arc::new(TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [0, 2, 3]],
+            &material(),
+        ));
+        let triangles = mesh.triangles();
+        assert_eq!(triangles.len(), 2);
+
+        // from behind, the second triangle is hit on its back face
+        let ray = Ray::new(Vec3A::new(0.25, 0.75, -1.0), Vec3A::Z, 0.0);
+        let rec = triangles
+            .hit(&ray, 0.001, f32::INFINITY)
+            .expect("ray should hit the mesh");
+        assert!(!rec.front_face, "ray should hit the back face");
+        assert_eq!(rec.normal, -Vec3A::Z);
+    }
+}
@@ -1,6 +1,6 @@
 //! Intersectable volumes/participating media
 
-use std::{f32::INFINITY, ops::Neg, sync::Arc};
+use std::{ops::Neg, sync::Arc};
 
 use rand::Rng;
 
@@ -8,6 +8,7 @@ use crate::{
     hittables::{HitRecord, Hittable},
     material::Material,
     textures::Texture,
+    utils::random::ray_rng,
 };
 
 /// A Volume with a constant density
@@ -31,8 +32,10 @@ impl ConstantMedium {
 
 impl Hittable for ConstantMedium {
     fn hit(&self, ray: &crate::ray::Ray, t_min: f32, t_max: f32) -> Option<super::HitRecord> {
-        let Some(mut min_rec) = self.boundary.hit(ray, -INFINITY, INFINITY) else { return None };
-        let Some(mut max_rec) = self.boundary.hit(ray, min_rec.t + 0.0001, INFINITY) else { return None };
+        let mut min_rec = self.boundary.hit(ray, -f32::INFINITY, f32::INFINITY)?;
+        let mut max_rec = self.boundary.hit(ray, min_rec.t + 0.0001, f32::INFINITY)?;
+        // the boundary's entry point tells apart media along the same ray
+        let rng = &mut ray_rng(ray.seed, min_rec.t.to_bits() as u64);
 
         min_rec.t = min_rec.t.max(t_min);
         max_rec.t = max_rec.t.min(t_max);
@@ -45,7 +48,7 @@ impl Hittable for ConstantMedium {
 
         let ray_dir_length = ray.direction.length();
         let dist_inside_boundary = (max_rec.t - min_rec.t) * ray_dir_length;
-        let hit_dist = self.neg_inv_density * (1.0 - rand::thread_rng().gen::<f32>()).log10();
+        let hit_dist = self.neg_inv_density * (1.0 - rng.gen::<f32>()).log10();
         if hit_dist > dist_inside_boundary {
             return None;
         }
@@ -62,6 +65,7 @@ impl Hittable for ConstantMedium {
             u: min_rec.u,
             v: min_rec.v,
             front_face: true,
+            vertex_color: None,
         })
     }
 
@@ -108,8 +112,10 @@ where
     N: ::noise::NoiseFn<f64, 3> + Send + Sync,
 {
     fn hit(&self, ray: &crate::ray::Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let Some(mut min_rec) = self.boundary.hit(ray, -INFINITY, INFINITY) else { return None };
-        let Some(mut max_rec) = self.boundary.hit(ray, min_rec.t + 0.0001, INFINITY) else { return None };
+        let mut min_rec = self.boundary.hit(ray, -f32::INFINITY, f32::INFINITY)?;
+        let mut max_rec = self.boundary.hit(ray, min_rec.t + 0.0001, f32::INFINITY)?;
+        // the boundary's entry point tells apart media along the same ray
+        let rng = &mut ray_rng(ray.seed, min_rec.t.to_bits() as u64);
 
         min_rec.t = min_rec.t.max(t_min);
         max_rec.t = max_rec.t.min(t_max);
@@ -127,14 +133,14 @@ where
         // https://psgraphics.blogspot.com/2009/05/neat-trick-for-ray-collisions-in.html
         let mut hit_dist = 0.0;
         loop {
-            let rand_num = rand::thread_rng().gen::<f32>();
-            hit_dist += -1.0 * (1.0 - rand_num).log10();
+            let rand_num = rng.gen::<f32>();
+            hit_dist -= (1.0 - rand_num).log10();
             let t = min_rec.t + hit_dist / ray_dir_length;
             let point = ray.at(t);
             let noised = self
                 .density_fn
                 .get((self.scale * point).as_dvec3().to_array());
-            if noised as f32 / ray_dir_length > rand::thread_rng().gen::<f32>() {
+            if noised as f32 / ray_dir_length > rng.gen::<f32>() {
                 break;
             }
         }
@@ -155,6 +161,7 @@ where
             u: min_rec.u,
             v: min_rec.v,
             front_face: true,
+            vertex_color: None,
         })
     }
 
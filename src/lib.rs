@@ -2,8 +2,10 @@ mod bounds;
 mod bvh;
 mod camera;
 mod color;
+mod lights;
 mod material;
 mod ray;
+mod spectrum;
 mod textures;
 mod utils;
 
@@ -11,6 +13,8 @@ mod utils;
 pub mod cli;
 // public: `Hittable` trait
 pub mod hittables;
+// public: asset importers
+pub mod loaders;
 // public: renderer functionality
 pub mod render;
 // public: scene selection
@@ -0,0 +1,205 @@
+//! Collection of light-emitting objects used for direct light sampling
+
+use std::sync::Arc;
+
+use glam::Vec3A;
+use rand::Rng;
+
+use crate::{
+    color::colors,
+    hittables::{Hittable, SurfaceSample},
+    ray::Ray,
+};
+
+pub mod delta;
+pub mod environment;
+pub mod sky;
+
+pub use delta::*;
+pub use environment::*;
+pub use sky::*;
+
+/// Returns the weight of a sample taken with density `pdf`, when `other_pdf` is the density
+/// with which the other sampling strategy would have chosen it
+///
+/// Uses Veach's power heuristic with an exponent of 2.
+/// See [PBR's section on multiple importance sampling](https://pbr-book.org/3ed-2018/Monte_Carlo_Integration/Importance_Sampling#MultipleImportanceSampling)
+#[inline]
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let pdf_squared = pdf * pdf;
+    let sum = pdf_squared + other_pdf * other_pdf;
+    if sum > 0.0 {
+        pdf_squared / sum
+    } else {
+        0.0
+    }
+}
+
+/// The set of objects that [Ray::shade] samples directly, the [Environment] surrounding them, and any [DeltaLight]s
+///
+/// Only emissive objects that support sampling (see [Hittable::is_light]) are collected,
+/// including those nested inside other objects, like [crate::tree::Tree]s or [crate::hittables::Transform]s
+/// (see [Hittable::lights]).
+/// The environment is sampled alongside the objects if it's a light (see [Environment::is_light]).
+/// Delta lights can't be hit, so they're sampled separately.
+pub struct LightList {
+    lights: Vec<Arc<dyn Hittable>>,
+    environment: Arc<dyn Environment>,
+    delta_lights: Vec<DeltaLight>,
+}
+
+impl Default for LightList {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl LightList {
+    /// Creates a new LightList from the lights among and inside the given objects, in a black environment
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
+        Self {
+            lights: objects.iter().flat_map(Hittable::lights).collect(),
+            environment: Arc::new(colors::BLACK),
+            delta_lights: Vec::new(),
+        }
+    }
+
+    /// Returns this LightList surrounded by the given `environment`
+    pub fn with_environment(self, environment: Arc<dyn Environment>) -> Self {
+        Self {
+            environment,
+            ..self
+        }
+    }
+
+    /// Returns this LightList with the given `delta_lights` added to it
+    pub fn with_delta_lights(mut self, delta_lights: impl IntoIterator<Item = DeltaLight>) -> Self {
+        self.delta_lights.extend(delta_lights);
+        self
+    }
+
+    /// Returns the environment seen by rays leaving the scene
+    pub fn environment(&self) -> &dyn Environment {
+        self.environment.as_ref()
+    }
+
+    /// Returns the number of lights to choose from when sampling, including the environment if it's a light
+    fn num_sampled(&self) -> usize {
+        self.lights.len() + self.environment.is_light() as usize
+    }
+
+    /// Returns whether there are no lights to sample, including delta lights
+    pub fn is_empty(&self) -> bool {
+        self.num_sampled() == 0 && self.delta_lights.is_empty()
+    }
+
+    /// Samples a direction from `origin` towards a uniformly chosen light
+    ///
+    /// The returned density accounts for the choice of light.
+    /// Directions towards the environment have an infinite distance.
+    pub fn sample(&self, origin: Vec3A, rng: &mut impl Rng) -> Option<SurfaceSample> {
+        let num_sampled = self.num_sampled();
+        if num_sampled == 0 {
+            return None;
+        }
+
+        let sample = match self.lights.get(rng.gen_range(0..num_sampled)) {
+            Some(light) => light.sample_towards(origin, rng),
+            None => self.environment.sample(rng),
+        };
+        sample.map(|sample| SurfaceSample {
+            pdf: sample.pdf / num_sampled as f32,
+            ..sample
+        })
+    }
+
+    /// Returns the light arriving at `origin` from a uniformly chosen [DeltaLight], if any
+    ///
+    /// The returned irradiance accounts for the choice of light.
+    pub fn sample_delta(&self, origin: Vec3A, rng: &mut impl Rng) -> Option<DeltaSample> {
+        let num_delta = self.delta_lights.len();
+        let light = match num_delta {
+            0 => return None,
+            1 => &self.delta_lights[0],
+            _ => &self.delta_lights[rng.gen_range(0..num_delta)],
+        };
+        light.sample_towards(origin).map(|sample| DeltaSample {
+            irradiance: sample.irradiance * num_delta as f32,
+            ..sample
+        })
+    }
+
+    /// Returns the probability density, with respect to solid angle, of [LightList::sample]
+    /// choosing the direction of `ray`, given that the ray hits a light at `t`
+    ///
+    /// Returns zero if no light is hit at `t`, for example when the hit object isn't in this list.
+    pub fn pdf_value(&self, ray: &Ray, t: f32) -> f32 {
+        let pdf_sum: f32 = self
+            .lights
+            .iter()
+            .filter(|light| {
+                light
+                    .hit(ray, 0.001, f32::INFINITY)
+                    .is_some_and(|rec| (rec.t - t).abs() <= 1e-3 * t.max(1.0))
+            })
+            .map(|light| light.pdf_value(ray))
+            .sum();
+        pdf_sum / self.num_sampled().max(1) as f32
+    }
+
+    /// Returns the probability density, with respect to solid angle, of [LightList::sample]
+    /// choosing `direction` towards the environment
+    pub fn environment_pdf(&self, direction: Vec3A) -> f32 {
+        if !self.environment.is_light() {
+            return 0.0;
+        }
+        self.environment.pdf_value(direction) / self.num_sampled() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittables::{Transform, Triangle},
+        material::Material,
+        tree::Tree,
+    };
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    #[test]
+    fn nested_lights() {
+        let light = Arc::new(Material::DiffuseLight {
+            albedo: Arc::new(colors::WHITE),
+            brightness: 1.0,
+            two_sided: true,
+            profile: None,
+        });
+        let diffuse = Arc::new(Material::Lambertian {
+            albedo: Arc::new(colors::WHITE),
+        });
+        let emitter = Triangle::new(
+            Vec3A::new(-1.0, 0.0, -1.0),
+            Vec3A::new(1.0, 0.0, -1.0),
+            Vec3A::new(0.0, 0.0, 1.0),
+            &light,
+        );
+        let floor = Triangle::new(Vec3A::ZERO, Vec3A::X, Vec3A::Z, &diffuse);
+        let tree: Arc<dyn Hittable> =
+            Tree::new(vec![emitter.wrap(), floor.wrap()], 0.0, 1.0).wrap();
+        let geometry = [
+            Transform::from_translation(glam::Vec3::new(0.0, 2.0, 0.0), &tree).wrap()
+                as Arc<dyn Hittable>,
+        ];
+
+        let lights = LightList::new(&geometry);
+        assert_eq!(lights.lights.len(), 1);
+
+        let rng = &mut SmallRng::seed_from_u64(0);
+        let sample = lights.sample(Vec3A::ZERO, rng).unwrap();
+        assert!(sample.direction.y > 0.0);
+        let ray = Ray::new(Vec3A::ZERO, sample.direction, 0.0);
+        let pdf = lights.pdf_value(&ray, sample.distance);
+        assert!((pdf - sample.pdf).abs() < 1e-4 * sample.pdf);
+    }
+}
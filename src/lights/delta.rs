@@ -0,0 +1,183 @@
+//! Lights without any surface, which emit from a single point or direction
+
+use glam::Vec3A;
+
+use crate::color::Color;
+
+/// The light arriving at a point from a [DeltaLight]
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaSample {
+    /// Normalized direction from the point towards the light
+    pub direction: Vec3A,
+    /// Distance from the point to the light, infinite for [DeltaLight::Directional] lights
+    pub distance: f32,
+    /// The light's irradiance at the point, on a surface facing the light
+    pub irradiance: Color,
+}
+
+/// A light that emits from a single point, or from a single direction
+///
+/// Since rays can never hit these lights, they are only seen by [crate::ray::Ray::shade]
+/// through shadow rays, from surfaces that can be [evaluated](crate::material::Material::is_evaluable).
+#[derive(Debug, Clone, Copy)]
+pub enum DeltaLight {
+    /// A light emitting equally in all directions from `position`, like a small bulb
+    Point {
+        position: Vec3A,
+        /// The emitted radiant intensity; the irradiance at distance `d` is `intensity / d²`
+        intensity: Color,
+    },
+    /// A point light only emitting in a cone around `direction`, like a stage light
+    Spot {
+        position: Vec3A,
+        /// Normalized direction that the cone points to
+        direction: Vec3A,
+        /// The radiant intensity along the cone's axis
+        intensity: Color,
+        /// The cosine of the angle from the axis where the intensity starts to fall off
+        cos_falloff_start: f32,
+        /// The cosine of the angle from the axis where the intensity reaches zero
+        cos_total_width: f32,
+    },
+    /// A light arriving from infinitely far away in parallel rays, like sunlight
+    Directional {
+        /// Normalized direction that the light travels in
+        direction: Vec3A,
+        /// The irradiance on a surface facing the light
+        irradiance: Color,
+    },
+}
+
+impl DeltaLight {
+    /// Creates a new [DeltaLight::Point] light
+    pub fn point(position: Vec3A, intensity: Color) -> Self {
+        Self::Point {
+            position,
+            intensity,
+        }
+    }
+
+    /// Creates a new [DeltaLight::Spot] light at `position` pointing towards `look_at`
+    ///
+    /// The intensity falls off smoothly from `falloff_start` degrees to `total_width` degrees from the cone's axis.
+    pub fn spot(
+        position: Vec3A,
+        look_at: Vec3A,
+        intensity: Color,
+        falloff_start: f32,
+        total_width: f32,
+    ) -> Self {
+        let total_width = total_width.clamp(0.0, 180.0);
+        let falloff_start = falloff_start.clamp(0.0, total_width);
+        Self::Spot {
+            position,
+            direction: (look_at - position).normalize(),
+            intensity,
+            cos_falloff_start: falloff_start.to_radians().cos(),
+            cos_total_width: total_width.to_radians().cos(),
+        }
+    }
+
+    /// Creates a new [DeltaLight::Directional] light travelling in `direction`
+    pub fn directional(direction: Vec3A, irradiance: Color) -> Self {
+        Self::Directional {
+            direction: direction.normalize(),
+            irradiance,
+        }
+    }
+
+    /// Returns the light arriving at `origin`, without checking whether anything blocks it
+    ///
+    /// Returns `None` if no light arrives, like outside a spot light's cone.
+    pub fn sample_towards(&self, origin: Vec3A) -> Option<DeltaSample> {
+        let (direction, distance, irradiance) = match *self {
+            DeltaLight::Point {
+                position,
+                intensity,
+            } => {
+                let offset = position - origin;
+                let distance_squared = offset.length_squared();
+                let distance = distance_squared.sqrt();
+                (offset / distance, distance, intensity / distance_squared)
+            }
+            DeltaLight::Spot {
+                position,
+                direction,
+                intensity,
+                cos_falloff_start,
+                cos_total_width,
+            } => {
+                let offset = position - origin;
+                let distance_squared = offset.length_squared();
+                let distance = distance_squared.sqrt();
+                let to_light = offset / distance;
+                let falloff =
+                    smoothstep(cos_total_width, cos_falloff_start, -to_light.dot(direction));
+                (to_light, distance, intensity * falloff / distance_squared)
+            }
+            DeltaLight::Directional {
+                direction,
+                irradiance,
+            } => (-direction, f32::INFINITY, irradiance),
+        };
+
+        (distance > 0.0 && irradiance.max_element() > 0.0).then_some(DeltaSample {
+            direction,
+            distance,
+            irradiance,
+        })
+    }
+}
+
+/// Smoothly interpolates from 0 at `edge0` to 1 at `edge1`, like GLSL's `smoothstep`
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge0 >= edge1 {
+        // a sharp edge, without any falloff
+        return (x >= edge1) as u8 as f32;
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_square_falloff() {
+        let light = DeltaLight::point(Vec3A::new(0.0, 2.0, 0.0), Color::splat(8.0));
+        let sample = light.sample_towards(Vec3A::ZERO).unwrap();
+        assert_eq!(sample.direction, Vec3A::Y);
+        assert_eq!(sample.distance, 2.0);
+        assert_eq!(sample.irradiance, Color::splat(2.0));
+    }
+
+    #[test]
+    fn spot_cone() {
+        let light = DeltaLight::spot(Vec3A::Y, Vec3A::ZERO, Color::ONE, 20.0, 30.0);
+        let irradiance = |x: f32| {
+            light
+                .sample_towards(Vec3A::new(x, 0.0, 0.0))
+                .map_or(0.0, |sample| sample.irradiance.x * (1.0 + x * x))
+        };
+
+        // full intensity inside the falloff, none outside the cone, and some in between
+        assert!((irradiance(0.0) - 1.0).abs() < 1e-6);
+        assert!((irradiance(15f32.to_radians().tan()) - 1.0).abs() < 1e-6);
+        assert_eq!(irradiance(35f32.to_radians().tan()), 0.0);
+        let blend = irradiance(25f32.to_radians().tan());
+        assert!(
+            0.0 < blend && blend < 1.0,
+            "{blend} should be partially lit"
+        );
+    }
+
+    #[test]
+    fn directional_is_infinitely_far() {
+        let light = DeltaLight::directional(Vec3A::new(0.0, -2.0, 0.0), Color::ONE);
+        let sample = light.sample_towards(Vec3A::splat(100.0)).unwrap();
+        assert_eq!(sample.direction, Vec3A::Y);
+        assert!(sample.distance.is_infinite());
+        assert_eq!(sample.irradiance, Color::ONE);
+    }
+}
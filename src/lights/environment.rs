@@ -0,0 +1,353 @@
+//! Light arriving from infinitely far away, seen by rays that leave the scene
+
+use std::f32::consts::{PI, TAU};
+
+use glam::{Mat3A, Vec3A};
+use rand::{Rng, RngCore};
+
+use crate::{
+    color::{luminance, Color},
+    hittables::SurfaceSample,
+    textures::ImageMap,
+};
+
+/// Describes the light surrounding a scene
+///
+/// Rays that miss every object gather the environment's radiance, and environments that are lights
+/// are also sampled directly, like emissive objects.
+pub trait Environment: Send + Sync {
+    /// Returns the radiance arriving from the environment along `-direction`,
+    /// as seen by a ray leaving the scene in `direction`
+    fn radiance(&self, direction: Vec3A) -> Color;
+
+    /// Returns whether the environment supports [Environment::sample]
+    ///
+    /// Such environments are used as lights for direct light sampling.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Samples a direction towards the environment, with an infinite distance
+    ///
+    /// Returns `None` if the environment does not support sampling or no direction could be sampled.
+    fn sample(&self, _rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// Returns the probability density, with respect to solid angle, of [Environment::sample] choosing `direction`
+    ///
+    /// Returns zero if the environment does not support sampling.
+    fn pdf_value(&self, _direction: Vec3A) -> f32 {
+        0.0
+    }
+}
+
+impl Environment for Color {
+    fn radiance(&self, _direction: Vec3A) -> Color {
+        // like a background color, the same in all directions
+        *self
+    }
+}
+
+/// Returns the equirectangular coordinates of the unit vector `direction`, and the sine of its polar angle
+///
+/// The center of the image at (0.5, 0.5) looks down the negative z axis, with the positive y axis up at v = 0.
+fn equirect_coords(direction: Vec3A) -> (f32, f32, f32) {
+    let theta = direction.y.clamp(-1.0, 1.0).acos();
+    let u = 0.5 + direction.x.atan2(-direction.z) / TAU;
+    (u, theta / PI, theta.sin())
+}
+
+/// Returns the unit vector at the equirectangular coordinates (`u`, `v`), and the sine of its polar angle
+pub(crate) fn equirect_direction(u: f32, v: f32) -> (Vec3A, f32) {
+    let (sin_phi, cos_phi) = (TAU * (u - 0.5)).sin_cos();
+    let (sin_theta, cos_theta) = (PI * v).sin_cos();
+    let direction = Vec3A::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi);
+    (direction, sin_theta)
+}
+
+/// A distribution of directions, piecewise constant over the texels of an equirectangular image
+///
+/// Texels are chosen in proportion to their weight times the solid angle they cover,
+/// by first choosing a row, then a texel in that row.
+#[derive(Debug)]
+pub(crate) struct EquirectDistribution {
+    width: usize,
+    height: usize,
+    /// The cumulative distribution of choosing each row
+    rows_cdf: Vec<f32>,
+    /// The cumulative distribution of choosing each texel, given its row
+    columns_cdf: Vec<f32>,
+    /// The probability of choosing each texel
+    texel_probs: Vec<f32>,
+}
+
+impl EquirectDistribution {
+    /// Creates a new distribution from the non-negative `weights` of a `width` by `height` image, row by row from the top
+    pub(crate) fn new(width: usize, height: usize, weights: &[f32]) -> Self {
+        // rows near the poles cover less solid angle, and are chosen less often
+        let weights: Vec<f32> = weights
+            .iter()
+            .enumerate()
+            .map(|(idx, &weight)| {
+                let theta = PI * ((idx / width) as f32 + 0.5) / height as f32;
+                weight.max(0.0) * theta.sin()
+            })
+            .collect();
+
+        let row_sums: Vec<f32> = weights.chunks(width).map(|row| row.iter().sum()).collect();
+        let total: f32 = row_sums.iter().sum();
+
+        let (rows_cdf, columns_cdf, texel_probs) = if total > 0.0 {
+            (
+                cumulative(&row_sums),
+                weights.chunks(width).flat_map(cumulative).collect(),
+                weights.iter().map(|weight| weight / total).collect(),
+            )
+        } else {
+            // nothing to sample
+            (
+                vec![0.0; height],
+                vec![0.0; width * height],
+                vec![0.0; width * height],
+            )
+        };
+
+        Self {
+            width,
+            height,
+            rows_cdf,
+            columns_cdf,
+            texel_probs,
+        }
+    }
+
+    /// Returns whether any direction can be sampled
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.rows_cdf.last().is_some_and(|&total| total > 0.0)
+    }
+
+    /// Returns the index of the texel at the equirectangular coordinates (`u`, `v`)
+    fn texel_idx(&self, u: f32, v: f32) -> usize {
+        let column = ((u * self.width as f32) as usize).min(self.width - 1);
+        let row = ((v * self.height as f32) as usize).min(self.height - 1);
+        row * self.width + column
+    }
+
+    /// Converts a texel's probability into a density with respect to solid angle
+    ///
+    /// Texels are sampled uniformly in their area of the image, which covers `2π²sin(θ)` times as much solid angle.
+    fn solid_angle_pdf(&self, texel_prob: f32, sin_theta: f32) -> f32 {
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        texel_prob * (self.width * self.height) as f32 / (2.0 * PI * PI * sin_theta)
+    }
+
+    /// Samples a unit direction, returning it with its probability density with respect to solid angle
+    pub(crate) fn sample(&self, rng: &mut dyn RngCore) -> Option<(Vec3A, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // choose a texel by its row, then its column in that row
+        let row = sample_cdf(&self.rows_cdf, rng.gen());
+        let row_cdf = &self.columns_cdf[row * self.width..(row + 1) * self.width];
+        let column = sample_cdf(row_cdf, rng.gen());
+
+        // then a point uniformly within the texel
+        let u = (column as f32 + rng.gen::<f32>()) / self.width as f32;
+        let v = (row as f32 + rng.gen::<f32>()) / self.height as f32;
+        let (direction, sin_theta) = equirect_direction(u, v);
+
+        let pdf = self.solid_angle_pdf(self.texel_probs[row * self.width + column], sin_theta);
+        (pdf > 0.0).then_some((direction, pdf))
+    }
+
+    /// Returns the probability density, with respect to solid angle, of [EquirectDistribution::sample] choosing the unit `direction`
+    pub(crate) fn pdf(&self, direction: Vec3A) -> f32 {
+        let (u, v, sin_theta) = equirect_coords(direction);
+        self.solid_angle_pdf(self.texel_probs[self.texel_idx(u, v)], sin_theta)
+    }
+}
+
+/// Returns the normalized cumulative sums of `weights`, or all zeroes if they sum to zero
+fn cumulative(weights: &[f32]) -> Vec<f32> {
+    let mut sum = 0.0;
+    let mut cdf: Vec<f32> = weights
+        .iter()
+        .map(|weight| {
+            sum += weight;
+            sum
+        })
+        .collect();
+    if sum > 0.0 {
+        cdf.iter_mut().for_each(|value| *value /= sum);
+    }
+    cdf
+}
+
+/// Returns the index of the first entry in the cumulative distribution `cdf` above `u`
+fn sample_cdf(cdf: &[f32], u: f32) -> usize {
+    cdf.partition_point(|&value| value <= u).min(cdf.len() - 1)
+}
+
+/// An environment given by an equirectangular image, like an HDR or EXR light probe
+///
+/// The image's center looks down the negative z axis, with the positive y axis up.
+/// Directions are importance sampled in proportion to the luminance of the image,
+/// so small, bright features like the sun are found by shadow rays instead of by chance.
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    /// The radiance of each texel, row by row from the top
+    texels: Vec<Color>,
+    /// The distribution of sampled directions, in the image's frame
+    distribution: EquirectDistribution,
+    /// Rotates directions in the scene into the image's frame
+    to_local: Mat3A,
+    /// Rotates directions in the image's frame into the scene
+    to_world: Mat3A,
+}
+
+impl EnvironmentMap {
+    /// Creates a new [EnvironmentMap] from the given equirectangular image
+    ///
+    /// The environment is turned counterclockwise by `rotation` degrees around the y axis, and its radiance scaled by `intensity`.
+    pub fn new(image_map: &ImageMap, rotation: f32, intensity: f32) -> Self {
+        let image = image_map.image().to_rgb32f();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let texels: Vec<Color> = image
+            .pixels()
+            .map(|pixel| Color::from_array(pixel.0) * intensity)
+            .collect();
+        let luminances: Vec<f32> = texels.iter().map(|&texel| luminance(texel)).collect();
+
+        let to_world = Mat3A::from_rotation_y(rotation.to_radians());
+        Self {
+            distribution: EquirectDistribution::new(width, height, &luminances),
+            texels,
+            to_local: to_world.transpose(),
+            to_world,
+        }
+    }
+
+    /// Creates a new [EnvironmentMap] from the equirectangular image located at `file_path`, see [EnvironmentMap::new]
+    ///
+    /// HDR and EXR images need the `hdr` feature.
+    pub fn open(
+        file_path: impl AsRef<std::path::Path>,
+        rotation: f32,
+        intensity: f32,
+    ) -> image::ImageResult<Self> {
+        ImageMap::open(file_path).map(|image_map| Self::new(&image_map, rotation, intensity))
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3A) -> Color {
+        let (u, v, _) = equirect_coords(self.to_local * direction.normalize());
+        self.texels[self.distribution.texel_idx(u, v)]
+    }
+
+    fn is_light(&self) -> bool {
+        !self.distribution.is_empty()
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        let (local, pdf) = self.distribution.sample(rng)?;
+        Some(SurfaceSample {
+            direction: self.to_world * local,
+            distance: f32::INFINITY,
+            pdf,
+        })
+    }
+
+    fn pdf_value(&self, direction: Vec3A) -> f32 {
+        self.distribution.pdf(self.to_local * direction.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgb32FImage};
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    /// A dim environment with a bright spot, shaped like a sun
+    fn sunny() -> EnvironmentMap {
+        let image = Rgb32FImage::from_fn(64, 32, |x, y| {
+            if (x, y) == (40, 10) {
+                image::Rgb([10000.0, 9000.0, 8000.0])
+            } else {
+                image::Rgb([0.1, 0.2, 0.4])
+            }
+        });
+        EnvironmentMap::new(
+            &ImageMap::from_image(DynamicImage::ImageRgb32F(image)),
+            30.0,
+            2.0,
+        )
+    }
+
+    #[test]
+    fn samples_match_pdf() {
+        let env = sunny();
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let mut towards_sun = 0;
+        for _ in 0..1000 {
+            let sample = env.sample(&mut rng).expect("the map should be sampled");
+            let pdf = env.pdf_value(sample.direction);
+            assert!(
+                (pdf - sample.pdf).abs() <= 1e-3 * pdf,
+                "sampled pdf {} doesn't match {pdf}",
+                sample.pdf
+            );
+            towards_sun += (env.radiance(sample.direction).x > 1000.0) as usize;
+        }
+        assert!(
+            towards_sun > 900,
+            "only {towards_sun} samples found the sun"
+        );
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let image = Rgb32FImage::from_fn(64, 32, |x, y| {
+            image::Rgb([x as f32 / 64.0, y as f32 / 32.0, 0.5])
+        });
+        let env = EnvironmentMap::new(
+            &ImageMap::from_image(DynamicImage::ImageRgb32F(image)),
+            0.0,
+            1.0,
+        );
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let n = 100_000;
+        let estimate: f32 = (0..n)
+            .map(|_| {
+                let direction = crate::utils::random::rand_vec3_on_unit_sphere(&mut rng);
+                env.pdf_value(direction) * 4.0 * PI
+            })
+            .sum::<f32>()
+            / n as f32;
+        assert!(
+            (estimate - 1.0).abs() < 0.02,
+            "pdf integrates to {estimate}"
+        );
+    }
+
+    #[test]
+    fn rotation_and_intensity() {
+        let env = sunny();
+        let bright = Color::new(20000.0, 18000.0, 16000.0);
+
+        // the sun's texel center in the image's frame, turned by 30 degrees
+        let (local, _) = equirect_direction(40.5 / 64.0, 10.5 / 32.0);
+        let world = Mat3A::from_rotation_y(30f32.to_radians()) * local;
+
+        assert_eq!(env.radiance(world), bright);
+        assert_ne!(env.radiance(local), bright);
+        assert!(!Color::splat(1.0).is_light());
+    }
+}
@@ -0,0 +1,342 @@
+//! A procedural daylight sky and sun, following [Preetham et al.'s "A Practical Analytic Model for Daylight"](https://www2.cs.utah.edu/~shirley/papers/sunsky/sunsky.pdf)
+
+use std::f32::consts::PI;
+
+use glam::Vec3A;
+use rand::{Rng, RngCore};
+
+use super::environment::{equirect_direction, Environment, EquirectDistribution};
+use crate::{
+    color::{luminance, Color},
+    hittables::SurfaceSample,
+    spectrum::XYZ_TO_SRGB,
+    utils::random::rand_vec3_in_cone,
+};
+
+/// The model's luminance is in kilocandela per square meter; scaling it by this factor
+/// makes a white diffuse surface under a high sun in a clear sky roughly white
+const UNITS_PER_KCD: f32 = 0.025;
+
+/// The luminance of the sun outside the atmosphere, in kilocandela per square meter
+const SUN_LUMINANCE: f32 = 1.9e6;
+
+/// The cosine of the angular radius of the sun, as seen from the earth
+const SUN_COS_RADIUS: f32 = 0.999_989_2;
+
+/// Wavelengths in micrometers standing in for the red, green, and blue channels when attenuating sunlight
+const RGB_WAVELENGTHS: [f32; 3] = [0.680, 0.550, 0.440];
+
+/// The resolution of the table that sky directions are importance sampled from
+const TABLE_SIZE: (usize, usize) = (64, 32);
+
+/// The coefficients of Perez et al.'s sky luminance distribution for one channel
+#[derive(Debug, Clone, Copy)]
+struct Perez([f32; 5]);
+
+impl Perez {
+    /// Returns the relative luminance at the polar angle `theta`, and the angle `gamma` from the sun
+    fn eval(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta.max(1e-3)).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+/// A clear daylight sky, lit by the sun
+///
+/// The sky's color depends on the sun's position and the turbidity of the atmosphere, roughly the amount of haze:
+/// 2 is a very clear sky, 3 a clear one, and 10 a hazy one.
+/// Below the horizon is a ground reflecting the sky and sun with the given albedo.
+///
+/// The sun is a directional light the angular size of the real one,
+/// its color attenuated by the atmosphere like Preetham's model of sunlight.
+/// It is sampled along with the sky, with directions towards the sky importance sampled by their luminance.
+#[derive(Debug)]
+pub struct Sky {
+    /// The unit direction towards the sun
+    sun_direction: Vec3A,
+    /// The radiance of the sun's disk
+    sun_radiance: Color,
+    /// The distribution coefficients for luminance Y and chromaticities x and y
+    perez: [Perez; 3],
+    /// The values of Y, x, and y at the zenith, divided by their distribution at the zenith
+    zenith: [f32; 3],
+    /// The radiance of the ground
+    ground: Color,
+    /// The factor to scale the sky's radiance by
+    intensity: f32,
+    /// The distribution of sampled directions towards the sky and ground
+    distribution: EquirectDistribution,
+    /// The chance of sampling the sun instead of the sky
+    sun_prob: f32,
+}
+
+impl Sky {
+    /// The albedo of the ground when none is given, about that of grass or soil
+    pub const DEFAULT_GROUND_ALBEDO: Color = Color::splat(0.3);
+
+    /// Creates a new [Sky], with the sun towards `sun_direction`, scaling the radiance of both by `intensity`
+    ///
+    /// The model isn't made for night skies, so the sun is kept above the horizon.
+    /// A zero `sun_direction` puts the sun at the zenith.
+    pub fn new(sun_direction: Vec3A, turbidity: f32, ground_albedo: Color, intensity: f32) -> Self {
+        let mut sun_direction = sun_direction.try_normalize().unwrap_or(Vec3A::Y);
+        if sun_direction.y < 0.01 {
+            sun_direction = Vec3A::new(sun_direction.x, 0.0, sun_direction.z).normalize_or_zero();
+            sun_direction = (sun_direction + Vec3A::new(0.0, 0.01, 0.0)).normalize();
+        }
+
+        let t = turbidity;
+        let perez = [
+            Perez([
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ]),
+            Perez([
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ]),
+            Perez([
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ]),
+        ];
+
+        // the zenith's luminance and chromaticity, see the paper's appendix
+        let theta_s = sun_direction.y.acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta_powers = Vec3A::new(theta_s.powi(3), theta_s.powi(2), theta_s);
+        let zenith_chroma = |coefficients: [[f32; 4]; 3]| {
+            let [t2, t1, t0] =
+                coefficients.map(|[c3, c2, c1, c0]| theta_powers.dot(Vec3A::new(c3, c2, c1)) + c0);
+            t * t * t2 + t * t1 + t0
+        };
+        let zenith_x = zenith_chroma([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y_chroma = zenith_chroma([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [zenith_y, zenith_x, zenith_y_chroma];
+        let zenith = std::array::from_fn(|idx| zenith[idx] / perez[idx].eval(1.0, theta_s));
+
+        let mut sky = Self {
+            sun_direction,
+            sun_radiance: sun_radiance(theta_s, turbidity) * intensity,
+            perez,
+            zenith,
+            ground: Color::ZERO,
+            intensity,
+            distribution: EquirectDistribution::new(1, 1, &[0.0]),
+            sun_prob: 0.0,
+        };
+
+        // tabulate the sky, to importance sample it and to light the ground
+        let (width, height) = TABLE_SIZE;
+        let texel_solid_angle = 2.0 * PI * PI / (width * height) as f32;
+        let directions: Vec<Vec3A> = (0..width * height)
+            .map(|idx| {
+                let u = ((idx % width) as f32 + 0.5) / width as f32;
+                let v = ((idx / width) as f32 + 0.5) / height as f32;
+                equirect_direction(u, v).0
+            })
+            .collect();
+        let sky_radiance: Vec<Color> = directions
+            .iter()
+            .map(|&direction| sky.sky_radiance(direction))
+            .collect();
+
+        let sun_solid_angle = 2.0 * PI * (1.0 - SUN_COS_RADIUS);
+        let sky_irradiance: Color = directions
+            .iter()
+            .zip(&sky_radiance)
+            .map(|(direction, &radiance)| {
+                let sin_theta = (1.0 - direction.y * direction.y).sqrt();
+                radiance * direction.y.max(0.0) * sin_theta * texel_solid_angle
+            })
+            .sum();
+        let sun_irradiance = sky.sun_radiance * sun_solid_angle * sun_direction.y;
+        sky.ground = ground_albedo * (sky_irradiance + sun_irradiance) / PI;
+
+        let luminances: Vec<f32> = directions
+            .iter()
+            .zip(&sky_radiance)
+            .map(|(direction, &radiance)| match direction.y >= 0.0 {
+                true => luminance(radiance),
+                false => luminance(sky.ground),
+            })
+            .collect();
+        let sky_power: f32 = directions
+            .iter()
+            .zip(&luminances)
+            .map(|(direction, luminance)| {
+                luminance * (1.0 - direction.y * direction.y).sqrt() * texel_solid_angle
+            })
+            .sum();
+        let sun_power = luminance(sky.sun_radiance) * sun_solid_angle;
+
+        sky.distribution = EquirectDistribution::new(width, height, &luminances);
+        // always sample both, in case either is much brighter than the estimate
+        sky.sun_prob = (sun_power / (sun_power + sky_power)).clamp(0.1, 0.9);
+        sky
+    }
+
+    /// Returns the radiance of the sky above the horizon in the unit `direction`, without the sun
+    fn sky_radiance(&self, direction: Vec3A) -> Color {
+        let cos_theta = direction.y.max(0.0);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let [big_y, x, y] =
+            std::array::from_fn(|idx| self.zenith[idx] * self.perez[idx].eval(cos_theta, gamma));
+        if y <= 0.0 {
+            return Color::ZERO;
+        }
+        let xyz = Vec3A::new(x / y * big_y, big_y, (1.0 - x - y) / y * big_y);
+        (XYZ_TO_SRGB * xyz).max(Vec3A::ZERO) * UNITS_PER_KCD * self.intensity
+    }
+
+    /// Returns the density, with respect to solid angle, of sampling the unit `direction` towards the sun's disk
+    fn sun_pdf(&self, direction: Vec3A) -> f32 {
+        if direction.dot(self.sun_direction) >= SUN_COS_RADIUS {
+            1.0 / (2.0 * PI * (1.0 - SUN_COS_RADIUS))
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Returns the radiance of the sun's disk at the polar angle `theta_s`,
+/// after passing through the atmosphere with the given `turbidity`
+///
+/// Follows the paper's appendix, with only Rayleigh scattering and scattering by aerosols.
+fn sun_radiance(theta_s: f32, turbidity: f32) -> Color {
+    // relative optical mass, the length of the path through the atmosphere
+    let air_mass =
+        1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).max(0.1).powf(-1.253));
+    let beta = 0.046_083_66 * turbidity - 0.045_860_26;
+
+    let transmittance = RGB_WAVELENGTHS.map(|lambda| {
+        let rayleigh = 0.008_735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    });
+    Color::from_array(transmittance) * SUN_LUMINANCE * UNITS_PER_KCD
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: Vec3A) -> Color {
+        let direction = direction.normalize();
+        if direction.y < 0.0 {
+            return self.ground;
+        }
+
+        let sun = match direction.dot(self.sun_direction) >= SUN_COS_RADIUS {
+            true => self.sun_radiance,
+            false => Color::ZERO,
+        };
+        self.sky_radiance(direction) + sun
+    }
+
+    fn is_light(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, mut rng: &mut dyn RngCore) -> Option<SurfaceSample> {
+        let direction = if rng.gen::<f32>() < self.sun_prob {
+            rand_vec3_in_cone(&mut rng, self.sun_direction, SUN_COS_RADIUS)
+        } else {
+            self.distribution.sample(rng)?.0
+        };
+
+        let pdf = self.pdf_value(direction);
+        (pdf > 0.0).then_some(SurfaceSample {
+            direction,
+            distance: f32::INFINITY,
+            pdf,
+        })
+    }
+
+    fn pdf_value(&self, direction: Vec3A) -> f32 {
+        let direction = direction.normalize();
+        self.sun_prob * self.sun_pdf(direction)
+            + (1.0 - self.sun_prob) * self.distribution.pdf(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    fn sky(elevation: f32) -> Sky {
+        let (sin, cos) = elevation.to_radians().sin_cos();
+        Sky::new(Vec3A::new(0.0, sin, -cos), 3.0, Color::splat(0.3), 1.0)
+    }
+
+    #[test]
+    fn daylight_colors() {
+        let noon = sky(60.0);
+        let zenith = noon.radiance(Vec3A::Y);
+        assert!(zenith.z > zenith.x, "the sky should be blue, got {zenith}");
+        assert!(
+            noon.radiance(Vec3A::new(0.0, 0.1, 1.0)).length()
+                < noon.radiance(Vec3A::new(0.0, 0.9, -0.5)).length(),
+            "the sky should be brighter around the sun"
+        );
+
+        // the setting sun is dimmer and redder than the high sun
+        let sunset = sky(2.0);
+        let (high, low) = (noon.sun_radiance, sunset.sun_radiance);
+        assert!(luminance(low) < luminance(high));
+        assert!(
+            low.x / low.z > high.x / high.z,
+            "{low} should be redder than {high}"
+        );
+
+        // a white surface under the high sun is about white
+        let white = luminance(noon.ground) / 0.3;
+        assert!(
+            (0.5..3.0).contains(&white),
+            "white surface has luminance {white}"
+        );
+    }
+
+    #[test]
+    fn samples_match_pdf() {
+        let env = sky(30.0);
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let mut towards_sun = 0;
+        for _ in 0..1000 {
+            let sample = env.sample(&mut rng).expect("the sky should be sampled");
+            let pdf = env.pdf_value(sample.direction);
+            assert!(
+                (pdf - sample.pdf).abs() <= 1e-3 * pdf,
+                "sampled pdf {} doesn't match {pdf}",
+                sample.pdf
+            );
+            towards_sun += (env.sun_pdf(sample.direction) > 0.0) as usize;
+        }
+        let fraction = towards_sun as f32 / 1000.0;
+        assert!(
+            (fraction - env.sun_prob).abs() < 0.05,
+            "{fraction} of samples found the sun, expected {}",
+            env.sun_prob
+        );
+    }
+}
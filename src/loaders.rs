@@ -0,0 +1,110 @@
+//! Importers for geometry stored in external asset formats
+//!
+//! Each importer produces a [crate::hittables::HittableList] ready for use in a [crate::tree::Tree].
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{camera::Camera, hittables::HittableList, material::Material};
+
+pub mod gltf;
+pub mod obj;
+pub mod ply;
+
+pub use self::gltf::*;
+pub use obj::*;
+pub use ply::*;
+
+/// Errors produced while importing a model of any supported format
+#[derive(Debug)]
+pub enum ModelError {
+    /// The OBJ model could not be imported
+    Obj(ObjError),
+    /// The PLY model could not be imported
+    Ply(PlyError),
+    /// The glTF model could not be imported
+    Gltf(GltfError),
+    /// The model's file extension doesn't name a supported format
+    UnknownFormat(PathBuf),
+}
+
+impl std::fmt::Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::Obj(why) => why.fmt(f),
+            ModelError::Ply(why) => why.fmt(f),
+            ModelError::Gltf(why) => why.fmt(f),
+            ModelError::UnknownFormat(path) => write!(
+                f,
+                "unknown model format of {}, expected an .obj, .ply, .gltf, or .glb file",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModelError::Obj(why) => Some(why),
+            ModelError::Ply(why) => Some(why),
+            ModelError::Gltf(why) => Some(why),
+            ModelError::UnknownFormat(_) => None,
+        }
+    }
+}
+
+/// Loads the model at `path`, choosing the importer by its file extension.
+///
+/// Models without materials of their own use `default_material`.
+pub fn load_model(
+    path: impl AsRef<Path>,
+    default_material: &Arc<Material>,
+) -> Result<HittableList, ModelError> {
+    load_model_with_cameras(path, default_material).map(|(hittables, _)| hittables)
+}
+
+/// Returns the paths of the files that loading the model at `path` reads: the model itself,
+/// and any material libraries, textures, or buffers it refers to.
+///
+/// Files that can't be read are skipped, as loading the model reports them.
+pub fn model_sources(path: impl AsRef<Path>) -> Vec<PathBuf> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let mut sources = vec![path.to_path_buf()];
+    match extension.as_deref() {
+        Some("obj") => sources.extend(obj_sources(path)),
+        Some("gltf" | "glb") => sources.extend(gltf_sources(path)),
+        _ => {}
+    }
+    sources
+}
+
+/// Like [load_model], but also returns the cameras defined by the model, which only glTF files have.
+pub fn load_model_with_cameras(
+    path: impl AsRef<Path>,
+    default_material: &Arc<Material>,
+) -> Result<(HittableList, Vec<Camera>), ModelError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("obj") => load_obj(path, default_material)
+            .map(|hittables| (hittables, Vec::new()))
+            .map_err(ModelError::Obj),
+        Some("ply") => load_ply(path, default_material)
+            .map(|hittables| (hittables, Vec::new()))
+            .map_err(ModelError::Ply),
+        Some("gltf" | "glb") => load_gltf(path, default_material)
+            .map(|scene| (scene.hittables, scene.cameras))
+            .map_err(ModelError::Gltf),
+        _ => Err(ModelError::UnknownFormat(path.to_path_buf())),
+    }
+}
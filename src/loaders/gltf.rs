@@ -0,0 +1,816 @@
+//! glTF 2.0 importer
+//!
+//! Relies on the [gltf] crate for parsing, which reads both `.gltf` and `.glb` files,
+//! with buffers and images embedded as data URIs, stored in the binary chunk, or in external files.
+//!
+//! The default scene's node hierarchy is walked from its roots:
+//! * meshes of nodes without a transform are added as their triangles
+//! * meshes of transformed nodes are added as a [Transform] of a [Tree] over their triangles,
+//!   which is shared by every node instancing the same mesh
+//! * perspective and orthographic cameras become [Camera]s looking down the node's -Z axis, with its +Y axis as up
+//!
+//! Metallic-roughness materials are translated to the closest [Material]:
+//! * a non-black emission becomes [Material::DiffuseLight]
+//! * everything else becomes [Material::Principled], using the base color, metallic, and roughness factors and textures,
+//!   along with the `KHR_materials_transmission` and `KHR_materials_ior` extensions
+//!
+//! Emissive triangles are sampled as lights, including those inside the [Transform]s of instanced meshes.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Affine3A, Mat4, Vec2, Vec3A};
+use gltf::{
+    camera::Projection,
+    image::Format,
+    mesh::Mode,
+    texture::{Info, WrappingMode},
+};
+use image::{DynamicImage, ImageBuffer};
+
+use crate::{
+    camera::{Camera, Projection as CameraProjection},
+    color::{colors, Color},
+    hittables::{Hittable, HittableList, Transform, TriangleMesh},
+    material::Material,
+    textures::{ImageMap, Texture},
+    tree::Tree,
+};
+
+/// Errors produced while importing a glTF file
+#[derive(Debug)]
+pub enum GltfError {
+    /// The glTF file, or one of its buffers or images, could not be read or is malformed
+    Import {
+        /// Path of the glTF file
+        path: PathBuf,
+        /// The underlying import error
+        source: gltf::Error,
+    },
+    /// The glTF file's contents can't be rendered
+    Format {
+        /// Path of the glTF file
+        path: PathBuf,
+        /// Description of the problem
+        message: String,
+    },
+}
+
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Import { path, source } => {
+                write!(f, "failed to load glTF file {}: {source}", path.display())
+            }
+            GltfError::Format { path, message } => {
+                write!(f, "invalid glTF file {}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Import { source, .. } => Some(source),
+            GltfError::Format { .. } => None,
+        }
+    }
+}
+
+/// The contents of a glTF file's default scene
+pub struct GltfScene {
+    /// Geometry of every mesh in the scene
+    pub hittables: HittableList,
+    /// Cameras of the scene, in node order
+    ///
+    /// Their background color is black; glTF doesn't describe one.
+    pub cameras: Vec<Camera>,
+}
+
+/// Returns the paths of the external buffers and images the glTF or GLB file at `path` refers to
+pub(crate) fn gltf_sources(path: &Path) -> Vec<PathBuf> {
+    let Ok(gltf) = gltf::Gltf::open(path) else {
+        return Vec::new();
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let buffers = gltf.buffers().filter_map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let images = gltf.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffers
+        .chain(images)
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| base_dir.join(uri))
+        .collect()
+}
+
+/// Loads the glTF or GLB file at `path`, along with its buffers and images.
+///
+/// Primitives without a material use `default_material`.
+pub fn load_gltf(
+    path: impl AsRef<Path>,
+    default_material: &Arc<Material>,
+) -> Result<GltfScene, GltfError> {
+    let path = path.as_ref();
+    let (document, buffers, images) = gltf::import(path).map_err(|source| GltfError::Import {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let images: Vec<_> = images
+        .into_iter()
+        .map(|data| Arc::new(ImageMap::from_image(to_dynamic_image(data))))
+        .collect();
+    let materials = document
+        .materials()
+        .map(|material| translate_material(&material, &images))
+        .collect();
+
+    let mut importer = Importer {
+        path,
+        buffers: &buffers,
+        materials,
+        default_material,
+        meshes: vec![None; document.meshes().len()],
+        instances: vec![None; document.meshes().len()],
+        scene: GltfScene {
+            hittables: HittableList::new(),
+            cameras: Vec::new(),
+        },
+    };
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+    for node in scene.iter().flat_map(|scene| scene.nodes()) {
+        importer.visit(&node, Affine3A::IDENTITY)?;
+    }
+
+    if importer.scene.hittables.is_empty() {
+        return Err(importer.invalid("the default scene has no triangle meshes"));
+    }
+    Ok(importer.scene)
+}
+
+/// State kept while walking a glTF scene's node hierarchy
+struct Importer<'a> {
+    path: &'a Path,
+    buffers: &'a [gltf::buffer::Data],
+    materials: Vec<Arc<Material>>,
+    default_material: &'a Arc<Material>,
+    /// Triangles of each mesh, once loaded
+    meshes: Vec<Option<HittableList>>,
+    /// Shared [Tree] of each mesh's triangles, once a transformed node instances it
+    instances: Vec<Option<Arc<dyn Hittable>>>,
+    scene: GltfScene,
+}
+
+impl Importer<'_> {
+    /// Returns an error describing malformed contents of the file
+    fn invalid(&self, message: impl Into<String>) -> GltfError {
+        GltfError::Format {
+            path: self.path.to_path_buf(),
+            message: message.into(),
+        }
+    }
+
+    /// Adds the node's mesh and camera to the scene, then recurses into its children
+    fn visit(&mut self, node: &gltf::Node, parent: Affine3A) -> Result<(), GltfError> {
+        let local = Mat4::from_cols_array_2d(&node.transform().matrix());
+        let world = parent * Affine3A::from_mat4(local);
+
+        if let Some(mesh) = node.mesh() {
+            if world == Affine3A::IDENTITY {
+                let triangles = self.mesh_triangles(&mesh)?;
+                self.scene.hittables.extend(triangles);
+            } else {
+                let instance = self.mesh_instance(&mesh)?;
+                self.scene
+                    .hittables
+                    .push(Arc::new(Transform::from_affine(world, &instance)));
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            let look_from = world.transform_point3a(Vec3A::ZERO);
+            let forward = world.transform_vector3a(Vec3A::NEG_Z).normalize();
+            let new_camera = |vert_fov: f32, aspect_ratio: f32| {
+                Camera::new(
+                    look_from,
+                    look_from + forward,
+                    world.transform_vector3a(Vec3A::Y).normalize(),
+                    vert_fov,
+                    aspect_ratio,
+                    0.0,
+                    1.0,
+                    0.0..1.0,
+                    colors::BLACK,
+                )
+            };
+            let camera = match camera.projection() {
+                Projection::Perspective(perspective) => new_camera(
+                    perspective.yfov().to_degrees(),
+                    perspective.aspect_ratio().unwrap_or(16.0 / 9.0),
+                ),
+                // the magnifications are half the view's size, before the node's scale
+                Projection::Orthographic(orthographic) => {
+                    let scale = world.transform_vector3a(Vec3A::Y).length();
+                    let height = 2.0 * orthographic.ymag() * scale;
+                    new_camera(90.0, orthographic.xmag() / orthographic.ymag())
+                        .with_projection(CameraProjection::Orthographic { height })
+                }
+            };
+            self.scene.cameras.push(camera);
+        }
+
+        for child in node.children() {
+            self.visit(&child, world)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the shared [Tree] over the mesh's triangles, building it on first use
+    fn mesh_instance(&mut self, mesh: &gltf::Mesh) -> Result<Arc<dyn Hittable>, GltfError> {
+        if let Some(instance) = &self.instances[mesh.index()] {
+            return Ok(Arc::clone(instance));
+        }
+        let triangles = self.mesh_triangles(mesh)?;
+        let instance: Arc<dyn Hittable> = Arc::new(Tree::new(triangles, 0.0, 1.0));
+        self.instances[mesh.index()] = Some(Arc::clone(&instance));
+        Ok(instance)
+    }
+
+    /// Returns the triangles of all of the mesh's primitives, loading them on first use
+    fn mesh_triangles(&mut self, mesh: &gltf::Mesh) -> Result<HittableList, GltfError> {
+        if let Some(triangles) = &self.meshes[mesh.index()] {
+            return Ok(triangles.clone());
+        }
+        let mut triangles = HittableList::new();
+        for primitive in mesh.primitives() {
+            if let Some(tri_mesh) = self.load_primitive(mesh, &primitive)? {
+                triangles.extend(Arc::new(tri_mesh).triangles());
+            }
+        }
+        if triangles.is_empty() {
+            return Err(self.invalid(format!(
+                "mesh {} has no triangles",
+                mesh.name().unwrap_or_default()
+            )));
+        }
+        self.meshes[mesh.index()] = Some(triangles.clone());
+        Ok(triangles)
+    }
+
+    /// Reads a primitive into a [TriangleMesh]
+    ///
+    /// Returns `None` for points and lines, which have no surface to render.
+    fn load_primitive(
+        &self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+    ) -> Result<Option<TriangleMesh>, GltfError> {
+        let mesh_name = mesh.name().unwrap_or_default();
+        let reader =
+            primitive.reader(|buffer| self.buffers.get(buffer.index()).map(|data| &**data));
+
+        let positions: Vec<Vec3A> = reader
+            .read_positions()
+            .ok_or_else(|| self.invalid(format!("mesh {mesh_name} has no vertex positions")))?
+            .map(Vec3A::from)
+            .collect();
+        let vertices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        let indices: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::Triangles => vertices
+                .chunks_exact(3)
+                .map(|tri| [tri[0], tri[1], tri[2]])
+                .collect(),
+            // every other triangle of a strip is wound the other way
+            Mode::TriangleStrip => vertices
+                .windows(3)
+                .enumerate()
+                .map(|(i, tri)| match i % 2 {
+                    0 => [tri[0], tri[1], tri[2]],
+                    _ => [tri[1], tri[0], tri[2]],
+                })
+                .collect(),
+            Mode::TriangleFan => vertices
+                .windows(2)
+                .skip(1)
+                .map(|edge| [vertices[0], edge[0], edge[1]])
+                .collect(),
+            Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => return Ok(None),
+        };
+        if let Some(&index) = indices
+            .iter()
+            .flatten()
+            .find(|&&index| index as usize >= positions.len())
+        {
+            return Err(self.invalid(format!(
+                "mesh {mesh_name} indexes vertex {index}, but only has {} vertices",
+                positions.len()
+            )));
+        }
+
+        let material = primitive
+            .material()
+            .index()
+            .and_then(|index| self.materials.get(index))
+            .unwrap_or(self.default_material);
+        let vertex_count = positions.len();
+        let mut tri_mesh = TriangleMesh::new(positions, indices, material);
+
+        if let Some(normals) = reader.read_normals() {
+            let normals: Vec<_> = normals.map(Vec3A::from).collect();
+            if normals.len() != vertex_count {
+                return Err(self.invalid(format!(
+                    "mesh {mesh_name} has {} normals for {vertex_count} vertices",
+                    normals.len()
+                )));
+            }
+            tri_mesh = tri_mesh.with_normals(normals);
+        }
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            // glTF puts the uv origin at the top left of images, lustre at the bottom left
+            let uvs: Vec<_> = uvs.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect();
+            if uvs.len() != vertex_count {
+                return Err(self.invalid(format!(
+                    "mesh {mesh_name} has {} texture coordinates for {vertex_count} vertices",
+                    uvs.len()
+                )));
+            }
+            tri_mesh = tri_mesh.with_uvs(uvs);
+        }
+
+        Ok(Some(tri_mesh))
+    }
+}
+
+/// Converts image data decoded by [gltf] back into an [image::DynamicImage]
+fn to_dynamic_image(data: gltf::image::Data) -> DynamicImage {
+    let gltf::image::Data {
+        pixels,
+        format,
+        width,
+        height,
+    } = data;
+    // wider channels are stored as native-endian bytes
+    fn wide(bytes: &[u8]) -> Vec<u16> {
+        bytes
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect()
+    }
+    fn float(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    }
+
+    let image = match format {
+        Format::R8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+        Format::R8G8B8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => {
+            ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+        }
+        Format::R16 => {
+            ImageBuffer::from_raw(width, height, wide(&pixels)).map(DynamicImage::ImageLuma16)
+        }
+        Format::R16G16 => {
+            ImageBuffer::from_raw(width, height, wide(&pixels)).map(DynamicImage::ImageLumaA16)
+        }
+        Format::R16G16B16 => {
+            ImageBuffer::from_raw(width, height, wide(&pixels)).map(DynamicImage::ImageRgb16)
+        }
+        Format::R16G16B16A16 => {
+            ImageBuffer::from_raw(width, height, wide(&pixels)).map(DynamicImage::ImageRgba16)
+        }
+        Format::R32G32B32FLOAT => {
+            ImageBuffer::from_raw(width, height, float(&pixels)).map(DynamicImage::ImageRgb32F)
+        }
+        Format::R32G32B32A32FLOAT => {
+            ImageBuffer::from_raw(width, height, float(&pixels)).map(DynamicImage::ImageRgba32F)
+        }
+    };
+    // gltf sizes the pixel data from the image's dimensions, so this always fits
+    image.unwrap_or_else(|| unreachable!("glTF image data should match its dimensions"))
+}
+
+/// A glTF texture lookup, scaled by its material factor
+struct GltfTexture {
+    image: Arc<ImageMap>,
+    /// Wrapping modes along u and v
+    wrap: [WrappingMode; 2],
+    /// Whether texels are sRGB encoded, like base colors and emission, rather than linear data
+    srgb: bool,
+    /// The channel holding a scalar parameter, like roughness, which is splatted across the color
+    channel: Option<usize>,
+    factor: Color,
+}
+
+impl GltfTexture {
+    fn new(
+        info: &Info,
+        images: &[Arc<ImageMap>],
+        srgb: bool,
+        channel: Option<usize>,
+        factor: Color,
+    ) -> Self {
+        let texture = info.texture();
+        let sampler = texture.sampler();
+        Self {
+            image: Arc::clone(&images[texture.source().index()]),
+            wrap: [sampler.wrap_s(), sampler.wrap_t()],
+            srgb,
+            channel,
+            factor,
+        }
+    }
+}
+
+/// Maps a texture coordinate into `[0, 1]` according to the wrapping `mode`
+fn wrap(coord: f32, mode: WrappingMode) -> f32 {
+    match mode {
+        WrappingMode::ClampToEdge => coord.clamp(0.0, 1.0),
+        WrappingMode::Repeat => coord.rem_euclid(1.0),
+        WrappingMode::MirroredRepeat => 1.0 - (coord.rem_euclid(2.0) - 1.0).abs(),
+    }
+}
+
+impl Texture for GltfTexture {
+    fn color(&self, u: f32, v: f32, point: Vec3A) -> Color {
+        let [wrap_u, wrap_v] = self.wrap;
+        let mut color = self.image.color(wrap(u, wrap_u), wrap(v, wrap_v), point);
+        if self.srgb {
+            // matches the gamma used when encoding the rendered image
+            color = color.powf(2.2);
+        }
+        if let Some(channel) = self.channel {
+            color = Color::splat(color[channel]);
+        }
+        color * self.factor
+    }
+}
+
+/// Translates a glTF material into a [Material]
+fn translate_material(material: &gltf::Material, images: &[Arc<ImageMap>]) -> Arc<Material> {
+    let emission =
+        Color::from(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0);
+    if emission.max_element() > 0.0 {
+        // split the emission into a normalized color and its brightness
+        let brightness = emission.max_element();
+        let albedo: Arc<dyn Texture> = match material.emissive_texture() {
+            Some(info) => Arc::new(GltfTexture::new(
+                &info,
+                images,
+                true,
+                None,
+                emission / brightness,
+            )),
+            None => Arc::new(emission / brightness),
+        };
+        return Arc::new(Material::DiffuseLight {
+            albedo,
+            brightness,
+            two_sided: true,
+            profile: None,
+        });
+    }
+
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, _alpha] = pbr.base_color_factor();
+    let base_color: Arc<dyn Texture> = match pbr.base_color_texture() {
+        Some(info) => Arc::new(GltfTexture::new(
+            &info,
+            images,
+            true,
+            None,
+            Color::new(r, g, b),
+        )),
+        None => Arc::new(Color::new(r, g, b)),
+    };
+
+    // roughness is stored in the green channel, and metalness in the blue channel
+    let scalar = |channel: usize, factor: f32, info: Option<Info>| -> Arc<dyn Texture> {
+        match info {
+            Some(info) => Arc::new(GltfTexture::new(
+                &info,
+                images,
+                false,
+                Some(channel),
+                Color::splat(factor),
+            )),
+            None => Arc::new(Color::splat(factor)),
+        }
+    };
+    let metallic = scalar(2, pbr.metallic_factor(), pbr.metallic_roughness_texture());
+    let roughness = scalar(1, pbr.roughness_factor(), pbr.metallic_roughness_texture());
+    let transmission = match material.transmission() {
+        Some(transmission) => scalar(
+            0,
+            transmission.transmission_factor(),
+            transmission.transmission_texture(),
+        ),
+        None => Arc::new(Color::ZERO),
+    };
+
+    Arc::new(Material::Principled {
+        base_color,
+        metallic,
+        roughness,
+        // glTF's dielectric reflectance of 4% at normal incidence
+        specular: Arc::new(Color::splat(0.5)),
+        clearcoat: Arc::new(Color::ZERO),
+        sheen: Arc::new(Color::ZERO),
+        transmission,
+        refract_index: material.ior().unwrap_or(1.5),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    /// Geometry of the unit square in the xy plane: positions, then uvs, then indices
+    fn square_buffer() -> Vec<u8> {
+        let positions = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0f32,
+        ];
+        let uvs = [0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0f32];
+        let indices = [0, 1, 2, 0, 2, 3u16];
+
+        let mut buffer: Vec<u8> = positions
+            .iter()
+            .chain(&uvs)
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        buffer
+    }
+
+    /// Returns the JSON of a glTF file with the square mesh, given the description of its only buffer
+    fn square_json(buffer: &str, nodes: &str, scene_nodes: &str, extra: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": {scene_nodes}}}],
+                "nodes": {nodes},
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "TEXCOORD_0": 1}}, "indices": 2, "material": 0}}]}}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC2"}},
+                    {{"bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR"}}
+                ],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 48}},
+                    {{"buffer": 0, "byteOffset": 48, "byteLength": 32}},
+                    {{"buffer": 0, "byteOffset": 80, "byteLength": 12}}
+                ],
+                "buffers": [{buffer}]
+                {extra}
+            }}"#
+        )
+    }
+
+    /// Encodes `bytes` as standard base64, with padding
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        bytes
+            .chunks(3)
+            .flat_map(|chunk| {
+                let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+                    bits | (byte as u32) << (16 - 8 * i)
+                });
+                (0..4).map(move |i| match i <= chunk.len() {
+                    true => ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char,
+                    false => '=',
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the directory for the test's files
+    fn test_dir(test_name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lustre-gltf-{test_name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn default_material() -> Arc<Material> {
+        Arc::new(Material::Lambertian {
+            albedo: Arc::new(Color::splat(0.5)),
+        })
+    }
+
+    #[test]
+    fn embedded_hierarchy() {
+        let buffer = format!(
+            r#"{{"byteLength": 92, "uri": "data:application/octet-stream;base64,{}"}}"#,
+            base64(&square_buffer())
+        );
+        // the square is scaled up by its own node, then moved back by its parent, which the camera also inherits
+        let nodes = r#"[
+            {"translation": [0, 0, -5], "children": [1, 2]},
+            {"mesh": 0, "scale": [2, 2, 2]},
+            {"camera": 0, "translation": [1, 1, 5]}
+        ]"#;
+        let extra = r#",
+            "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [0.8, 0.2, 0.1, 1], "metallicFactor": 0.25}}],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.5, "aspectRatio": 2, "znear": 0.1}}]"#;
+        let dir = test_dir("embedded");
+        let path = dir.join("square.gltf");
+        std::fs::write(&path, square_json(&buffer, nodes, "[0]", extra)).unwrap();
+
+        let scene = load_gltf(&path, &default_material()).unwrap();
+        assert_eq!(scene.hittables.len(), 1, "the mesh should be one instance");
+        let bbox = scene.hittables.bounding_box(0.0, 1.0).unwrap();
+        assert!(bbox.min.abs_diff_eq(Vec3A::new(0.0, 0.0, -5.0), 1e-3));
+        assert!(bbox.max.abs_diff_eq(Vec3A::new(2.0, 2.0, -5.0), 1e-3));
+
+        // the camera sits at (1, 1, 0), looking down -Z at the middle of the square
+        assert_eq!(scene.cameras.len(), 1);
+        let camera = scene.cameras[0];
+        assert_eq!(camera.aspect_ratio, 2.0);
+        let mut rng = rand::thread_rng();
+        let ray = camera.get_ray(0.5, 0.5, &mut rng).unwrap();
+        assert!(ray.origin.abs_diff_eq(Vec3A::new(1.0, 1.0, 0.0), 1e-3));
+        assert!(ray.direction.normalize().abs_diff_eq(Vec3A::NEG_Z, 1e-3));
+
+        let rec = scene.hittables.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((rec.t * ray.direction.length() - 5.0).abs() < 1e-3);
+        assert!(rec.front_face, "the square faces +Z");
+        assert!(rec.normal.abs_diff_eq(Vec3A::Z, 1e-3));
+        match rec.material.as_ref() {
+            Material::Principled {
+                base_color,
+                metallic,
+                roughness,
+                ..
+            } => {
+                let color = |texture: &Arc<dyn Texture>| texture.color(rec.u, rec.v, rec.point);
+                assert_eq!(color(base_color), Color::new(0.8, 0.2, 0.1));
+                assert_eq!(color(metallic), Color::splat(0.25));
+                assert_eq!(color(roughness), Color::ONE, "roughness defaults to 1");
+            }
+            material => panic!("expected a principled material, got {material:?}"),
+        }
+    }
+
+    #[test]
+    fn emissive_instance_is_sampled() {
+        let buffer = format!(
+            r#"{{"byteLength": 92, "uri": "data:application/octet-stream;base64,{}"}}"#,
+            base64(&square_buffer())
+        );
+        let nodes = r#"[{"mesh": 0, "scale": [2, 2, 2], "translation": [0, 0, -5]}]"#;
+        let extra = r#",
+            "materials": [{"emissiveFactor": [1, 1, 1]}]"#;
+        let dir = test_dir("emissive");
+        let path = dir.join("lamp.gltf");
+        std::fs::write(&path, square_json(&buffer, nodes, "[0]", extra)).unwrap();
+
+        let mut geometry = load_gltf(&path, &default_material()).unwrap().hittables;
+        // a floor in front of the lamp, which only lights it directly within a single bounce
+        geometry.push(
+            crate::hittables::Quad::new(
+                Vec3A::new(-5.0, -1.0, -10.0),
+                Vec3A::new(-5.0, -1.0, 0.0),
+                Vec3A::new(5.0, -1.0, 0.0),
+                Vec3A::new(5.0, -1.0, -10.0),
+                &default_material(),
+            )
+            .wrap(),
+        );
+        let lights = crate::lights::LightList::new(&geometry);
+
+        let rng = &mut SmallRng::seed_from_u64(0);
+        let ray = Ray::new(Vec3A::new(1.0, 0.0, 0.0), Vec3A::new(0.0, -1.0, -1.0), 0.0);
+        let radiance = (0..16)
+            .map(|_| ray.shade(&geometry, &lights, 1, 1, rng))
+            .sum::<Color>();
+        assert!(
+            radiance.min_element() > 0.0,
+            "the floor should be lit by sampling the lamp"
+        );
+    }
+
+    #[test]
+    fn binary_with_external_image() {
+        let dir = test_dir("binary");
+        // the left half is red and the right half is mid-gray blue, in sRGB
+        let mut image = image::RgbImage::new(2, 1);
+        image.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        image.put_pixel(1, 0, image::Rgb([0, 0, 128]));
+        image.save(dir.join("texture.png")).unwrap();
+
+        let nodes = r#"[{"mesh": 0}]"#;
+        let extra = r#",
+            "materials": [{"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}}}],
+            "textures": [{"source": 0}],
+            "images": [{"uri": "texture.png"}]"#;
+        let mut json = square_json(r#"{"byteLength": 92}"#, nodes, "[0]", extra).into_bytes();
+        let mut bin = square_buffer();
+        // chunks are padded to 4 bytes, JSON with spaces and binary data with zeros
+        json.resize(json.len().next_multiple_of(4), b' ');
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut glb = Vec::new();
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(&json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(&bin);
+        let path = dir.join("square.glb");
+        std::fs::write(&path, glb).unwrap();
+
+        let scene = load_gltf(&path, &default_material()).unwrap();
+        assert_eq!(
+            scene.hittables.len(),
+            2,
+            "an untransformed mesh is added as its triangles"
+        );
+        assert!(scene.cameras.is_empty());
+
+        let base_color_at = |x: f32| {
+            let ray = Ray::new(Vec3A::new(x, 0.5, 1.0), Vec3A::NEG_Z, 0.0);
+            let rec = scene.hittables.hit(&ray, 0.001, f32::INFINITY).unwrap();
+            match rec.material.as_ref() {
+                Material::Principled { base_color, .. } => {
+                    base_color.color(rec.u, rec.v, rec.point)
+                }
+                material => panic!("expected a principled material, got {material:?}"),
+            }
+        };
+        assert!(base_color_at(0.25).abs_diff_eq(Color::X, 1e-3));
+        let blue = (128.0f32 / 255.0).powf(2.2);
+        assert!(base_color_at(0.75).abs_diff_eq(Color::new(0.0, 0.0, blue), 1e-3));
+    }
+
+    #[test]
+    fn malformed_files() {
+        let dir = test_dir("malformed");
+        let materials = r#", "materials": [{}]"#;
+
+        let path = dir.join("missing-buffer.gltf");
+        let buffer = r#"{"byteLength": 92, "uri": "missing.bin"}"#;
+        std::fs::write(
+            &path,
+            square_json(buffer, r#"[{"mesh": 0}]"#, "[0]", materials),
+        )
+        .unwrap();
+        assert!(matches!(
+            load_gltf(&path, &default_material()),
+            Err(GltfError::Import { .. })
+        ));
+
+        // indices past the four vertices of the square
+        let mut bytes = square_buffer();
+        bytes[80..82].copy_from_slice(&7u16.to_le_bytes());
+        std::fs::write(dir.join("square.bin"), bytes).unwrap();
+        let path = dir.join("bad-index.gltf");
+        let buffer = r#"{"byteLength": 92, "uri": "square.bin"}"#;
+        std::fs::write(
+            &path,
+            square_json(buffer, r#"[{"mesh": 0}]"#, "[0]", materials),
+        )
+        .unwrap();
+        match load_gltf(&path, &default_material()) {
+            Err(GltfError::Format { message, .. }) => {
+                assert!(message.contains("vertex 7"), "{message}")
+            }
+            Err(why) => panic!("expected a format error, got {why}"),
+            Ok(_) => panic!("expected a format error"),
+        }
+
+        // the default scene doesn't show the mesh
+        let path = dir.join("empty.gltf");
+        std::fs::write(
+            &path,
+            square_json(buffer, r#"[{"mesh": 0}]"#, "[]", materials),
+        )
+        .unwrap();
+        assert!(matches!(
+            load_gltf(&path, &default_material()),
+            Err(GltfError::Format { .. })
+        ));
+    }
+}
@@ -0,0 +1,378 @@
+//! Wavefront OBJ/MTL importer
+//!
+//! Relies on the [tobj] crate for parsing. MTL materials are translated to the closest [Material]:
+//! * a non-black emission (`Ke`) becomes [Material::DiffuseLight]
+//! * transparency (`d` below 1, or illumination models 4, 6, 7, and 9) becomes [Material::Dielectric] using `Ni`
+//! * mirror reflection (illumination models 3, 5, and 8) becomes [Material::Metal] using `Ks`, with roughness from `Ns`
+//! * everything else becomes [Material::Lambertian] using `Kd`, or `map_Kd` through an [ImageMap] if present
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Vec2, Vec3A};
+
+use crate::{
+    color::Color,
+    hittables::{HittableList, TriangleMesh},
+    material::{Ior, Material},
+    textures::{ImageMap, Texture},
+};
+
+/// Errors produced while importing an OBJ file
+#[derive(Debug)]
+pub enum ObjError {
+    /// The OBJ file could not be read or is malformed
+    Obj {
+        /// Path of the OBJ file
+        path: PathBuf,
+        /// The underlying parsing error
+        source: tobj::LoadError,
+    },
+    /// A material library referenced by the OBJ file could not be read or is malformed
+    Mtl {
+        /// Path of the MTL file
+        path: PathBuf,
+        /// The underlying parsing error
+        source: tobj::LoadError,
+    },
+    /// A texture referenced by a material could not be loaded
+    Texture {
+        /// Name of the material using the texture
+        material: String,
+        /// Path of the texture image
+        path: PathBuf,
+        /// The underlying image error
+        source: image::ImageError,
+    },
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Obj { path, source } => {
+                write!(f, "failed to load OBJ file {}: {source}", path.display())
+            }
+            ObjError::Mtl { path, source } => {
+                write!(f, "failed to load MTL file {}: {source}", path.display())
+            }
+            ObjError::Texture {
+                material,
+                path,
+                source,
+            } => write!(
+                f,
+                "failed to load texture {} of material `{material}`: {source}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Obj { source, .. } | ObjError::Mtl { source, .. } => Some(source),
+            ObjError::Texture { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Returns the paths of the material libraries the OBJ file at `path` refers to, and of their diffuse textures
+pub(crate) fn obj_sources(path: &Path) -> Vec<PathBuf> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let source = std::fs::read_to_string(path).unwrap_or_default();
+    let mut sources = Vec::new();
+    for line in source.lines() {
+        let Some(libraries) = line.trim().strip_prefix("mtllib") else {
+            continue;
+        };
+        for library in libraries.split_whitespace() {
+            let mtl_path = base_dir.join(library);
+            let materials = tobj::load_mtl(&mtl_path).map_or_else(|_| Vec::new(), |(mtls, _)| mtls);
+            sources.push(mtl_path);
+            sources.extend(
+                materials
+                    .iter()
+                    .filter_map(|mtl| mtl.diffuse_texture.as_ref())
+                    .map(|texture| base_dir.join(texture)),
+            );
+        }
+    }
+    sources
+}
+
+/// Loads the OBJ file at `path`, along with its material libraries and textures.
+///
+/// Faces are triangulated, and each model in the file becomes a [TriangleMesh].
+/// Models without a material use `default_material`.
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    default_material: &Arc<Material>,
+) -> Result<HittableList, ObjError> {
+    let path = path.as_ref();
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+    };
+
+    // tobj doesn't report which material library failed, so keep track of it here
+    let mtl_error = RefCell::new(None);
+    let mtl_loader = |mtl_path: &Path| {
+        let mtl_path = base_dir.join(mtl_path);
+        tobj::load_mtl(&mtl_path).inspect_err(|&why| {
+            mtl_error.borrow_mut().get_or_insert((mtl_path, why));
+        })
+    };
+
+    let file = std::fs::File::open(path).map_err(|_| ObjError::Obj {
+        path: path.to_path_buf(),
+        source: tobj::LoadError::OpenFileFailed,
+    })?;
+    let (models, mtl_result) =
+        tobj::load_obj_buf(&mut std::io::BufReader::new(file), &options, mtl_loader).map_err(
+            |source| ObjError::Obj {
+                path: path.to_path_buf(),
+                source,
+            },
+        )?;
+
+    if let Some((path, source)) = mtl_error.into_inner() {
+        return Err(ObjError::Mtl { path, source });
+    }
+    let mtl_materials = mtl_result.map_err(|source| ObjError::Mtl {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let materials = mtl_materials
+        .iter()
+        .map(|mtl| translate_material(mtl, base_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut hittables = HittableList::new();
+    for model in models {
+        let mesh = model.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let material = mesh
+            .material_id
+            .and_then(|id| materials.get(id))
+            .unwrap_or(default_material);
+
+        let positions = mesh
+            .positions
+            .chunks_exact(3)
+            .map(Vec3A::from_slice)
+            .collect();
+        let indices = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|tri| [tri[0], tri[1], tri[2]])
+            .collect();
+
+        let mut tri_mesh = TriangleMesh::new(positions, indices, material);
+        if !mesh.normals.is_empty() {
+            tri_mesh = tri_mesh.with_normals(
+                mesh.normals
+                    .chunks_exact(3)
+                    .map(Vec3A::from_slice)
+                    .collect(),
+            );
+        }
+        if !mesh.texcoords.is_empty() {
+            tri_mesh = tri_mesh.with_uvs(
+                mesh.texcoords
+                    .chunks_exact(2)
+                    .map(Vec2::from_slice)
+                    .collect(),
+            );
+        }
+
+        hittables.extend(Arc::new(tri_mesh).triangles());
+    }
+
+    Ok(hittables)
+}
+
+/// Translates a MTL material into a [Material]; textures are resolved relative to `base_dir`
+fn translate_material(mtl: &tobj::Material, base_dir: &Path) -> Result<Arc<Material>, ObjError> {
+    let diffuse = Color::from(mtl.diffuse.unwrap_or([0.8; 3]));
+    let specular = Color::from(mtl.specular.unwrap_or([0.0; 3]));
+    let illum = mtl.illumination_model.unwrap_or(2);
+
+    // emission isn't part of the original MTL spec, so tobj leaves it unparsed
+    let emission = mtl
+        .unknown_param
+        .get("Ke")
+        .and_then(|ke| {
+            let channels: Vec<f32> = ke
+                .split_whitespace()
+                .filter_map(|c| c.parse().ok())
+                .collect();
+            (channels.len() == 3).then(|| Color::from_slice(&channels))
+        })
+        .unwrap_or_default();
+
+    let material = if emission.max_element() > 0.0 {
+        // split the emission into a normalized color and its brightness
+        let brightness = emission.max_element();
+        Material::DiffuseLight {
+            albedo: Arc::new(emission / brightness),
+            brightness,
+            two_sided: true,
+            profile: None,
+        }
+    } else if mtl.dissolve.unwrap_or(1.0) < 1.0 || matches!(illum, 4 | 6 | 7 | 9) {
+        Material::Dielectric {
+            refract_index: Ior::Constant(mtl.optical_density.unwrap_or(1.5)),
+        }
+    } else if matches!(illum, 3 | 5 | 8) {
+        // map the Phong exponent onto a roughness in [0, 1]
+        let shininess = mtl.shininess.unwrap_or(0.0).max(0.0);
+        Material::Metal {
+            albedo: Arc::new(specular),
+            roughness: (2.0 / (shininess + 2.0)).sqrt(),
+        }
+    } else {
+        let albedo: Arc<dyn Texture> = match &mtl.diffuse_texture {
+            Some(texture) => {
+                let path = base_dir.join(texture);
+                match ImageMap::open(&path) {
+                    Ok(image_map) => Arc::new(image_map),
+                    Err(source) => {
+                        return Err(ObjError::Texture {
+                            material: mtl.name.clone(),
+                            path,
+                            source,
+                        })
+                    }
+                }
+            }
+            None => Arc::new(diffuse),
+        };
+        Material::Lambertian { albedo }
+    };
+
+    Ok(Arc::new(material))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittables::Hittable;
+
+    const CUBE_OBJ: &str = "mtllib cube.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+usemtl red
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+usemtl lamp
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    /// Writes the given files into a fresh directory, returning the directory
+    fn write_files(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lustre-obj-{test_name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn default_material() -> Arc<Material> {
+        Arc::new(Material::Lambertian {
+            albedo: Arc::new(Color::splat(0.5)),
+        })
+    }
+
+    #[test]
+    fn load_cube() {
+        let mtl = "newmtl red\nKd 0.8 0.1 0.1\nnewmtl lamp\nKd 0 0 0\nKe 4 4 2\n";
+        let dir = write_files("cube", &[("cube.obj", CUBE_OBJ), ("cube.mtl", mtl)]);
+
+        let cube = load_obj(dir.join("cube.obj"), &default_material()).unwrap();
+        assert_eq!(cube.len(), 12, "6 quads should give 12 triangles");
+
+        let bbox = cube.bounding_box(0.0, 1.0).unwrap();
+        assert!(bbox.min.abs_diff_eq(Vec3A::ZERO, 1e-3));
+        assert!(bbox.max.abs_diff_eq(Vec3A::ONE, 1e-3));
+
+        let emissive = cube
+            .iter()
+            .filter_map(|tri| {
+                let ray = crate::ray::Ray::new(Vec3A::splat(0.5), Vec3A::Y, 0.0);
+                tri.hit(&ray, 0.001, f32::INFINITY)
+            })
+            .find_map(|rec| {
+                let ray = crate::ray::Ray::new(Vec3A::splat(0.5), Vec3A::Y, 0.0);
+                rec.material.emit(&ray, &rec)
+            });
+        assert_eq!(emissive, Some(Color::new(4.0, 4.0, 2.0)));
+    }
+
+    #[test]
+    fn malformed_obj() {
+        let obj = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+        let dir = write_files("malformed", &[("bad.obj", obj)]);
+        match load_obj(dir.join("bad.obj"), &default_material()) {
+            Err(ObjError::Obj { source, .. }) => {
+                assert_eq!(source, tobj::LoadError::FaceVertexOutOfBounds)
+            }
+            Err(why) => panic!("expected an OBJ error, got {why}"),
+            Ok(_) => panic!("expected an OBJ error"),
+        }
+    }
+
+    #[test]
+    fn missing_mtl() {
+        let dir = write_files("missing-mtl", &[("cube.obj", CUBE_OBJ)]);
+        match load_obj(dir.join("cube.obj"), &default_material()) {
+            Err(ObjError::Mtl { path, .. }) => assert_eq!(path, dir.join("cube.mtl")),
+            Err(why) => panic!("expected an MTL error, got {why}"),
+            Ok(_) => panic!("expected an MTL error"),
+        }
+    }
+
+    #[test]
+    fn missing_texture() {
+        let mtl = "newmtl red\nmap_Kd missing.png\nnewmtl lamp\nKe 1 1 1\n";
+        let dir = write_files(
+            "missing-texture",
+            &[("cube.obj", CUBE_OBJ), ("cube.mtl", mtl)],
+        );
+        match load_obj(dir.join("cube.obj"), &default_material()) {
+            Err(ObjError::Texture { material, .. }) => assert_eq!(material, "red"),
+            Err(why) => panic!("expected a texture error, got {why}"),
+            Ok(_) => panic!("expected a texture error"),
+        }
+
+        // resumed renders notice changes to the material library and textures too
+        assert_eq!(
+            crate::loaders::model_sources(dir.join("cube.obj")),
+            [
+                dir.join("cube.obj"),
+                dir.join("cube.mtl"),
+                dir.join("missing.png")
+            ]
+        );
+    }
+}
@@ -0,0 +1,652 @@
+//! Stanford PLY importer
+//!
+//! Reads the ASCII, binary little-endian, and binary big-endian encodings of the format.
+//! The `vertex` element provides positions (`x`, `y`, `z`), and optionally
+//! normals (`nx`, `ny`, `nz`), texture coordinates (`u`/`v`, `s`/`t`, or `texture_u`/`texture_v`),
+//! and colors (`red`, `green`, `blue`).
+//! The `face` element provides polygons as a `vertex_indices` (or `vertex_index`) list, which are triangulated as fans.
+//! Other elements and properties are read past and ignored.
+//!
+//! PLY files don't describe materials, so the model uses the given default material,
+//! unless it has vertex colors: then, the whole mesh shares a [Material::Lambertian]
+//! interpolating the colors through a [VertexColors] texture.
+//! See [Paul Bourke's description of the format](https://paulbourke.net/dataformats/ply/).
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Vec2, Vec3A};
+
+use crate::{
+    color::Color,
+    hittables::{HittableList, TriangleMesh},
+    material::Material,
+    textures::VertexColors,
+};
+
+/// Errors produced while importing a PLY file
+#[derive(Debug)]
+pub enum PlyError {
+    /// The PLY file could not be read
+    Io {
+        /// Path of the PLY file
+        path: PathBuf,
+        /// The underlying I/O error
+        source: std::io::Error,
+    },
+    /// The PLY file is malformed, or doesn't describe a triangle mesh
+    Format {
+        /// Path of the PLY file
+        path: PathBuf,
+        /// Description of the problem
+        message: String,
+    },
+}
+
+impl std::fmt::Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlyError::Io { path, source } => {
+                write!(f, "failed to read PLY file {}: {source}", path.display())
+            }
+            PlyError::Format { path, message } => {
+                write!(f, "invalid PLY file {}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io { source, .. } => Some(source),
+            PlyError::Format { .. } => None,
+        }
+    }
+}
+
+/// Returns an error for malformed file contents
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Encodings of a PLY file's body
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// Numeric types of property values
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    /// Parses a type name, in either its original or its sized spelling
+    fn parse(name: &str) -> std::io::Result<Self> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(invalid(format!("unknown property type `{name}`"))),
+        }
+    }
+
+    /// Returns the value that stands for full intensity in a color channel of this type
+    fn color_scale(self) -> f32 {
+        match self {
+            Scalar::U8 => u8::MAX as f32,
+            Scalar::U16 => u16::MAX as f32,
+            Scalar::F32 | Scalar::F64 => 1.0,
+            Scalar::I8 => i8::MAX as f32,
+            Scalar::I16 => i16::MAX as f32,
+            Scalar::I32 => i32::MAX as f32,
+            Scalar::U32 => u32::MAX as f32,
+        }
+    }
+}
+
+/// The type of a property: a single value, or a list of values preceded by their count
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PropertyType {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+/// A named property of each instance of an [Element]
+#[derive(Debug)]
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+/// A kind of data stored in the file, like vertices or faces, along with how many there are
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Returns the position of the property with any of the given names, if it holds single values
+    fn scalar(&self, names: &[&str]) -> Option<(usize, Scalar)> {
+        self.properties
+            .iter()
+            .enumerate()
+            .find_map(|(index, property)| match property.kind {
+                PropertyType::Scalar(scalar) if names.contains(&property.name.as_str()) => {
+                    Some((index, scalar))
+                }
+                _ => None,
+            })
+    }
+}
+
+/// Reads the header, up to and including the `end_header` line
+fn read_header(reader: &mut impl BufRead) -> std::io::Result<(Format, Vec<Element>)> {
+    let mut lines = reader.lines();
+    let mut next_line = || -> std::io::Result<String> {
+        lines
+            .next()
+            .unwrap_or_else(|| Err(invalid("missing `end_header`")))
+    };
+
+    if next_line()?.trim_end() != "ply" {
+        return Err(invalid("not a PLY file"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let line = next_line()?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["comment" | "obj_info", ..] | [] => {}
+            ["format", encoding, _version] => {
+                format = Some(match *encoding {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid(format!("unknown format `{encoding}`"))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid count of element `{name}`")))?,
+                properties: Vec::new(),
+            }),
+            ["property", kind @ .., name] => {
+                let kind = match kind {
+                    [scalar] => PropertyType::Scalar(Scalar::parse(scalar)?),
+                    ["list", count, item] => PropertyType::List {
+                        count: Scalar::parse(count)?,
+                        item: Scalar::parse(item)?,
+                    },
+                    _ => return Err(invalid(format!("invalid property `{line}`"))),
+                };
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("property outside of an element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            _ => return Err(invalid(format!("unexpected header line `{line}`"))),
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid("missing `format`"))?;
+    Ok((format, elements))
+}
+
+/// Source of property values from a file's body
+trait Values {
+    /// Reads the next value, which has the type `scalar`
+    fn next(&mut self, scalar: Scalar) -> std::io::Result<f64>;
+
+    /// Reads all properties of an instance of `element`,
+    /// storing single values in `scalars` and passing lists to `on_list` along with their property's position
+    fn read_instance(
+        &mut self,
+        element: &Element,
+        scalars: &mut [f64],
+        mut on_list: impl FnMut(usize, &[f64]) -> std::io::Result<()>,
+        list: &mut Vec<f64>,
+    ) -> std::io::Result<()> {
+        for (index, property) in element.properties.iter().enumerate() {
+            match property.kind {
+                PropertyType::Scalar(scalar) => scalars[index] = self.next(scalar)?,
+                PropertyType::List { count, item } => {
+                    let count = self.next(count)?;
+                    if count < 0.0 {
+                        return Err(invalid("negative list length"));
+                    }
+                    list.clear();
+                    for _ in 0..count as usize {
+                        list.push(self.next(item)?);
+                    }
+                    on_list(index, list)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Values in the ASCII encoding, separated by whitespace
+struct AsciiValues<'a>(std::str::SplitAsciiWhitespace<'a>);
+
+impl Values for AsciiValues<'_> {
+    fn next(&mut self, _scalar: Scalar) -> std::io::Result<f64> {
+        let token = self.0.next().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "unexpected end of file")
+        })?;
+        token
+            .parse()
+            .map_err(|_| invalid(format!("invalid number `{token}`")))
+    }
+}
+
+/// Values in either binary encoding
+struct BinaryValues<R> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read> BinaryValues<R> {
+    /// Reads the next `N` bytes in little-endian order
+    fn bytes<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> Values for BinaryValues<R> {
+    fn next(&mut self, scalar: Scalar) -> std::io::Result<f64> {
+        Ok(match scalar {
+            Scalar::I8 => i8::from_le_bytes(self.bytes()?) as f64,
+            Scalar::U8 => u8::from_le_bytes(self.bytes()?) as f64,
+            Scalar::I16 => i16::from_le_bytes(self.bytes()?) as f64,
+            Scalar::U16 => u16::from_le_bytes(self.bytes()?) as f64,
+            Scalar::I32 => i32::from_le_bytes(self.bytes()?) as f64,
+            Scalar::U32 => u32::from_le_bytes(self.bytes()?) as f64,
+            Scalar::F32 => f32::from_le_bytes(self.bytes()?) as f64,
+            Scalar::F64 => f64::from_le_bytes(self.bytes()?),
+        })
+    }
+}
+
+/// The vertex attributes and triangles read from a PLY file
+#[derive(Debug, Default)]
+struct PlyMesh {
+    positions: Vec<Vec3A>,
+    normals: Option<Vec<Vec3A>>,
+    uvs: Option<Vec<Vec2>>,
+    colors: Option<Vec<Color>>,
+    indices: Vec<[u32; 3]>,
+}
+
+/// The most instances of an element to reserve memory for up front
+///
+/// Counts come from the header, so a corrupt or truncated file could otherwise claim billions;
+/// larger elements grow as they are actually read.
+const MAX_RESERVED: usize = 1 << 20;
+
+/// Reads the elements of the body, keeping the vertices and faces
+fn read_body(elements: &[Element], values: &mut impl Values) -> std::io::Result<PlyMesh> {
+    let mut mesh = PlyMesh::default();
+    let mut list = Vec::new();
+
+    for element in elements {
+        let mut scalars = vec![0.0; element.properties.len()];
+        match element.name.as_str() {
+            "vertex" => {
+                let find = |names: [&[&str]; 3]| -> Option<[(usize, Scalar); 3]> {
+                    Some([
+                        element.scalar(names[0])?,
+                        element.scalar(names[1])?,
+                        element.scalar(names[2])?,
+                    ])
+                };
+                let position = find([&["x"], &["y"], &["z"]])
+                    .ok_or_else(|| invalid("vertices are missing `x`, `y`, or `z`"))?;
+                let normal = find([&["nx"], &["ny"], &["nz"]]);
+                let color = find([&["red", "r"], &["green", "g"], &["blue", "b"]]);
+                let uv = element
+                    .scalar(&["u", "s", "texture_u", "texture_s"])
+                    .zip(element.scalar(&["v", "t", "texture_v", "texture_t"]));
+
+                let vec3 = |scalars: &[f64], [x, y, z]: [(usize, Scalar); 3]| {
+                    Vec3A::new(
+                        scalars[x.0] as f32,
+                        scalars[y.0] as f32,
+                        scalars[z.0] as f32,
+                    )
+                };
+
+                let reserved = element.count.min(MAX_RESERVED);
+                mesh.positions.reserve(reserved);
+                let mut normals = normal.map(|_| Vec::with_capacity(reserved));
+                let mut colors = color.map(|_| Vec::with_capacity(reserved));
+                let mut uvs = uv.map(|_| Vec::with_capacity(reserved));
+                for _ in 0..element.count {
+                    values.read_instance(element, &mut scalars, |_, _| Ok(()), &mut list)?;
+                    mesh.positions.push(vec3(&scalars, position));
+                    if let (Some(normals), Some(normal)) = (&mut normals, normal) {
+                        normals.push(vec3(&scalars, normal));
+                    }
+                    if let (Some(colors), Some(color)) = (&mut colors, color) {
+                        let scale =
+                            Vec3A::from_array(color.map(|(_, scalar)| scalar.color_scale()));
+                        colors.push(vec3(&scalars, color) / scale);
+                    }
+                    if let (Some(uvs), Some((u, v))) = (&mut uvs, uv) {
+                        uvs.push(Vec2::new(scalars[u.0] as f32, scalars[v.0] as f32));
+                    }
+                }
+                mesh.normals = normals;
+                mesh.colors = colors;
+                mesh.uvs = uvs;
+            }
+            "face" => {
+                let vertex_indices = element
+                    .properties
+                    .iter()
+                    .position(|property| {
+                        matches!(property.kind, PropertyType::List { .. })
+                            && matches!(property.name.as_str(), "vertex_indices" | "vertex_index")
+                    })
+                    .ok_or_else(|| invalid("faces are missing `vertex_indices`"))?;
+
+                mesh.indices.reserve(element.count.min(MAX_RESERVED));
+                for _ in 0..element.count {
+                    values.read_instance(
+                        element,
+                        &mut scalars,
+                        |index, polygon| {
+                            if index != vertex_indices {
+                                return Ok(());
+                            }
+                            if polygon.iter().any(|&i| i < 0.0 || i > u32::MAX as f64) {
+                                return Err(invalid("negative vertex index"));
+                            }
+                            // triangulate the polygon as a fan around its first vertex
+                            for i in 1..polygon.len().saturating_sub(1) {
+                                mesh.indices.push([
+                                    polygon[0] as u32,
+                                    polygon[i] as u32,
+                                    polygon[i + 1] as u32,
+                                ]);
+                            }
+                            Ok(())
+                        },
+                        &mut list,
+                    )?;
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    values.read_instance(element, &mut scalars, |_, _| Ok(()), &mut list)?;
+                }
+            }
+        }
+    }
+
+    let vertex_count = mesh.positions.len();
+    if mesh
+        .indices
+        .iter()
+        .flatten()
+        .any(|&i| i as usize >= vertex_count)
+    {
+        return Err(invalid(format!(
+            "vertex index out of bounds of {vertex_count} vertices"
+        )));
+    }
+    if mesh.indices.is_empty() {
+        return Err(invalid("no faces to render"));
+    }
+
+    Ok(mesh)
+}
+
+/// Reads a PLY file's header and body
+fn read_ply(reader: &mut impl BufRead) -> std::io::Result<PlyMesh> {
+    let (format, elements) = read_header(reader)?;
+    match format {
+        Format::Ascii => {
+            let mut body = String::new();
+            reader.read_to_string(&mut body)?;
+            read_body(&elements, &mut AsciiValues(body.split_ascii_whitespace()))
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => read_body(
+            &elements,
+            &mut BinaryValues {
+                reader,
+                big_endian: format == Format::BinaryBigEndian,
+            },
+        ),
+    }
+}
+
+/// Loads the PLY file at `path` as a list of triangles.
+///
+/// The model uses `default_material`, unless it has vertex colors.
+pub fn load_ply(
+    path: impl AsRef<Path>,
+    default_material: &Arc<Material>,
+) -> Result<HittableList, PlyError> {
+    let path = path.as_ref();
+    let mesh = std::fs::File::open(path)
+        .and_then(|file| read_ply(&mut BufReader::new(file)))
+        .map_err(|source| match source.kind() {
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => {
+                PlyError::Format {
+                    path: path.to_path_buf(),
+                    message: source.to_string(),
+                }
+            }
+            _ => PlyError::Io {
+                path: path.to_path_buf(),
+                source,
+            },
+        })?;
+
+    let PlyMesh {
+        positions,
+        normals,
+        uvs,
+        colors,
+        indices,
+    } = mesh;
+
+    // vertex colors are stored in the mesh, and interpolated by a single texture
+    let material = match colors {
+        Some(_) => Arc::new(Material::Lambertian {
+            albedo: Arc::new(VertexColors),
+        }),
+        None => Arc::clone(default_material),
+    };
+    let mut tri_mesh = TriangleMesh::new(positions, indices, &material);
+    if let Some(normals) = normals {
+        tri_mesh = tri_mesh.with_normals(normals);
+    }
+    if let Some(uvs) = uvs {
+        tri_mesh = tri_mesh.with_uvs(uvs);
+    }
+    if let Some(colors) = colors {
+        tri_mesh = tri_mesh.with_colors(colors);
+    }
+    Ok(Arc::new(tri_mesh).triangles())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittables::Hittable;
+
+    /// A unit square in the xy plane, as a single quad with colored and textured corners
+    const SQUARE_ASCII: &str = "ply
+format ascii 1.0
+comment a unit square
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float u
+property float v
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0 0 0
+1 0 0 255 0 0 2 0
+1 1 0 0 0 255 2 2
+0 1 0 0 0 255 0 2
+4 0 1 2 3
+";
+
+    /// Encodes the same square as binary, with normals, and an extra element to skip
+    fn square_binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut bytes = format!(
+            "ply\nformat {format} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+             property double nx\nproperty double ny\nproperty double nz\n\
+             element edge 1\nproperty list uchar uint vertex_pair\n\
+             element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+        )
+        .into_bytes();
+
+        let mut push = |value: &[u8]| {
+            let mut value = value.to_vec();
+            if big_endian {
+                value.reverse();
+            }
+            bytes.extend(value);
+        };
+        for [x, y] in [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]] {
+            for coordinate in [x, y, 0.0] {
+                push(&coordinate.to_le_bytes());
+            }
+            for coordinate in [0.0f64, 0.0, 1.0] {
+                push(&coordinate.to_le_bytes());
+            }
+        }
+        for list in [&[0u32, 1][..], &[0, 1, 2, 3]] {
+            push(&[list.len() as u8]);
+            for index in list {
+                push(&index.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Writes `contents` to a file named `name` in the temporary directory, returning its path
+    fn write_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lustre-ply-{name}"));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn default_material() -> Arc<Material> {
+        Arc::new(Material::Lambertian {
+            albedo: Arc::new(Color::splat(0.5)),
+        })
+    }
+
+    #[test]
+    fn ascii_vertex_colors() {
+        let path = write_file("ascii.ply", SQUARE_ASCII.as_bytes());
+        let square = load_ply(path, &default_material()).unwrap();
+        assert_eq!(square.len(), 2, "a quad should give 2 triangles");
+
+        // halfway up the square, the colors are mixed evenly
+        let ray = crate::ray::Ray::new(Vec3A::new(0.5, 0.5, 1.0), -Vec3A::Z, 0.0);
+        let hits: Vec<_> = square
+            .iter()
+            .filter_map(|tri| tri.hit(&ray, 0.001, f32::INFINITY))
+            .collect();
+        let rec = &hits[0];
+        let Material::Lambertian { albedo } = rec.material.as_ref() else {
+            panic!("expected a lambertian material");
+        };
+        let color = albedo.color_at(rec);
+        assert!(
+            color.abs_diff_eq(Color::new(0.5, 0.0, 0.5), 1e-3),
+            "{color}"
+        );
+        // the texture coordinates are kept, and both triangles share the material
+        assert!((rec.u - 1.0).abs() < 1e-3 && (rec.v - 1.0).abs() < 1e-3);
+        assert!(Arc::ptr_eq(&hits[0].material, &hits[1].material));
+    }
+
+    #[test]
+    fn binary_encodings() {
+        for big_endian in [false, true] {
+            let path = write_file(
+                &format!("binary-{big_endian}.ply"),
+                &square_binary(big_endian),
+            );
+            let square = load_ply(path, &default_material()).unwrap();
+            assert_eq!(square.len(), 2);
+
+            let bbox = square.bounding_box(0.0, 1.0).unwrap();
+            assert!(bbox.min.abs_diff_eq(Vec3A::ZERO, 1e-3));
+            assert!(bbox.max.abs_diff_eq(Vec3A::new(1.0, 1.0, 0.0), 1e-3));
+        }
+    }
+
+    #[test]
+    fn malformed_files() {
+        let out_of_bounds = SQUARE_ASCII.replace("4 0 1 2 3", "4 0 1 2 4");
+        let binary = square_binary(false);
+        let truncated = &binary[..binary.len() - 10];
+        // the header's counts can't be trusted to allocate memory for
+        let huge = SQUARE_ASCII.replace("element vertex 4", "element vertex 4000000000");
+        for (name, contents) in [
+            ("out-of-bounds.ply", out_of_bounds.as_bytes()),
+            ("truncated.ply", truncated),
+            ("huge-count.ply", huge.as_bytes()),
+            ("not-ply.ply", b"solid cube\n".as_slice()),
+        ] {
+            match load_ply(write_file(name, contents), &default_material()) {
+                Err(PlyError::Format { .. }) => {}
+                Err(why) => panic!("{name}: expected a format error, got {why}"),
+                Ok(_) => panic!("{name}: expected a format error"),
+            }
+        }
+    }
+}
@@ -18,7 +18,13 @@ fn main() {
         rand::rngs::SmallRng::from_entropy()
     };
 
-    let render_context = RenderContext::from_arguments(&cli_args, &mut rng);
+    let render_context = match RenderContext::from_arguments(&cli_args, &mut rng) {
+        Ok(render_context) => render_context,
+        Err(why) => {
+            eprintln!("Failed to set up render: {why}");
+            std::process::exit(1);
+        }
+    };
     let img_buf = render_context.render();
 
     // write image to file
@@ -1,6 +1,9 @@
 //! Implementation of material types
 
-use std::sync::Arc;
+use std::{
+    f32::consts::{FRAC_1_PI, PI},
+    sync::Arc,
+};
 
 use glam::Vec3A;
 use rand::Rng;
@@ -9,9 +12,18 @@ use crate::{
     color::{colors, Color},
     hittables::HitRecord,
     ray::Ray,
+    spectrum::{Wavelengths, REFERENCE_WAVELENGTH},
     textures::Texture,
 };
 
+mod ies;
+mod microfacet;
+mod principled;
+
+pub use ies::IesProfile;
+use microfacet::{fresnel_conductor, fresnel_dielectric, Frame, Ggx};
+use principled::Principled;
+
 /// Returns a reflected ray direction based on the given normal
 ///
 /// Performs the following computation: `v - 2 * v.dot(n) * n`
@@ -28,10 +40,251 @@ fn reflect(v: Vec3A, n: Vec3A) -> Vec3A {
 fn refract(uv: Vec3A, n: Vec3A, eta_ratio: f32) -> Vec3A {
     let cos_theta = (-uv).dot(n).min(1.0);
     let r_perp = eta_ratio * (uv + cos_theta * n);
-    let r_para = (1.0 - r_perp.length_squared()).abs().sqrt() * -1.0 * n;
+    let r_para = -(1.0 - r_perp.length_squared()).abs().sqrt() * n;
     r_perp + r_para
 }
 
+/// Returns the probability density, with respect to solid angle,
+/// of the normalized `direction` pointing towards `reflected + radius * v`, where `v` is a uniform unit vector.
+///
+/// The offset points are uniform on a sphere around the unit vector `reflected`;
+/// the density over that sphere's area is converted to solid angle at each intersection with `direction`.
+fn fuzzy_reflection_pdf(reflected: Vec3A, radius: f32, direction: Vec3A) -> f32 {
+    // solve |t * direction - reflected|^2 = radius^2 for t
+    let b = direction.dot(reflected);
+    let discriminant = b * b - (1.0 - radius * radius);
+    // directions sampled at the very edge of the cone may come out slightly negative
+    if discriminant < -1e-6 {
+        return 0.0;
+    }
+
+    // at each intersection, the cosine to the sphere's normal is sqrt(discriminant) / radius
+    let sqrt_d = discriminant.max(1e-8).sqrt();
+    [b - sqrt_d, b + sqrt_d]
+        .into_iter()
+        .filter(|&t| t > 0.0)
+        .map(|t| t * t / (4.0 * PI * radius * sqrt_d))
+        .sum()
+}
+
+/// The complex index of refraction `eta + i k` of a conductor, per color channel
+///
+/// The presets are sampled at roughly 650, 550, and 450 nm from measured spectral data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexIor {
+    /// The real part, the ratio of the speed of light in vacuum to that in the conductor
+    pub eta: Color,
+    /// The imaginary part, the extinction coefficient
+    pub k: Color,
+}
+
+impl ComplexIor {
+    /// Gold (Au)
+    pub const GOLD: Self = Self::new(
+        Color::new(0.143, 0.374, 1.442),
+        Color::new(3.983, 2.385, 1.603),
+    );
+    /// Copper (Cu)
+    pub const COPPER: Self = Self::new(
+        Color::new(0.200, 0.924, 1.102),
+        Color::new(3.912, 2.452, 2.142),
+    );
+    /// Aluminium (Al)
+    pub const ALUMINIUM: Self = Self::new(
+        Color::new(1.657, 0.880, 0.521),
+        Color::new(9.224, 6.270, 4.837),
+    );
+    /// Silver (Ag)
+    pub const SILVER: Self = Self::new(
+        Color::new(0.155, 0.117, 0.138),
+        Color::new(4.828, 3.122, 2.147),
+    );
+
+    /// Names of the presets, as accepted by [ComplexIor::preset]
+    pub const PRESETS: [&'static str; 4] = ["gold", "copper", "aluminium", "silver"];
+
+    /// Creates a new ComplexIor.
+    pub const fn new(eta: Color, k: Color) -> Self {
+        Self { eta, k }
+    }
+
+    /// Returns the preset of the given metal's name, if any
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "gold" => Some(Self::GOLD),
+            "copper" => Some(Self::COPPER),
+            "aluminium" | "aluminum" => Some(Self::ALUMINIUM),
+            "silver" => Some(Self::SILVER),
+            _ => None,
+        }
+    }
+
+    /// Returns the index of refraction as seen by `ray`, interpolated at its wavelengths in spectral mode
+    fn seen_by(&self, ray: &Ray) -> Self {
+        match ray.wavelengths {
+            Some(wavelengths) => Self::new(
+                wavelengths.interpolate(self.eta),
+                wavelengths.interpolate(self.k),
+            ),
+            None => *self,
+        }
+    }
+}
+
+/// The refractive index of a dielectric, which may vary with the wavelength of light
+///
+/// In spectral mode, wavelength-dependent indices disperse light into its colors;
+/// otherwise, they are evaluated at [REFERENCE_WAVELENGTH].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    /// The same index at all wavelengths
+    Constant(f32),
+    /// Cauchy's equation `a + b / λ²`, with the wavelength `λ` in micrometers
+    Cauchy { a: f32, b: f32 },
+    /// The Sellmeier equation `n² = 1 + Σ b λ² / (λ² - c)`, with the wavelength `λ` in micrometers
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    /// Borosilicate crown glass (N-BK7), the most common optical glass
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.039_612, 0.231_792_3, 1.010_469_5],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+    /// Fused silica (SiO2)
+    pub const FUSED_SILICA: Self = Self::Sellmeier {
+        b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+        c: [0.004_679_148, 0.013_512_063, 97.934],
+    };
+    /// Diamond (C), known for its strong dispersion
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    /// Names of the presets, as accepted by [Ior::preset]
+    pub const PRESETS: [&'static str; 3] = ["bk7", "fused_silica", "diamond"];
+
+    /// Returns the preset of the given material's name, if any
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "bk7" => Some(Self::BK7),
+            "fused_silica" => Some(Self::FUSED_SILICA),
+            "diamond" => Some(Self::DIAMOND),
+            _ => None,
+        }
+    }
+
+    /// Returns the refractive index at `wavelength` nanometers
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let micrometers = wavelength * 1e-3;
+        let lambda2 = micrometers * micrometers;
+        match self {
+            Ior::Constant(index) => *index,
+            Ior::Cauchy { a, b } => a + b / lambda2,
+            Ior::Sellmeier { b, c } => {
+                let terms: f32 = b
+                    .iter()
+                    .zip(c)
+                    .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                    .sum();
+                (1.0 + terms).sqrt()
+            }
+        }
+    }
+
+    /// Returns whether the index varies with wavelength
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+
+    /// Returns the index as seen by `ray`,
+    /// along with the wavelengths the ray continues with after refracting and the weight that goes with them
+    ///
+    /// Since refraction through a dispersive index sends each wavelength into its own direction,
+    /// spectral rays continue with only their hero wavelength.
+    fn seen_by(&self, ray: &Ray) -> (f32, Option<Wavelengths>, Color) {
+        match ray.wavelengths {
+            Some(wavelengths) if self.is_dispersive() => {
+                let (remaining, weight) = wavelengths.terminate_secondary();
+                (self.at(wavelengths.hero()), Some(remaining), weight)
+            }
+            _ => (
+                self.at(REFERENCE_WAVELENGTH),
+                ray.wavelengths,
+                colors::WHITE,
+            ),
+        }
+    }
+}
+
+impl From<f32> for Ior {
+    fn from(index: f32) -> Self {
+        Ior::Constant(index)
+    }
+}
+
+/// Returns the BSDF (including the cosine term) and sampling density of a rough dielectric
+/// for the directions `wo` and `wi` in the local shading frame
+///
+/// `eta` is the ratio of the refractive index beyond the surface to the one in front of it.
+/// See [Walter et al.'s "Microfacet Models for Refraction through Rough Surfaces"](https://www.graphics.cornell.edu/~bjw/microfacetbsdf.pdf).
+fn rough_dielectric(ggx: &Ggx, eta: f32, tint: Color, wo: Vec3A, wi: Vec3A) -> (Color, f32) {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return (colors::BLACK, 0.0);
+    }
+
+    // the generalized half vector, facing the same side as the normal
+    let reflected = wi.z > 0.0;
+    let wh = if reflected { wo + wi } else { wo + wi * eta }.normalize_or_zero();
+    let wh = if wh.z < 0.0 { -wh } else { wh };
+
+    // discard microfacets that face away from either direction
+    if wh == Vec3A::ZERO || wh.dot(wo) <= 0.0 || wh.dot(wi) * wi.z <= 0.0 {
+        return (colors::BLACK, 0.0);
+    }
+
+    let fresnel = fresnel_dielectric(wo.dot(wh), eta);
+    let visible_pdf = ggx.visible_normal_pdf(wo, wh);
+    let dg = ggx.d(wh) * ggx.g(wo, wi);
+    if reflected {
+        let bsdf = Color::splat(fresnel * dg / (4.0 * wo.z));
+        (bsdf, fresnel * visible_pdf / (4.0 * wo.dot(wh)))
+    } else {
+        // change of variables from the half vector to the refracted direction
+        let denom = wi.dot(wh) + wo.dot(wh) / eta;
+        let dwh_dwi = wi.dot(wh).abs() / (denom * denom);
+        let transmitted = 1.0 - fresnel;
+        let bsdf = tint * transmitted * dg * wo.dot(wh) * dwh_dwi / wo.z;
+        (bsdf, transmitted * visible_pdf * dwh_dwi)
+    }
+}
+
+/// The total power emitted by a light, in radiometric or photometric units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Power {
+    /// Radiant power, in watts
+    Watts(f32),
+    /// Luminous power, in lumens; converted at the peak luminous efficacy of 683 lumens per watt
+    Lumens(f32),
+}
+
+impl Power {
+    /// Returns the `brightness` of a [Material::DiffuseLight] emitting this power from a surface of the given `area`
+    ///
+    /// Scene units are taken as meters. A white light emits exactly this power;
+    /// lights of other colors emit it scaled by the [luminance](crate::color::luminance) of their color.
+    pub fn brightness(self, area: f32, two_sided: bool) -> f32 {
+        let watts = match self {
+            Power::Watts(watts) => watts,
+            Power::Lumens(lumens) => lumens / 683.0,
+        };
+        // a diffuse emitter's exitant power per unit area is π times its radiance, from each emitting side
+        let sides = if two_sided { 2.0 } else { 1.0 };
+        watts / (PI * area * sides)
+    }
+}
+
 /// Enumeration of possible material types.
 #[derive(Debug)]
 pub enum Material {
@@ -44,12 +297,51 @@ pub enum Material {
         albedo: Arc<dyn Texture>,
         roughness: f32,
     },
+    /// A physically based metal with the given complex index of refraction.
+    ///
+    /// Rough surfaces are modeled with the GGX microfacet distribution and Smith masking-shadowing;
+    /// a roughness of zero makes a perfect mirror.
+    /// See [Walter et al.'s "Microfacet Models for Refraction through Rough Surfaces"](https://www.graphics.cornell.edu/~bjw/microfacetbsdf.pdf) for more information.
+    Conductor { ior: ComplexIor, roughness: f32 },
     /// A glass material that scatters rays based on the given refractive index.
-    Dielectric { refract_index: f32 },
+    Dielectric { refract_index: Ior },
+    /// A glass material with a microfacet surface, for frosted and colored glass.
+    ///
+    /// Uses the exact Fresnel equations and the GGX microfacet distribution;
+    /// a roughness of zero makes smooth glass.
+    /// Transmitted light is multiplied by `tint` on each pass through the surface,
+    /// and absorbed inside the glass following the Beer-Lambert law with the `absorption` coefficients per unit of distance.
+    RoughDielectric {
+        refract_index: Ior,
+        roughness: f32,
+        tint: Arc<dyn Texture>,
+        absorption: Color,
+    },
+    /// A layered material following Disney's principled BSDF, as used by content creation tools.
+    ///
+    /// Blends a diffuse base with sheen, a GGX specular layer, a clear coat, and rough dielectric transmission.
+    /// Every parameter is a texture; scalar parameters are read from the red channel and clamped to `[0, 1]`.
+    /// See [Burley's "Physically Based Shading at Disney"](https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf) for more information.
+    Principled {
+        base_color: Arc<dyn Texture>,
+        metallic: Arc<dyn Texture>,
+        roughness: Arc<dyn Texture>,
+        specular: Arc<dyn Texture>,
+        clearcoat: Arc<dyn Texture>,
+        sheen: Arc<dyn Texture>,
+        transmission: Arc<dyn Texture>,
+        refract_index: f32,
+    },
     /// A material emitting diffuse light
+    ///
+    /// One-sided lights only emit from their front face, see [HitRecord::front_face].
+    /// An IES `profile` makes the emitted light vary by angle from the surface normal, like a real luminaire.
+    /// See [Power] for setting the brightness in physical units.
     DiffuseLight {
         albedo: Arc<dyn Texture>,
         brightness: f32,
+        two_sided: bool,
+        profile: Option<Arc<IesProfile>>,
     },
     /// A material whose properties are the same (uniform) no matter where or how its intersected
     Isotropic { albedo: Arc<dyn Texture> },
@@ -59,9 +351,17 @@ pub enum Material {
 #[derive(Debug)]
 pub struct ScatterRecord {
     /// The resultant ray for subsequent intersections
+    ///
+    /// Rays without wavelengths continue with those of the incoming ray.
     pub ray: Ray,
     /// The attenuation at the point of intersection
+    ///
+    /// For non-delta distributions, this is the BSDF (including the cosine term) divided by `pdf`.
     pub attenuation: Vec3A,
+    /// The probability density, with respect to solid angle, of sampling the direction of `ray`
+    ///
+    /// `None` for delta distributions like mirrors and glass, which only scatter into a single direction.
+    pub pdf: Option<f32>,
 }
 
 impl Material {
@@ -72,6 +372,39 @@ impl Material {
         r0_doubled + (1.0 - r0_doubled) * (1.0 - cosine).powi(5)
     }
 
+    /// Returns the [Principled] BSDF at the point of intersection, if this is a [Material::Principled]
+    fn principled_bsdf(&self, ray: &Ray, rec: &HitRecord) -> Option<Principled> {
+        let Material::Principled {
+            base_color,
+            metallic,
+            roughness,
+            specular,
+            clearcoat,
+            sheen,
+            transmission,
+            refract_index,
+        } = self
+        else {
+            return None;
+        };
+        let scalar = |texture: &Arc<dyn Texture>| texture.color_at(rec).x;
+        let eta = if rec.front_face {
+            *refract_index
+        } else {
+            refract_index.recip()
+        };
+        Some(Principled::new(
+            ray.upsample(base_color.color_at(rec)),
+            scalar(metallic),
+            scalar(roughness),
+            scalar(specular),
+            scalar(clearcoat),
+            scalar(sheen),
+            scalar(transmission),
+            eta,
+        ))
+    }
+
     /// Returns a scattered ray and its attenuation based on the specific material type.
     ///
     /// Returns `None` if the material type computes a lack of scattering
@@ -84,7 +417,8 @@ impl Material {
                 // returns a random unit direction
                 Some(ScatterRecord {
                     ray: Ray::new(rec.point, rand_unit_v, ray.time),
-                    attenuation: albedo.color(rec.u, rec.v, rec.point),
+                    attenuation: ray.upsample(albedo.color_at(rec)),
+                    pdf: Some(0.25 * FRAC_1_PI),
                 })
             }
             Material::Lambertian { albedo } => {
@@ -100,7 +434,8 @@ impl Material {
 
                 Some(ScatterRecord {
                     ray: Ray::new(rec.point, scatter_dir, ray.time),
-                    attenuation: albedo.color(rec.u, rec.v, rec.point),
+                    attenuation: ray.upsample(albedo.color_at(rec)),
+                    pdf: Some(self.pdf(ray, rec, scatter_dir.normalize())),
                 })
             }
             Material::Metal { albedo, roughness } => {
@@ -112,16 +447,52 @@ impl Material {
                     ray.time,
                 );
 
-                (scattered.direction.dot(rec.normal) > 0.0).then_some(ScatterRecord {
+                (scattered.direction.dot(rec.normal) > 0.0).then(|| ScatterRecord {
                     ray: scattered,
-                    attenuation: albedo.color(rec.u, rec.v, rec.point),
+                    attenuation: ray.upsample(albedo.color_at(rec)),
+                    pdf: (*roughness > 0.0)
+                        .then(|| self.pdf(ray, rec, scattered.direction.normalize())),
+                })
+            }
+            Material::Conductor { ior, roughness } => {
+                let ior = ior.seen_by(ray);
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-normed_dir);
+                if wo.z <= 0.0 {
+                    return None;
+                }
+
+                // a perfect mirror
+                if *roughness <= 0.0 {
+                    let wi = Vec3A::new(-wo.x, -wo.y, wo.z);
+                    return Some(ScatterRecord {
+                        ray: Ray::new(rec.point, frame.to_world(wi), ray.time),
+                        attenuation: fresnel_conductor(wo.z, ior.eta, ior.k),
+                        pdf: None,
+                    });
+                }
+
+                let ggx = Ggx::from_roughness(*roughness);
+                let wh = ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+                let wi = microfacet::reflect(wo, wh);
+                // reflected into the surface, the light is absorbed
+                if wi.z <= 0.0 {
+                    return None;
+                }
+
+                let fresnel = fresnel_conductor(wo.dot(wh), ior.eta, ior.k);
+                Some(ScatterRecord {
+                    ray: Ray::new(rec.point, frame.to_world(wi), ray.time),
+                    attenuation: fresnel * ggx.g(wo, wi) / ggx.g1(wo),
+                    pdf: Some(ggx.visible_normal_pdf(wo, wh) / (4.0 * wo.dot(wh))),
                 })
             }
             Material::Dielectric { refract_index } => {
+                let (refract_index, wavelengths, weight) = refract_index.seen_by(ray);
                 let refract_ratio = if rec.front_face {
                     1.0 / refract_index
                 } else {
-                    *refract_index
+                    refract_index
                 };
 
                 let cos_theta = (-normed_dir).dot(rec.normal).min(1.0);
@@ -139,20 +510,249 @@ impl Material {
                 };
 
                 Some(ScatterRecord {
-                    ray: Ray::new(rec.point, direction, ray.time),
-                    attenuation: colors::WHITE,
+                    ray: Ray::new(rec.point, direction, ray.time).with_wavelengths(wavelengths),
+                    attenuation: weight,
+                    pdf: None,
+                })
+            }
+            Material::RoughDielectric {
+                refract_index,
+                roughness,
+                tint,
+                ..
+            } => {
+                let (refract_index, wavelengths, weight) = refract_index.seen_by(ray);
+                let eta = if rec.front_face {
+                    refract_index
+                } else {
+                    refract_index.recip()
+                };
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-normed_dir);
+                if wo.z <= 0.0 {
+                    return None;
+                }
+
+                let ggx = Ggx::from_roughness(*roughness);
+                let wh = if *roughness > 0.0 {
+                    ggx.sample_visible_normal(wo, rng.gen(), rng.gen())
+                } else {
+                    Vec3A::Z
+                };
+
+                // choose between reflection and refraction by their share of the light
+                let fresnel = fresnel_dielectric(wo.dot(wh), eta);
+                let reflected = rng.gen::<f32>() < fresnel;
+                let (wi, attenuation) = if reflected {
+                    (microfacet::reflect(wo, wh), weight)
+                } else {
+                    // refraction always succeeds here, since total internal reflection has a fresnel of 1
+                    let wi = microfacet::refract(wo, wh, eta)?;
+                    (wi, weight * ray.upsample(tint.color_at(rec)))
+                };
+                let scattered =
+                    Ray::new(rec.point, frame.to_world(wi), ray.time).with_wavelengths(wavelengths);
+
+                if *roughness <= 0.0 {
+                    return Some(ScatterRecord {
+                        ray: scattered,
+                        attenuation,
+                        pdf: None,
+                    });
+                }
+
+                // rough surfaces may scatter onto the wrong side
+                if reflected != (wi.z > 0.0) {
+                    return None;
+                }
+                let (_, pdf) = rough_dielectric(&ggx, eta, attenuation, wo, wi);
+                (pdf > 0.0).then(|| ScatterRecord {
+                    ray: scattered,
+                    attenuation: attenuation * ggx.g(wo, wi) / ggx.g1(wo),
+                    pdf: Some(pdf),
+                })
+            }
+            Material::Principled { .. } => {
+                let bsdf = self.principled_bsdf(ray, rec)?;
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-normed_dir);
+                let wi = bsdf.sample(wo, rng)?;
+
+                // weigh the direction by the density of all lobes, not just the sampled one
+                let pdf = bsdf.pdf(wo, wi);
+                (pdf > 0.0).then(|| ScatterRecord {
+                    ray: Ray::new(rec.point, frame.to_world(wi), ray.time),
+                    attenuation: bsdf.eval(wo, wi) / pdf,
+                    pdf: Some(pdf),
                 })
             }
             Material::DiffuseLight { .. } => None,
         }
     }
 
-    /// Returns the emmited color of light from the material, if any.
-    pub fn emit(&self, u: f32, v: f32, point: Vec3A) -> Option<Color> {
+    /// Returns the coefficients with which light is absorbed per unit of distance inside the material, if any
+    pub fn absorption(&self) -> Option<Color> {
+        match self {
+            Material::RoughDielectric { absorption, .. } if *absorption != Color::ZERO => {
+                Some(*absorption)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the material emits light
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Material::DiffuseLight { .. })
+    }
+
+    /// Returns whether [Material::eval] and [Material::pdf] support the material
+    ///
+    /// This excludes materials that don't scatter, and those whose scattering is a delta distribution,
+    /// like [Material::Dielectric], and a [Material::Metal], [Material::Conductor],
+    /// or [Material::RoughDielectric] without roughness.
+    /// Only evaluable materials can gather light through direct light sampling;
+    /// the others rely on [Material::scatter] alone.
+    pub fn is_evaluable(&self) -> bool {
         match self {
-            Material::DiffuseLight { albedo, brightness } => {
-                let color = albedo.color(u, v, point);
-                let val = *brightness * color;
+            Material::Lambertian { .. }
+            | Material::Isotropic { .. }
+            | Material::Principled { .. } => true,
+            Material::Metal { roughness, .. }
+            | Material::Conductor { roughness, .. }
+            | Material::RoughDielectric { roughness, .. } => *roughness > 0.0,
+            _ => false,
+        }
+    }
+
+    /// Returns how much of the light arriving from the normalized `direction` is scattered back along `ray`,
+    /// including the cosine term where applicable.
+    ///
+    /// Returns black for materials that aren't [evaluable](Material::is_evaluable).
+    pub fn eval(&self, ray: &Ray, rec: &HitRecord, direction: Vec3A) -> Color {
+        match self {
+            Material::Lambertian { albedo } | Material::Isotropic { albedo } => {
+                ray.upsample(albedo.color_at(rec)) * self.pdf(ray, rec, direction)
+            }
+            // scattering into the surface is absorbed
+            Material::Metal { albedo, .. } if rec.normal.dot(direction) > 0.0 => {
+                ray.upsample(albedo.color_at(rec)) * self.pdf(ray, rec, direction)
+            }
+            Material::Conductor { ior, roughness } if *roughness > 0.0 => {
+                let ior = ior.seen_by(ray);
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-ray.direction.normalize());
+                let wi = frame.to_local(direction);
+                if wo.z <= 0.0 || wi.z <= 0.0 {
+                    return colors::BLACK;
+                }
+
+                let ggx = Ggx::from_roughness(*roughness);
+                let wh = (wo + wi).normalize();
+                let fresnel = fresnel_conductor(wo.dot(wh), ior.eta, ior.k);
+                fresnel * ggx.d(wh) * ggx.g(wo, wi) / (4.0 * wo.z)
+            }
+            Material::RoughDielectric {
+                refract_index,
+                roughness,
+                tint,
+                ..
+            } if *roughness > 0.0 => {
+                let (refract_index, _, weight) = refract_index.seen_by(ray);
+                let eta = if rec.front_face {
+                    refract_index
+                } else {
+                    refract_index.recip()
+                };
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-ray.direction.normalize());
+                let wi = frame.to_local(direction);
+                let tint = ray.upsample(tint.color_at(rec));
+                weight * rough_dielectric(&Ggx::from_roughness(*roughness), eta, tint, wo, wi).0
+            }
+            Material::Principled { .. } => {
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-ray.direction.normalize());
+                let wi = frame.to_local(direction);
+                self.principled_bsdf(ray, rec)
+                    .map_or(colors::BLACK, |bsdf| bsdf.eval(wo, wi))
+            }
+            _ => colors::BLACK,
+        }
+    }
+
+    /// Returns the probability density, with respect to solid angle,
+    /// of [Material::scatter] choosing the normalized `direction` for the incoming `ray`.
+    ///
+    /// Returns zero for materials that aren't [evaluable](Material::is_evaluable).
+    pub fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: Vec3A) -> f32 {
+        match self {
+            // cosine-weighted hemisphere
+            Material::Lambertian { .. } => rec.normal.dot(direction).max(0.0) * FRAC_1_PI,
+            // uniform sphere
+            Material::Isotropic { .. } => 0.25 * FRAC_1_PI,
+            Material::Metal { roughness, .. } if *roughness > 0.0 => {
+                let reflected = reflect(ray.direction.normalize(), rec.normal);
+                fuzzy_reflection_pdf(reflected, roughness.clamp(0.0, 1.0), direction)
+            }
+            Material::Conductor { roughness, .. } if *roughness > 0.0 => {
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-ray.direction.normalize());
+                let wi = frame.to_local(direction);
+                if wo.z <= 0.0 || wi.z <= 0.0 {
+                    return 0.0;
+                }
+
+                let ggx = Ggx::from_roughness(*roughness);
+                let wh = (wo + wi).normalize();
+                ggx.visible_normal_pdf(wo, wh) / (4.0 * wo.dot(wh))
+            }
+            Material::RoughDielectric {
+                refract_index,
+                roughness,
+                ..
+            } if *roughness > 0.0 => {
+                let (refract_index, ..) = refract_index.seen_by(ray);
+                let eta = if rec.front_face {
+                    refract_index
+                } else {
+                    refract_index.recip()
+                };
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-ray.direction.normalize());
+                let wi = frame.to_local(direction);
+                let ggx = Ggx::from_roughness(*roughness);
+                rough_dielectric(&ggx, eta, colors::WHITE, wo, wi).1
+            }
+            Material::Principled { .. } => {
+                let frame = Frame::new(rec.normal);
+                let wo = frame.to_local(-ray.direction.normalize());
+                let wi = frame.to_local(direction);
+                self.principled_bsdf(ray, rec)
+                    .map_or(0.0, |bsdf| bsdf.pdf(wo, wi))
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Returns the emmited color of light from the material, if any, travelling back along `ray` from the hit at `rec`.
+    pub fn emit(&self, ray: &Ray, rec: &HitRecord) -> Option<Color> {
+        match self {
+            Material::DiffuseLight {
+                albedo,
+                brightness,
+                two_sided,
+                profile,
+            } => {
+                if !two_sided && !rec.front_face {
+                    return None;
+                }
+                let color = albedo.color_at(rec);
+                let mut val = *brightness * color;
+                if let Some(profile) = profile {
+                    // the normal faces the side being looked at, which is the side emitting towards the ray
+                    let direction = Frame::new(rec.normal).to_local(-ray.direction.normalize());
+                    val *= profile.eval(direction);
+                }
                 Some(val)
             }
             // Make emission explicit; nothing emits unless specifically implemented.
@@ -160,3 +760,144 @@ impl Material {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+
+    /// Returns a hit record at the origin, facing up
+    fn hit_record(front_face: bool) -> HitRecord {
+        HitRecord {
+            point: Vec3A::ZERO,
+            normal: Vec3A::Z,
+            material: Arc::new(Material::Dielectric {
+                refract_index: Ior::Constant(1.0),
+            }),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face,
+            vertex_color: None,
+        }
+    }
+
+    #[test]
+    fn metal_pdf() {
+        let metal = Material::Metal {
+            albedo: Arc::new(colors::WHITE),
+            roughness: 0.5,
+        };
+        let rec = hit_record(true);
+        let ray = Ray::new(Vec3A::Z, -Vec3A::Z, 0.0);
+
+        // the expected inverse density is the solid angle of the cone of scattered directions
+        let mut rng = SmallRng::seed_from_u64(0);
+        let samples = 20_000;
+        let inv_pdf_sum: f32 = (0..samples)
+            .map(|_| {
+                let scattered = metal.scatter(&ray, &rec, &mut rng).unwrap();
+                scattered.pdf.unwrap().recip()
+            })
+            .sum();
+        let solid_angle = std::f32::consts::TAU * (1.0 - (1.0f32 - 0.25).sqrt());
+        let estimate = inv_pdf_sum / samples as f32;
+        assert!(
+            (estimate - solid_angle).abs() < 0.02 * solid_angle,
+            "expected {solid_angle}, estimated {estimate}"
+        );
+    }
+
+    #[test]
+    fn one_sided_emission() {
+        let light = |two_sided| Material::DiffuseLight {
+            albedo: Arc::new(colors::WHITE),
+            brightness: 2.0,
+            two_sided,
+            profile: None,
+        };
+        let ray = Ray::new(Vec3A::Z, -Vec3A::Z, 0.0);
+        assert_eq!(
+            light(false).emit(&ray, &hit_record(true)),
+            Some(Color::splat(2.0))
+        );
+        assert_eq!(light(false).emit(&ray, &hit_record(false)), None);
+        assert_eq!(
+            light(true).emit(&ray, &hit_record(false)),
+            Some(Color::splat(2.0))
+        );
+
+        // a square meter emitting pi watts from one side has unit radiance
+        let brightness = Power::Watts(PI).brightness(1.0, false);
+        assert!((brightness - 1.0).abs() < 1e-6);
+        assert_eq!(
+            Power::Lumens(683.0).brightness(1.0, true),
+            brightness / 2.0 / PI
+        );
+    }
+
+    #[test]
+    fn dispersive_indices() {
+        // catalog values at the Fraunhofer d line
+        assert!((Ior::BK7.at(REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-3);
+        assert!((Ior::DIAMOND.at(REFERENCE_WAVELENGTH) - 2.417).abs() < 1e-3);
+        // shorter wavelengths refract more
+        for ior in [Ior::BK7, Ior::Cauchy { a: 1.5, b: 0.004 }] {
+            assert!(ior.at(450.0) > ior.at(650.0));
+        }
+        assert_eq!(Ior::Constant(1.3).at(450.0), 1.3);
+    }
+
+    #[test]
+    fn microfacet_scatter_matches_eval() {
+        let materials = [
+            Material::Conductor {
+                ior: ComplexIor::GOLD,
+                roughness: 0.4,
+            },
+            Material::RoughDielectric {
+                refract_index: Ior::BK7,
+                roughness: 0.3,
+                tint: Arc::new(Color::new(0.9, 0.5, 0.2)),
+                absorption: Color::ZERO,
+            },
+            Material::Principled {
+                base_color: Arc::new(Color::new(0.8, 0.3, 0.1)),
+                metallic: Arc::new(Color::splat(0.3)),
+                roughness: Arc::new(Color::splat(0.4)),
+                specular: Arc::new(Color::splat(0.5)),
+                clearcoat: Arc::new(Color::splat(0.7)),
+                sheen: Arc::new(Color::splat(0.5)),
+                transmission: Arc::new(Color::splat(0.5)),
+                refract_index: 1.5,
+            },
+        ];
+        let ray = Ray::new(Vec3A::new(-1.0, 0.0, 1.0), Vec3A::new(1.0, 0.2, -1.0), 0.0);
+        let spectral_ray = ray.with_wavelengths(Some(Wavelengths::sample(0.3)));
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        // a sampled direction's weight must be the evaluated BSDF over the density
+        for material in materials {
+            for (ray, front_face) in [(ray, true), (ray, false), (spectral_ray, true)] {
+                let rec = hit_record(front_face);
+                for _ in 0..1000 {
+                    let Some(scattered) = material.scatter(&ray, &rec, &mut rng) else {
+                        continue;
+                    };
+                    let direction = scattered.ray.direction.normalize();
+                    let pdf = material.pdf(&ray, &rec, direction);
+                    let bsdf = material.eval(&ray, &rec, direction);
+
+                    let pdf_error = (pdf - scattered.pdf.unwrap()).abs() / pdf;
+                    assert!(pdf_error < 1e-2, "{material:?}: pdf mismatch");
+                    let expected = scattered.attenuation * pdf;
+                    assert!(
+                        bsdf.abs_diff_eq(expected, 1e-2 * expected.max_element().max(1.0)),
+                        "{material:?}: expected {expected}, evaluated {bsdf}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Photometric profiles in the IES LM-63 format, describing how bright a luminaire is in each direction
+//!
+//! See [the LM-63 format description](https://docs.agi32.com/PhotometricToolbox/Content/Open_Tool/iesna_lm-63_format.htm).
+
+use std::{
+    f32::consts::{FRAC_PI_2, PI, TAU},
+    path::Path,
+};
+
+use glam::Vec3A;
+
+/// Errors produced while loading an [IesProfile]
+#[derive(Debug)]
+pub enum IesError {
+    /// The profile could not be read
+    Io(std::io::Error),
+    /// The profile is malformed or unsupported
+    Parse(String),
+}
+
+impl std::fmt::Display for IesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IesError::Io(why) => write!(f, "{why}"),
+            IesError::Parse(message) => write!(f, "invalid IES profile: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for IesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IesError::Io(why) => Some(why),
+            IesError::Parse(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for IesError {
+    fn from(why: std::io::Error) -> Self {
+        IesError::Io(why)
+    }
+}
+
+/// The relative luminous intensity of a luminaire by direction, read from an IES LM-63 file
+///
+/// Only type C photometry, the usual type for architectural luminaires, is supported.
+/// The vertical angle is measured from the luminaire's axis, pointing along the surface normal of emitters using the profile.
+/// The horizontal angle starts at an arbitrary tangent of the surface,
+/// so profiles without rotational symmetry may appear turned.
+///
+/// Values are scaled so that applying the profile to a diffuse emitter doesn't change its total power.
+#[derive(Debug)]
+pub struct IesProfile {
+    /// Vertical angles in radians, increasing
+    vertical: Vec<f32>,
+    /// Horizontal angles in radians, increasing from zero
+    horizontal: Vec<f32>,
+    /// Scaled intensities, with a row of `vertical.len()` values per horizontal angle
+    values: Vec<f32>,
+}
+
+impl IesProfile {
+    /// Loads the profile in the file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, IesError> {
+        let bytes = std::fs::read(path)?;
+        // files are often Latin-1, but only the numbers after the keywords matter
+        Self::parse(&String::from_utf8_lossy(&bytes))
+    }
+
+    /// Parses the contents of an IES LM-63 file
+    pub fn parse(source: &str) -> Result<Self, IesError> {
+        let parse_error = |message: &str| IesError::Parse(message.to_string());
+
+        // skip the keywords, up to the line describing the lamp's tilt
+        let mut lines = source.lines();
+        let tilt = lines
+            .by_ref()
+            .find_map(|line| line.trim().strip_prefix("TILT="))
+            .ok_or_else(|| parse_error("missing `TILT=` line"))?;
+
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f32>()
+                    .map_err(|_| IesError::Parse(format!("expected a number, found `{token}`")))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(parse_error("unexpected end of file")))
+        };
+
+        if tilt.trim() == "INCLUDE" {
+            // the tilt only matters for lamps mounted at an angle; skip its geometry and angle-factor pairs
+            next()?;
+            let pairs = next()? as usize;
+            for _ in 0..2 * pairs {
+                next()?;
+            }
+        }
+
+        let [_lamps, _lumens, multiplier, num_vertical, num_horizontal, photometric_type] =
+            [(); 6].map(|_| next());
+        let (multiplier, photometric_type) = (multiplier?, photometric_type?);
+        let (num_vertical, num_horizontal) = (num_vertical? as usize, num_horizontal? as usize);
+        if photometric_type != 1.0 {
+            return Err(parse_error("only type C photometry is supported"));
+        }
+        if num_vertical < 2 || num_horizontal < 1 {
+            return Err(parse_error("too few angles"));
+        }
+        // units, luminous opening dimensions, ballast factors, and input watts
+        for _ in 0..7 {
+            next()?;
+        }
+
+        let mut angles = |count: usize| -> Result<Vec<f32>, IesError> {
+            let angles = (0..count)
+                .map(|_| next().map(f32::to_radians))
+                .collect::<Result<Vec<_>, _>>()?;
+            match angles.windows(2).all(|pair| pair[0] < pair[1]) {
+                true => Ok(angles),
+                false => Err(parse_error("angles must be increasing")),
+            }
+        };
+        let vertical = angles(num_vertical)?;
+        let horizontal = angles(num_horizontal)?;
+        let values = (0..num_vertical * num_horizontal)
+            .map(|_| next().map(|candela| (candela * multiplier).max(0.0)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut profile = Self {
+            vertical,
+            horizontal,
+            values,
+        };
+        let power = profile.hemisphere_power();
+        if power <= 0.0 {
+            return Err(parse_error(
+                "the luminaire emits no light within 90 degrees of its axis",
+            ));
+        }
+        profile.values.iter_mut().for_each(|value| *value /= power);
+        Ok(profile)
+    }
+
+    /// Returns the relative intensity towards the unit `direction`, given in a frame whose z axis is the luminaire's axis
+    pub fn eval(&self, direction: Vec3A) -> f32 {
+        let vertical = direction.z.clamp(-1.0, 1.0).acos();
+        let horizontal = direction.y.atan2(direction.x).rem_euclid(TAU);
+        self.eval_angles(vertical, horizontal)
+    }
+
+    /// Returns the relative intensity at the given angles, in radians
+    fn eval_angles(&self, vertical: f32, horizontal: f32) -> f32 {
+        // fold the horizontal angle into the range covered by the profile's symmetry
+        let last = *self.horizontal.last().unwrap();
+        let horizontal = if self.horizontal.len() == 1 {
+            0.0
+        } else if last <= FRAC_PI_2 + 1e-3 {
+            let angle = horizontal.min(TAU - horizontal);
+            angle.min(PI - angle)
+        } else if last <= PI + 1e-3 {
+            horizontal.min(TAU - horizontal)
+        } else {
+            horizontal
+        };
+
+        let Some((v0, vt)) = bracket(&self.vertical, vertical) else {
+            return 0.0;
+        };
+        let (h0, h1, ht) = match bracket(&self.horizontal, horizontal) {
+            Some((h0, ht)) => (h0, (h0 + 1).min(self.horizontal.len() - 1), ht),
+            // beyond the last angle of a full circle, wrap around to the first
+            None if horizontal > last && self.horizontal.len() > 1 => {
+                let span = TAU - last + self.horizontal[0];
+                let ht = ((horizontal - last) / span).clamp(0.0, 1.0);
+                (self.horizontal.len() - 1, 0, ht)
+            }
+            None => (0, 0, 0.0),
+        };
+
+        let row = |h: usize| {
+            let values = &self.values[h * self.vertical.len()..];
+            values[v0] + (values[(v0 + 1).min(self.vertical.len() - 1)] - values[v0]) * vt
+        };
+        let (a, b) = (row(h0), row(h1));
+        a + (b - a) * ht
+    }
+
+    /// Returns the cosine-weighted integral of the unscaled profile over the hemisphere around its axis, divided by π
+    fn hemisphere_power(&self) -> f32 {
+        const STEPS: (usize, usize) = (90, 72);
+        let d_vertical = FRAC_PI_2 / STEPS.0 as f32;
+        let d_horizontal = TAU / STEPS.1 as f32;
+        let total: f32 = (0..STEPS.0 * STEPS.1)
+            .map(|idx| {
+                let vertical = ((idx / STEPS.1) as f32 + 0.5) * d_vertical;
+                let horizontal = ((idx % STEPS.1) as f32 + 0.5) * d_horizontal;
+                let (sin, cos) = vertical.sin_cos();
+                self.eval_angles(vertical, horizontal) * cos * sin
+            })
+            .sum();
+        total * d_vertical * d_horizontal / PI
+    }
+}
+
+/// Returns the index of the angle in `angles` at or below `angle`, and how far `angle` is towards the next one
+///
+/// Returns `None` if `angle` is outside the range of `angles`.
+fn bracket(angles: &[f32], angle: f32) -> Option<(usize, f32)> {
+    let last = *angles.last()?;
+    if angle < angles[0] || angle > last {
+        return None;
+    }
+    let idx = angles
+        .partition_point(|&a| a <= angle)
+        .saturating_sub(1)
+        .min(angles.len().saturating_sub(2));
+    match angles.get(idx + 1) {
+        Some(&next) => Some((
+            idx,
+            ((angle - angles[idx]) / (next - angles[idx])).clamp(0.0, 1.0),
+        )),
+        None => Some((idx, 0.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A downlight whose intensity falls off linearly to zero at 60 degrees
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] downlight
+[MANUFAC] lustre
+TILT=NONE
+1 1000 1 5 1 1 2 0.1 0.1 0
+1 1 20
+0 30 60 75 90
+0
+1000 500 0 0 0
+";
+
+    #[test]
+    fn parse_downlight() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        let axis = profile.eval(Vec3A::Z);
+        let side = profile.eval(Vec3A::new(
+            0.0,
+            30f32.to_radians().sin(),
+            30f32.to_radians().cos(),
+        ));
+        assert!(
+            (side / axis - 0.5).abs() < 1e-3,
+            "expected half intensity at 30 degrees, got {}",
+            side / axis
+        );
+        assert_eq!(profile.eval(Vec3A::X), 0.0);
+        assert_eq!(profile.eval(-Vec3A::Z), 0.0);
+
+        // scaled to keep the power of a diffuse emitter
+        assert!((profile.hemisphere_power() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn horizontal_symmetry() {
+        // brighter towards 0 than towards 90 degrees, mirrored into the other quadrants
+        let quadrant = "TILT=NONE\n1 1000 1 2 2 1 2 0 0 0\n1 1 0\n0 90\n0 90\n100 100\n50 50\n";
+        let profile = IesProfile::parse(quadrant).unwrap();
+        let at = |degrees: f32| profile.eval_angles(0.5, degrees.to_radians());
+        assert!(at(0.0) > at(90.0));
+        assert!((at(30.0) - at(150.0)).abs() < 1e-6);
+        assert!((at(30.0) - at(330.0)).abs() < 1e-6);
+
+        assert!(IesProfile::parse("TILT=NONE\n1 1000 1 2 1 2 2 0 0 0\n").is_err());
+        assert!(IesProfile::parse("no tilt").is_err());
+    }
+}
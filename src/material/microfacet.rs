@@ -0,0 +1,259 @@
+//! Microfacet distribution and Fresnel helpers for physically based materials
+//!
+//! Directions are given in a local shading [Frame], where the surface normal is the z axis.
+//! See [Heitz's "Understanding the Masking-Shadowing Function in Microfacet-Based BRDFs"](https://jcgt.org/published/0003/02/03/)
+//! and [Heitz's "Sampling the GGX Distribution of Visible Normals"](https://jcgt.org/published/0007/04/01/).
+
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec3A;
+
+use crate::color::Color;
+
+/// An orthonormal basis around a surface normal
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    tangent: Vec3A,
+    bitangent: Vec3A,
+    normal: Vec3A,
+}
+
+impl Frame {
+    /// Creates a new Frame around the normalized `normal`
+    pub fn new(normal: Vec3A) -> Self {
+        let (tangent, bitangent) = normal.any_orthonormal_pair();
+        Self {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    /// Converts a world space direction into this frame
+    #[inline]
+    pub fn to_local(self, v: Vec3A) -> Vec3A {
+        Vec3A::new(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    /// Converts a direction in this frame into world space
+    #[inline]
+    pub fn to_world(self, v: Vec3A) -> Vec3A {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+}
+
+/// The isotropic GGX (Trowbridge-Reitz) microfacet distribution
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    /// Width of the distribution; the square of the perceptual roughness
+    alpha: f32,
+}
+
+impl Ggx {
+    /// Smallest supported width; narrower distributions are numerically unstable
+    const MIN_ALPHA: f32 = 1e-3;
+
+    /// Creates a new distribution from a perceptual roughness in `[0, 1]`
+    pub fn from_roughness(roughness: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: (roughness * roughness).max(Self::MIN_ALPHA),
+        }
+    }
+
+    /// Returns the density of microfacet normals `wh`
+    pub fn d(&self, wh: Vec3A) -> f32 {
+        let cos2 = wh.z * wh.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denom = cos2 * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denom * denom)
+    }
+
+    /// Returns Smith's auxiliary function for the direction `w`
+    fn lambda(&self, w: Vec3A) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    /// Returns the fraction of microfacets visible from the direction `w`
+    pub fn g1(&self, w: Vec3A) -> f32 {
+        (1.0 + self.lambda(w)).recip()
+    }
+
+    /// Returns the height-correlated fraction of microfacets visible from both `wo` and `wi`
+    pub fn g(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        (1.0 + self.lambda(wo) + self.lambda(wi)).recip()
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible from `wo`
+    ///
+    /// `u1` and `u2` are uniform random numbers in `[0, 1)`.
+    pub fn sample_visible_normal(&self, wo: Vec3A, u1: f32, u2: f32) -> Vec3A {
+        // stretch the view direction onto the hemisphere configuration
+        let vh = Vec3A::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normalize();
+
+        // orthonormal basis around the view direction
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vec3A::new(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3A::X
+        };
+        let t2 = vh.cross(t1);
+
+        // sample the projected area
+        let r = u1.sqrt();
+        let phi = TAU * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // unstretch back onto the ellipsoid configuration
+        Vec3A::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).normalize()
+    }
+
+    /// Returns the density of sampling the microfacet normal `wh` with [Ggx::sample_visible_normal]
+    pub fn visible_normal_pdf(&self, wo: Vec3A, wh: Vec3A) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(wh).max(0.0) * self.d(wh) / wo.z
+    }
+}
+
+/// Returns the Fresnel reflectance of a conductor with the complex index of refraction `eta + i k`,
+/// per color channel, for light arriving at `cos_theta` to the surface normal
+///
+/// See [PBR's section on Fresnel reflectance](https://pbr-book.org/3ed-2018/Reflection_Models/Specular_Reflection_and_Transmission#FresnelReflectance).
+pub fn fresnel_conductor(cos_theta: f32, eta: Color, k: Color) -> Color {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - Vec3A::splat(sin2);
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).max(Vec3A::ZERO).powf(0.5);
+    let a = (0.5 * (a2_plus_b2 + t0)).max(Vec3A::ZERO).powf(0.5);
+
+    let t1 = a2_plus_b2 + Vec3A::splat(cos2);
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + Vec3A::splat(sin2 * sin2);
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
+
+/// Returns the Fresnel reflectance of a dielectric interface for light arriving at `cos_theta`
+/// to the surface normal, where `eta` is the ratio of the refractive index of the far side to the near side
+///
+/// Returns 1 on total internal reflection.
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+/// Refracts `w` through the microfacet normal `wh` on the same side,
+/// where `eta` is the ratio of the refractive index of the far side to the near side
+///
+/// Returns `None` on total internal reflection.
+pub fn refract(w: Vec3A, wh: Vec3A, eta: f32) -> Option<Vec3A> {
+    let cos_i = w.dot(wh);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-w / eta + (cos_i / eta - cos_t) * wh)
+}
+
+/// Reflects `w` about the microfacet normal `wh`
+#[inline]
+pub fn reflect(w: Vec3A, wh: Vec3A) -> Vec3A {
+    2.0 * w.dot(wh) * wh - w
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        // at normal incidence, the reflectance is ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let (eta, k) = (Vec3A::new(0.2, 0.9, 1.1), Vec3A::new(3.9, 2.4, 2.1));
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        let reflectance = fresnel_conductor(1.0, eta, k);
+        assert!(
+            reflectance.abs_diff_eq(expected, 1e-4),
+            "expected {expected}, found {reflectance}"
+        );
+    }
+
+    #[test]
+    fn fresnel_dielectric_limits() {
+        // air to glass at normal incidence
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-5);
+        // glass to air beyond the critical angle
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        // refraction preserves Snell's law
+        let w = Vec3A::new(0.6, 0.0, 0.8);
+        let t = refract(w, Vec3A::Z, 1.5).unwrap();
+        assert!(t.is_normalized());
+        assert!((w.x - 1.5 * -t.x).abs() < 1e-5);
+    }
+
+    #[test]
+    fn visible_normals_match_pdf() {
+        // compare the mean of the sampled normals' cosine with its integral over the density
+        let mut rng = rand::thread_rng();
+        let ggx = Ggx::from_roughness(0.7);
+        let wo = Vec3A::new(0.6, 0.0, 0.8);
+        let samples = 100_000;
+
+        let sampled_mean = (0..samples)
+            .map(|_| ggx.sample_visible_normal(wo, rng.gen(), rng.gen()).z)
+            .sum::<f32>()
+            / samples as f32;
+
+        // integrate over uniformly sampled directions of the upper hemisphere
+        let integrated_mean = (0..samples)
+            .map(|_| {
+                let z: f32 = rng.gen();
+                let phi = TAU * rng.gen::<f32>();
+                let r = (1.0 - z * z).sqrt();
+                let wh = Vec3A::new(r * phi.cos(), r * phi.sin(), z);
+                wh.z * ggx.visible_normal_pdf(wo, wh) * TAU
+            })
+            .sum::<f32>()
+            / samples as f32;
+
+        assert!(
+            (sampled_mean - integrated_mean).abs() < 0.02,
+            "sampled {sampled_mean}, integrated {integrated_mean}"
+        );
+    }
+}
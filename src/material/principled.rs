@@ -0,0 +1,197 @@
+//! The lobes of the principled BSDF, evaluated at a single surface point
+//!
+//! Follows [Burley's "Physically Based Shading at Disney"](https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf)
+//! and its extension to transmission, simplified to GGX-based lobes:
+//! * a retro-reflective diffuse lobe with sheen, fading out with `metallic` and `transmission`
+//! * a specular GGX lobe, tinted by the base color as `metallic` increases
+//! * a rough dielectric transmission lobe, tinted by the base color
+//! * a clear coat, a fixed-roughness GGX lobe with a refractive index of 1.5
+
+use std::f32::consts::FRAC_1_PI;
+
+use glam::Vec3A;
+use rand::Rng;
+
+use super::{
+    microfacet::{self, fresnel_dielectric, Ggx},
+    rough_dielectric,
+};
+use crate::{
+    color::{colors, Color},
+    utils::random::rand_vec3_on_unit_sphere,
+};
+
+/// Roughness of the clear coat lobe
+const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+/// Returns Schlick's Fresnel weight `(1 - cos_theta)^5`
+#[inline]
+fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// The principled BSDF's parameters at a surface point, along with its lobe selection probabilities
+#[derive(Debug)]
+pub struct Principled {
+    base_color: Color,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    clearcoat: f32,
+    sheen: f32,
+    transmission: f32,
+    /// Ratio of the refractive index beyond the surface to the one in front of it
+    eta: f32,
+    /// Probabilities of sampling the diffuse, specular, clear coat, and transmission lobes
+    lobe_probabilities: [f32; 4],
+}
+
+impl Principled {
+    /// Creates a new Principled BSDF from parameters in `[0, 1]`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        base_color: Color,
+        metallic: f32,
+        roughness: f32,
+        specular: f32,
+        clearcoat: f32,
+        sheen: f32,
+        transmission: f32,
+        eta: f32,
+    ) -> Self {
+        let mut bsdf = Self {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            specular: specular.clamp(0.0, 1.0),
+            clearcoat: clearcoat.clamp(0.0, 1.0),
+            sheen: sheen.clamp(0.0, 1.0),
+            transmission: transmission.clamp(0.0, 1.0),
+            eta,
+            lobe_probabilities: [0.0; 4],
+        };
+        // sample each lobe in proportion to its weight
+        let weights = bsdf.lobe_weights();
+        let total: f32 = weights.iter().sum();
+        bsdf.lobe_probabilities = weights.map(|weight| weight / total);
+        bsdf
+    }
+
+    /// Returns the weights of the diffuse, specular, clear coat, and transmission lobes
+    #[inline]
+    fn lobe_weights(&self) -> [f32; 4] {
+        let dielectric = 1.0 - self.metallic;
+        [
+            dielectric * (1.0 - self.transmission),
+            1.0 - dielectric * self.transmission,
+            0.25 * self.clearcoat,
+            dielectric * self.transmission,
+        ]
+    }
+
+    /// Returns the BSDF, including the cosine term, for the directions `wo` and `wi` in the local shading frame
+    pub fn eval(&self, wo: Vec3A, wi: Vec3A) -> Color {
+        if wo.z <= 0.0 {
+            return colors::BLACK;
+        }
+        let [diffuse_w, specular_w, clearcoat_w, transmission_w] = self.lobe_weights();
+        let mut bsdf = colors::BLACK;
+
+        if wi.z > 0.0 {
+            let wh = (wo + wi).normalize();
+            let cos_d = wi.dot(wh);
+
+            if diffuse_w > 0.0 {
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                    * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+                let diffuse = self.base_color * FRAC_1_PI * retro;
+                let sheen = Color::splat(self.sheen * schlick_weight(cos_d));
+                bsdf += diffuse_w * (diffuse + sheen) * wi.z;
+            }
+
+            if specular_w > 0.0 {
+                let ggx = Ggx::from_roughness(self.roughness);
+                let f0 = Color::splat(0.08 * self.specular).lerp(self.base_color, self.metallic);
+                let fresnel = f0 + (colors::WHITE - f0) * schlick_weight(cos_d);
+                bsdf += specular_w * fresnel * ggx.d(wh) * ggx.g(wo, wi) / (4.0 * wo.z);
+            }
+
+            if clearcoat_w > 0.0 {
+                let ggx = Ggx::from_roughness(CLEARCOAT_ROUGHNESS);
+                let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+                bsdf +=
+                    Color::splat(clearcoat_w * fresnel * ggx.d(wh) * ggx.g(wo, wi) / (4.0 * wo.z));
+            }
+        }
+
+        if transmission_w > 0.0 {
+            let ggx = Ggx::from_roughness(self.roughness);
+            bsdf += transmission_w * rough_dielectric(&ggx, self.eta, self.base_color, wo, wi).0;
+        }
+
+        bsdf
+    }
+
+    /// Returns the density of [Principled::sample] choosing `wi` in the local shading frame
+    pub fn pdf(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        let [diffuse_p, specular_p, clearcoat_p, transmission_p] = self.lobe_probabilities;
+        let mut pdf = 0.0;
+
+        if wi.z > 0.0 {
+            let wh = (wo + wi).normalize();
+            pdf += diffuse_p * wi.z * FRAC_1_PI;
+
+            let reflection_pdf =
+                |ggx: Ggx| ggx.visible_normal_pdf(wo, wh) / (4.0 * wo.dot(wh).max(1e-6));
+            if specular_p > 0.0 {
+                pdf += specular_p * reflection_pdf(Ggx::from_roughness(self.roughness));
+            }
+            if clearcoat_p > 0.0 {
+                pdf += clearcoat_p * reflection_pdf(Ggx::from_roughness(CLEARCOAT_ROUGHNESS));
+            }
+        }
+
+        if transmission_p > 0.0 {
+            let ggx = Ggx::from_roughness(self.roughness);
+            pdf += transmission_p * rough_dielectric(&ggx, self.eta, colors::WHITE, wo, wi).1;
+        }
+
+        pdf
+    }
+
+    /// Samples a direction `wi` in the local shading frame, by first choosing one of the lobes
+    pub fn sample(&self, wo: Vec3A, rng: &mut impl Rng) -> Option<Vec3A> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let [diffuse_p, specular_p, clearcoat_p, _] = self.lobe_probabilities;
+        let lobe_choice: f32 = rng.gen();
+
+        let wi = if lobe_choice < diffuse_p {
+            // cosine-weighted hemisphere
+            (Vec3A::Z + rand_vec3_on_unit_sphere(rng)).normalize_or_zero()
+        } else if lobe_choice < diffuse_p + specular_p {
+            let ggx = Ggx::from_roughness(self.roughness);
+            let wh = ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+            microfacet::reflect(wo, wh)
+        } else if lobe_choice < diffuse_p + specular_p + clearcoat_p {
+            let ggx = Ggx::from_roughness(CLEARCOAT_ROUGHNESS);
+            let wh = ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+            microfacet::reflect(wo, wh)
+        } else {
+            let ggx = Ggx::from_roughness(self.roughness);
+            let wh = ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+            if rng.gen::<f32>() < fresnel_dielectric(wo.dot(wh), self.eta) {
+                microfacet::reflect(wo, wh)
+            } else {
+                microfacet::refract(wo, wh, self.eta)?
+            }
+        };
+
+        (wi != Vec3A::ZERO).then_some(wi)
+    }
+}
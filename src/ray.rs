@@ -5,8 +5,10 @@ use rand::Rng;
 
 use crate::{
     color::{colors, Color},
-    hittables::Hittable,
+    hittables::{HitRecord, Hittable},
+    lights::{power_heuristic, LightList},
     material::ScatterRecord,
+    spectrum::Wavelengths,
 };
 
 /// A 3-dimensional Ray
@@ -21,6 +23,12 @@ pub struct Ray {
     pub direction: Vec3A,
     /// "When" the ray was cast
     pub time: f32,
+    /// The wavelengths the ray carries in spectral mode, see [crate::spectrum]
+    pub wavelengths: Option<Wavelengths>,
+    /// Seed for the random decisions made while intersecting the ray, like scattering in participating media
+    ///
+    /// Paths draw it from their sample's generator, so that intersections stay reproducible.
+    pub seed: u64,
 }
 
 impl std::fmt::Display for Ray {
@@ -39,6 +47,32 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelengths: None,
+            seed: 0,
+        }
+    }
+
+    /// Returns this ray carrying the given `wavelengths`
+    pub fn with_wavelengths(self, wavelengths: Option<Wavelengths>) -> Self {
+        Self {
+            wavelengths,
+            ..self
+        }
+    }
+
+    /// Returns this ray carrying the given `seed`
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Returns the linear RGB color `rgb` as seen by this ray
+    ///
+    /// In spectral mode, this is the color's spectrum at the ray's wavelengths; otherwise, it is `rgb` itself.
+    #[inline]
+    pub fn upsample(&self, rgb: Color) -> Color {
+        match self.wavelengths {
+            Some(wavelengths) => wavelengths.upsample(rgb),
+            None => rgb,
         }
     }
 
@@ -49,48 +83,174 @@ impl Ray {
         self.origin + t * self.direction
     }
 
-    /// Returns a [`Color`] value based on the accumulated light and color at the initial intersection point.
+    /// Returns a [`Color`] value based on the light gathered along a path starting with this ray.
+    ///
+    /// The path is traced iteratively, bouncing at most `bounce_depth` times.
+    /// After `roulette_depth` bounces, paths are ended early by Russian roulette,
+    /// with a survival chance based on how much light the path can still carry.
+    /// At surfaces that support it, light from `lights` is gathered directly through shadow rays,
+    /// and combined with light found through scattering using multiple importance sampling.
+    /// Delta lights are only found through shadow rays.
+    /// Rays leaving the scene gather light from the environment of `lights`.
     ///
-    /// Uses `bounce_depth` to limit the amount of recursion when gathering contributions.
+    /// If the ray carries wavelengths, the returned value is spectral radiance at those wavelengths.
     pub fn shade(
         &self,
         hittable: &impl Hittable,
+        lights: &LightList,
         bounce_depth: u16,
-        bg_color: Color,
+        roulette_depth: u16,
         rng: &mut impl Rng,
     ) -> Color {
-        // Limit recursion depth
-        if bounce_depth == 0 {
-            return colors::BLACK;
-        }
+        let mut radiance = colors::BLACK;
+        // how much of the light arriving along `ray` reaches the camera
+        let mut throughput = colors::WHITE;
+        let mut ray = *self;
+        // density with which the previous intersection scattered `ray`,
+        // `None` for camera rays and delta distributions
+        let mut scatter_pdf: Option<f32> = None;
+
+        for depth in 0..bounce_depth {
+            ray.seed = rng.gen();
 
-        // Check for a hit against the `hittable` parameter
-        if let Some(hit_rec) = hittable.hit(self, 0.001, f32::INFINITY) {
-            // need a ref since scatter takes a ref to rec later
+            // Check for a hit against the `hittable` parameter
+            let Some(hit_rec) = hittable.hit(&ray, 0.001, f32::INFINITY) else {
+                // without a hit, functions like a miss shader gathering light from the environment,
+                // weighted against the chance of having sampled it directly
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, lights.environment_pdf(ray.direction)),
+                    None => 1.0,
+                };
+                let environment = lights.environment().radiance(ray.direction);
+                radiance += throughput * ray.upsample(environment) * weight;
+                break;
+            };
             let mat = &hit_rec.material;
-            // gather any emitted light contribution
-            let emit_contrib = match mat.emit(hit_rec.u, hit_rec.v, hit_rec.point) {
-                Some(color) => color,
-                None => colors::BLACK,
+
+            // light travelling through an absorbing material fades following the Beer-Lambert law
+            if let Some(absorption) = mat.absorption().filter(|_| !hit_rec.front_face) {
+                let distance = hit_rec.t * ray.direction.length();
+                throughput *= (-ray.upsample(absorption) * distance).exp();
+            }
+
+            // gather any emitted light contribution,
+            // weighted against the chance of having sampled it directly
+            if let Some(color) = mat.emit(&ray, &hit_rec) {
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, lights.pdf_value(&ray, hit_rec.t)),
+                    None => 1.0,
+                };
+                radiance += throughput * ray.upsample(color) * weight;
+            }
+
+            // gather any direct light contribution
+            if mat.is_evaluable() && !lights.is_empty() {
+                radiance += throughput * ray.sample_direct(hittable, lights, &hit_rec, rng);
+                radiance += throughput * ray.sample_delta(hittable, lights, &hit_rec, rng);
+            }
+
+            // continue the path along the scattered ray, if any
+            let Some(ScatterRecord {
+                ray: scattered,
+                attenuation,
+                pdf,
+            }) = mat.scatter(&ray, &hit_rec, rng)
+            else {
+                break;
             };
+            throughput *= attenuation;
 
-            // gather any scattered light contribution
-            let scatter_contrib = match mat.scatter(self, &hit_rec, rng) {
-                // A successful ray scatter leads to more contributions.
-                Some(ScatterRecord { ray, attenuation }) => {
-                    let bounced = ray.shade(hittable, bounce_depth - 1, bg_color, rng);
-                    attenuation * bounced
+            // Russian roulette: end dim paths early, and make up for it in the surviving ones
+            if depth >= roulette_depth {
+                let survival = throughput.max_element().min(1.0);
+                if rng.gen::<f32>() >= survival {
+                    break;
                 }
-                // Otherwise, we're done
-                None => colors::BLACK,
-            };
+                throughput /= survival;
+            }
+
+            // scattered rays keep the wavelengths of the path, unless the material changed them
+            ray = scattered.with_wavelengths(scattered.wavelengths.or(ray.wavelengths));
+            scatter_pdf = pdf;
+        }
+
+        radiance
+    }
 
-            // both emissives and scattered light contribute, unless they're zeroed
-            // with current materials, one of these will always be zero
-            emit_contrib + scatter_contrib
+    /// Returns the light arriving directly from a randomly sampled light in `lights`
+    /// that is scattered back along this ray at `hit_rec`,
+    /// weighted against the chance of the material scattering towards it
+    fn sample_direct(
+        &self,
+        hittable: &impl Hittable,
+        lights: &LightList,
+        hit_rec: &HitRecord,
+        rng: &mut impl Rng,
+    ) -> Color {
+        let Some(sample) = lights.sample(hit_rec.point, rng) else {
+            return colors::BLACK;
+        };
+
+        let bsdf = hit_rec.material.eval(self, hit_rec, sample.direction);
+        if bsdf == colors::BLACK {
+            return colors::BLACK;
+        }
+
+        // the light is visible if the shadow ray first hits it, and not anything in between
+        let shadow_ray = Ray::new(hit_rec.point, sample.direction, self.time).with_seed(rng.gen());
+        let emitted = if sample.distance.is_infinite() {
+            // the environment is visible if the shadow ray leaves the scene
+            match hittable.hit(&shadow_ray, 0.001, f32::INFINITY) {
+                Some(_) => None,
+                None => Some(lights.environment().radiance(sample.direction)),
+            }
         } else {
-            // without a hit, functions like a miss shader
-            bg_color
+            let near = sample.distance * (1.0 - 1e-3);
+            let far = sample.distance * (1.0 + 1e-3);
+            match hittable.hit(&shadow_ray, 0.001, far) {
+                Some(shadow_rec) if shadow_rec.t >= near => {
+                    shadow_rec.material.emit(&shadow_ray, &shadow_rec)
+                }
+                _ => None,
+            }
+        };
+
+        match emitted {
+            Some(emitted) => {
+                let scatter_pdf = hit_rec.material.pdf(self, hit_rec, sample.direction);
+                let weight = power_heuristic(sample.pdf, scatter_pdf);
+                bsdf * self.upsample(emitted) * weight / sample.pdf
+            }
+            None => colors::BLACK,
+        }
+    }
+
+    /// Returns the light arriving directly from a randomly chosen delta light in `lights`
+    /// that is scattered back along this ray at `hit_rec`
+    ///
+    /// Since the scattered ray can never hit a delta light, this is the only way to gather its light.
+    fn sample_delta(
+        &self,
+        hittable: &impl Hittable,
+        lights: &LightList,
+        hit_rec: &HitRecord,
+        rng: &mut impl Rng,
+    ) -> Color {
+        let Some(sample) = lights.sample_delta(hit_rec.point, rng) else {
+            return colors::BLACK;
+        };
+
+        let bsdf = hit_rec.material.eval(self, hit_rec, sample.direction);
+        if bsdf == colors::BLACK {
+            return colors::BLACK;
+        }
+
+        // the light is visible if the shadow ray doesn't hit anything before reaching it
+        let shadow_ray = Ray::new(hit_rec.point, sample.direction, self.time).with_seed(rng.gen());
+        let far = sample.distance * (1.0 - 1e-3);
+        match hittable.hit(&shadow_ray, 0.001, far) {
+            Some(_) => colors::BLACK,
+            None => bsdf * self.upsample(sample.irradiance),
         }
     }
 }
@@ -1,21 +1,138 @@
 //! Render an image given a [Camera] and a [Hittable].
 
-use glam::Vec3A;
+pub mod state;
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use glam::{Mat3A, Vec3A};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 
 use image::{DynamicImage, ImageFormat};
 
 #[cfg(feature = "parallel")]
-use {indicatif::ParallelProgressIterator, rayon::prelude::*};
-
-#[cfg(not(feature = "parallel"))]
-use indicatif::ProgressIterator;
+use rayon::prelude::*;
 
 use crate::{
-    camera::Camera, color::VecExt, hittables::Hittable, tree::Tree,
-    utils::progress::get_progressbar,
+    camera::Camera,
+    color::{luminance, VecExt},
+    hittables::Hittable,
+    lights::{EnvironmentMap, LightList, Sky},
+    loaders::ModelError,
+    scenes::{file::SceneFileError, Scene},
+    spectrum::Wavelengths,
+    tree::{Tree, TreeConfig},
+    utils::{progress::get_progressbar, random::sample_rng},
 };
 
+pub use state::{RenderState, StateError};
+
+/// Errors produced while setting up a render
+#[derive(Debug)]
+pub enum RenderError {
+    /// The scene file could not be loaded
+    Scene(SceneFileError),
+    /// The model could not be loaded
+    Model(ModelError),
+    /// The render to resume could not be loaded
+    State(StateError),
+    /// The environment map could not be loaded
+    Environment {
+        /// Path of the environment map
+        path: PathBuf,
+        /// The underlying image error
+        source: image::ImageError,
+    },
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::Scene(why) => why.fmt(f),
+            RenderError::Model(why) => why.fmt(f),
+            RenderError::State(why) => why.fmt(f),
+            RenderError::Environment { path, source } => {
+                write!(f, "failed to open {}: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Scene(why) => Some(why),
+            RenderError::Model(why) => Some(why),
+            RenderError::State(why) => Some(why),
+            RenderError::Environment { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<SceneFileError> for RenderError {
+    fn from(why: SceneFileError) -> Self {
+        RenderError::Scene(why)
+    }
+}
+
+impl From<ModelError> for RenderError {
+    fn from(why: ModelError) -> Self {
+        RenderError::Model(why)
+    }
+}
+
+impl From<StateError> for RenderError {
+    fn from(why: StateError) -> Self {
+        RenderError::State(why)
+    }
+}
+
+/// Number of samples a pixel takes before its noise estimate is trusted for adaptive sampling
+const MIN_ADAPTIVE_SAMPLES: u32 = 16;
+
+/// Returns the number of samples per pixel the next pass takes, doubling the `samples_taken` so far
+fn next_pass_samples(samples_taken: u32) -> u32 {
+    samples_taken.max(1)
+}
+
+/// Returns the estimated relative error of a pixel's mean luminance,
+/// given the sum of its `count` samples and the sum of squared luminance differences `m2`
+///
+/// This is the standard error of the mean divided by the mean,
+/// with the mean clamped away from zero so that dark pixels don't sample forever.
+fn relative_error(sum: Vec3A, count: u32, m2: f32) -> f32 {
+    if count < 2 {
+        return f32::INFINITY;
+    }
+    let mean = luminance(sum) / count as f32;
+    // rounding can leave the sum of squared differences of identical samples slightly negative
+    let variance = m2.max(0.0) / (count - 1) as f32;
+    (variance / count as f32).sqrt() / mean.max(1e-2)
+}
+
+/// A rectangular region of the image, rendered as one unit of work
+struct Tile {
+    /// Horizontal pixel range
+    x: std::ops::Range<u32>,
+    /// Vertical pixel range
+    y: std::ops::Range<u32>,
+}
+
+/// The accumulation values of a pixel after rendering a [Tile]
+struct PixelUpdate {
+    x: u32,
+    y: u32,
+    /// Sum of all samples
+    sum: Vec3A,
+    /// Number of samples
+    count: u32,
+    /// Sum of squared differences from the mean luminance
+    m2: f32,
+}
+
 /// Stores render context values such as image dimensions and scene geometry
 pub struct RenderContext {
     /// Width of the output image
@@ -26,25 +143,119 @@ pub struct RenderContext {
     samples_per_pixel: u32,
     /// How many bounces a ray can go down through the scene
     bounce_depth: u16,
+    /// How many bounces a ray goes through before Russian roulette may end it
+    roulette_depth: u16,
+    /// Side length of the square tiles the image is rendered in
+    tile_size: u32,
+    /// Whether camera rays carry wavelengths, see [crate::spectrum]
+    spectral: bool,
     /// The ray-generating Camera
     camera: Camera,
     /// The objects of the scene
     geometry: std::sync::Arc<dyn Hittable>,
+    /// The objects of the scene that are sampled as lights
+    lights: LightList,
     /// Whether or not to output HDR images
     output_hdr: bool,
+    /// Where to write intermediate images to after each pass, if anywhere
+    checkpoint: Option<PathBuf>,
+    /// How long rendering may take before stopping early, if limited
+    time_limit: Option<Duration>,
+    /// The samples taken before this render, if resumed, and the seed to continue with
+    initial_state: RenderState,
+    /// Where to write the render's state to after each pass, if anywhere
+    state_path: Option<PathBuf>,
+    /// The relative error below which pixels stop taking samples, if sampling adaptively
+    noise_threshold: Option<f32>,
+    /// Where to write an image of the number of samples per pixel to, if anywhere
+    sample_map: Option<PathBuf>,
     /// The level of output verbosity
     verbosity: crate::cli::Verbosity,
 }
 
 impl RenderContext {
     /// Creates a new [RenderContext] from the given commandline arguments
-    pub fn from_arguments(args: &crate::cli::Arguments, rng: &mut impl Rng) -> Self {
-        let (geometry, camera, (width, height)) =
-            crate::scenes::get_scene(args.image_width, args.scene, rng);
-        let geometry = Tree::new(
+    ///
+    /// Without a seed in the arguments or a render to resume, `rng` picks the seed.
+    /// Fails if the arguments name a scene file that cannot be loaded,
+    /// or a render to resume that cannot be loaded or belongs to another scene.
+    pub fn from_arguments(
+        args: &crate::cli::Arguments,
+        rng: &mut impl Rng,
+    ) -> Result<Self, RenderError> {
+        let resumed = match &args.resume {
+            Some(path) => Some(RenderState::load(path)?),
+            None => None,
+        };
+        let seed = match (&resumed, args.seed) {
+            (Some(state), _) => state.seed,
+            (None, Some(seed)) => seed,
+            (None, None) => rng.gen(),
+        };
+
+        // generate the scene from the seed alone, so that resumed renders get the same scene
+        let scene_rng = &mut SmallRng::seed_from_u64(seed);
+        let scene = match (&args.scene_file, &args.model) {
+            (Some(path), _) => crate::scenes::get_scene_from_file(args.image_width, path)?,
+            (None, Some(path)) => crate::scenes::get_scene_from_model(args.image_width, path)?,
+            (None, None) => crate::scenes::get_scene(args.image_width, args.scene, scene_rng),
+        };
+        let Scene {
+            geometry,
+            camera,
+            dimensions: (width, height),
+            mut environment,
+            lights: delta_lights,
+        } = scene;
+        let camera = match args.projection {
+            Some(projection) => camera.with_projection(projection.projection(&camera)),
+            None => camera,
+        };
+
+        // an environment map or sky given on the commandline replaces the scene's
+        if let Some(path) = &args.environment {
+            let map =
+                EnvironmentMap::open(path, args.environment_rotation, args.environment_intensity)
+                    .map_err(|source| RenderError::Environment {
+                    path: path.clone(),
+                    source,
+                })?;
+            environment = Arc::new(map);
+        } else if args.sky {
+            let (sin, cos) = args.sun_elevation.to_radians().sin_cos();
+            let sun_direction = Mat3A::from_rotation_y(args.environment_rotation.to_radians())
+                * Vec3A::new(0.0, sin, -cos);
+            environment = Arc::new(Sky::new(
+                sun_direction,
+                args.turbidity,
+                Sky::DEFAULT_GROUND_ALBEDO,
+                args.environment_intensity,
+            ));
+        }
+
+        let scene = RenderState::scene_identity(args);
+        let settings = RenderState::render_settings(args);
+        let initial_state = match (resumed, &args.resume) {
+            (Some(state), Some(path)) => {
+                state.check_matches(path, &scene, &settings, (width, height))?;
+                state
+            }
+            _ => RenderState::new(seed, scene, settings, width, height),
+        };
+
+        let lights = LightList::new(&geometry)
+            .with_environment(environment)
+            .with_delta_lights(delta_lights);
+        let tree_config = args
+            .spatial_splits
+            .map_or_else(TreeConfig::default, |alpha| {
+                TreeConfig::default().with_spatial_splits(alpha)
+            });
+        let geometry = Tree::with_config(
             geometry,
             camera.shutter_open_time,
             camera.shutter_close_time,
+            tree_config,
         );
 
         let output_hdr = matches!(
@@ -52,16 +263,26 @@ impl RenderContext {
             ImageFormat::OpenExr
         );
 
-        Self {
+        Ok(Self {
             image_width: width,
             image_height: height,
             camera,
             geometry: geometry.wrap(),
+            lights,
             bounce_depth: args.bounce_depth,
+            roulette_depth: args.roulette_depth,
             samples_per_pixel: args.samples_per_pixel,
+            tile_size: args.tile_size,
+            spectral: args.spectral,
             output_hdr,
+            checkpoint: args.checkpoint.clone(),
+            time_limit: args.time_limit.map(Duration::from_secs),
+            initial_state,
+            state_path: args.save_state.clone().or_else(|| args.resume.clone()),
+            noise_threshold: args.noise_threshold,
+            sample_map: args.sample_map.clone(),
             verbosity: args.verbosity.clone(),
-        }
+        })
     }
 
     /// Calculates the total color value of the pixel at image coordinates (`x`, `y`)
@@ -76,53 +297,241 @@ impl RenderContext {
         let u = (x as f32 + offset_u) / (self.image_width - 1) as f32;
         let v = ((self.image_height - y) as f32 + offset_v) / (self.image_height - 1) as f32;
 
-        // trace ray
-        self.camera.get_ray(u, v, rng).shade(
+        // pixels that the camera doesn't see through stay black
+        let Some(ray) = self.camera.get_ray(u, v, rng) else {
+            return Vec3A::ZERO;
+        };
+
+        // trace ray, at randomly sampled wavelengths in spectral mode
+        let wavelengths = self.spectral.then(|| Wavelengths::sample(rng.gen()));
+        let radiance = ray.with_wavelengths(wavelengths).shade(
             &self.geometry,
+            &self.lights,
             self.bounce_depth,
-            self.camera.bg_color,
+            self.roulette_depth,
             rng,
-        )
+        );
+
+        match wavelengths {
+            Some(wavelengths) => wavelengths.to_rgb(radiance),
+            None => radiance,
+        }
     }
 
     /// Generates an image from the given scene.
     ///
     /// A scene consists of a [Camera] and some [Hittable].
+    /// Samples are accumulated progressively in passes that double the samples per pixel taken so far, to 1, 2, 4, ...,
+    /// writing an intermediate image after each pass if a checkpoint path was given.
+    /// With a time limit, rendering stops early once the next pass would exceed it.
     /// This functions outputs its progress to the commandline.
     pub fn render(&self) -> DynamicImage {
-        let progress_bar = get_progressbar((self.image_height * self.image_width) as u64)
-            .with_prefix("Generating pixels");
+        let start_time = Instant::now();
+        let progress_bar = get_progressbar(
+            self.image_height as u64 * self.image_width as u64 * self.samples_per_pixel as u64,
+        )
+        .with_prefix("Generating samples");
 
         // stops the progress bar from outputting anything
         if self.verbosity.is_silent() {
             progress_bar.set_draw_target(indicatif::ProgressDrawTarget::hidden());
         }
 
-        // Allocate image buffer
-        // default to f32 to keep hdr data until write time
-        let mut img_buf = image::Rgb32FImage::new(self.image_width, self.image_height);
+        // holds the sum of all samples per pixel, to be averaged at write time
+        let mut state = self.initial_state.clone();
+        let mut samples_taken = state.sample_counts.iter().copied().max().unwrap_or(0);
+        let mut pass_samples = next_pass_samples(samples_taken);
+        progress_bar.inc(state.sample_counts.iter().map(|&count| count as u64).sum());
+
+        while samples_taken < self.samples_per_pixel {
+            let pass_samples_taken = pass_samples.min(self.samples_per_pixel - samples_taken);
+            progress_bar.set_message(format!("{} spp", samples_taken + pass_samples_taken));
+
+            let pass_start_time = Instant::now();
+            let sampled_pixels = self.render_pass(
+                &mut state,
+                samples_taken + pass_samples_taken,
+                &progress_bar,
+            );
+            samples_taken += pass_samples_taken;
+
+            if let Some(state_path) = &self.state_path {
+                if let Err(why) = state.save(state_path) {
+                    progress_bar.println(format!("Failed to write state: {why}"));
+                }
+            }
+            if samples_taken == self.samples_per_pixel || sampled_pixels == 0 {
+                break;
+            }
+
+            if let Some(checkpoint) = &self.checkpoint {
+                if let Err(why) = self.to_image(&state).save(checkpoint) {
+                    progress_bar.println(format!("Failed to write checkpoint: {why}"));
+                }
+            }
+
+            pass_samples = next_pass_samples(samples_taken);
+
+            // estimate the next pass' duration from the current one's
+            if let Some(time_limit) = self.time_limit {
+                let next_pass_estimate = pass_start_time.elapsed().mul_f64(
+                    pass_samples.min(self.samples_per_pixel - samples_taken) as f64
+                        / pass_samples_taken as f64,
+                );
+                if start_time.elapsed() + next_pass_estimate > time_limit {
+                    progress_bar.abandon_with_message(format!(
+                        "Stopped at {samples_taken} spp due to the time limit"
+                    ));
+                    break;
+                }
+            }
+        }
+
+        if let Some(sample_map) = &self.sample_map {
+            if let Err(why) = self.sample_count_image(&state).save(sample_map) {
+                progress_bar.println(format!("Failed to write sample map: {why}"));
+            }
+        }
 
-        // get (parallel) pixel iterator
-        let enumerated = img_buf.enumerate_pixels_mut();
+        self.to_image(&state)
+    }
+
+    /// Takes samples for each pixel in `state` until it has `sample_count` samples
+    ///
+    /// Pixels are rendered in square tiles, in parallel if enabled.
+    /// Each sample is added to its pixel's sum in order, using its own generator,
+    /// so the sums don't depend on how samples are split into passes or pixels into tiles.
+    /// With a noise threshold, pixels stop taking samples once their noise falls below it.
+    /// Returns the number of pixels that were sampled.
+    fn render_pass(
+        &self,
+        state: &mut RenderState,
+        sample_count: u32,
+        progress_bar: &indicatif::ProgressBar,
+    ) -> usize {
+        let tiles = self.tiles();
+
+        // get (parallel) tile iterator
         #[cfg(feature = "parallel")]
-        let enumerated = enumerated.par_bridge();
-
-        // Generate image
-        enumerated
-            .progress_with(progress_bar)
-            .for_each(|(x, y, pixel)| {
-                let rng = &mut SmallRng::from_rng(&mut rand::thread_rng()).unwrap();
-                // take N samples of pixel, sequentially
-                let mut color_v: Vec3A = std::iter::repeat_with(|| self.compute_pixel_v(x, y, rng))
-                    .take(self.samples_per_pixel as usize)
-                    .sum();
-
-                // Account for number of samples
-                color_v /= self.samples_per_pixel as f32;
-
-                // modify pixel with generated color value
-                *pixel = color_v.to_pixel();
-            });
+        let tile_iter = tiles.par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let tile_iter = tiles.iter();
+
+        let state_ref = &*state;
+        let rendered_tiles: Vec<Vec<PixelUpdate>> = tile_iter
+            .map(|tile| self.render_tile(state_ref, tile, sample_count, progress_bar))
+            .collect();
+
+        // write the tiles back into the accumulation buffers
+        let mut sampled_pixels = 0;
+        for update in rendered_tiles.into_iter().flatten() {
+            let index = (update.y * self.image_width + update.x) as usize;
+            state
+                .sample_sums
+                .put_pixel(update.x, update.y, update.sum.to_pixel());
+            state.sample_counts[index] = update.count;
+            state.luminance_m2[index] = update.m2;
+            sampled_pixels += 1;
+        }
+        sampled_pixels
+    }
+
+    /// Returns the tiles covering the image, in row-major order
+    fn tiles(&self) -> Vec<Tile> {
+        let tile_size = self.tile_size;
+        (0..self.image_height)
+            .step_by(tile_size as usize)
+            .flat_map(|y| {
+                (0..self.image_width)
+                    .step_by(tile_size as usize)
+                    .map(move |x| Tile {
+                        x: x..(x + tile_size).min(self.image_width),
+                        y: y..(y + tile_size).min(self.image_height),
+                    })
+            })
+            .collect()
+    }
+
+    /// Takes samples for each pixel of `tile` until it has `sample_count` samples or converges,
+    /// returning the updated accumulation values of the sampled pixels
+    fn render_tile(
+        &self,
+        state: &RenderState,
+        tile: &Tile,
+        sample_count: u32,
+        progress_bar: &indicatif::ProgressBar,
+    ) -> Vec<PixelUpdate> {
+        let mut updates = Vec::with_capacity(tile.x.len() * tile.y.len());
+        for y in tile.y.clone() {
+            for x in tile.x.clone() {
+                let index = (y * self.image_width + x) as usize;
+                let mut sum = Vec3A::from_pixel(*state.sample_sums.get_pixel(x, y));
+                let count = state.sample_counts[index];
+                let mut m2 = state.luminance_m2[index];
+
+                if count >= sample_count || self.is_converged(sum, count, m2) {
+                    continue;
+                }
+
+                let mut new_count = count;
+                while new_count < sample_count {
+                    let rng = &mut sample_rng(state.seed, x, y, new_count);
+                    let sample_v = self.compute_pixel_v(x, y, rng);
+
+                    // update the luminance variance using Welford's algorithm
+                    let lum = luminance(sample_v);
+                    let mean = luminance(sum) / new_count.max(1) as f32;
+                    sum += sample_v;
+                    new_count += 1;
+                    let new_mean = luminance(sum) / new_count as f32;
+                    m2 += (lum - mean) * (lum - new_mean);
+
+                    // stop as soon as the pixel converges, rather than at the end of the pass
+                    if self.is_converged(sum, new_count, m2) {
+                        break;
+                    }
+                }
+
+                progress_bar.inc((new_count - count) as u64);
+                updates.push(PixelUpdate {
+                    x,
+                    y,
+                    sum,
+                    count: new_count,
+                    m2,
+                });
+            }
+        }
+        updates
+    }
+
+    /// Returns whether a pixel with the given accumulation values has converged below the noise threshold, if any
+    fn is_converged(&self, sum: Vec3A, count: u32, m2: f32) -> bool {
+        self.noise_threshold.is_some_and(|threshold| {
+            count >= MIN_ADAPTIVE_SAMPLES && relative_error(sum, count, m2) < threshold
+        })
+    }
+
+    /// Returns a grayscale image of the number of samples taken per pixel,
+    /// relative to the number of samples per pixel
+    fn sample_count_image(&self, state: &RenderState) -> DynamicImage {
+        let (width, height) = state.dimensions();
+        let counts = state
+            .sample_counts
+            .iter()
+            .map(|&count| (count as f32 / self.samples_per_pixel as f32 * u8::MAX as f32) as u8)
+            .collect();
+        DynamicImage::ImageLuma8(image::GrayImage::from_raw(width, height, counts).unwrap())
+    }
+
+    /// Converts the accumulated samples of `state` into the output image
+    fn to_image(&self, state: &RenderState) -> DynamicImage {
+        let mut img_buf = state.sample_sums.clone();
+
+        // Account for number of samples
+        for (pixel, &count) in img_buf.pixels_mut().zip(&state.sample_counts) {
+            *pixel = (Vec3A::from_pixel(*pixel) / count.max(1) as f32).to_pixel();
+        }
 
         if self.output_hdr {
             DynamicImage::ImageRgb32F(img_buf)
@@ -132,7 +541,8 @@ impl RenderContext {
             // see http://poynton.ca/notes/colour_and_gamma/
             let gamma_constant = 2.2;
             for pixel in img_buf.pixels_mut() {
-                let mut color_v = Vec3A::from_pixel(*pixel);
+                // colors outside of the sRGB gamut may have negative channels
+                let mut color_v = Vec3A::from_pixel(*pixel).max(Vec3A::ZERO);
                 color_v = color_v.powf(1.0 / gamma_constant);
                 *pixel = color_v.to_pixel();
             }
@@ -142,3 +552,133 @@ impl RenderContext {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scenes covering surfaces, and participating media which sample their scattering while intersecting rays
+    const SCENES: [&str; 2] = [
+        "--scene=cornell-box",
+        "--scene-file=resources/scenes/cornell-smoke.toml",
+    ];
+
+    fn arguments(
+        scene: &str,
+        samples_per_pixel: u32,
+        state: &std::path::Path,
+    ) -> crate::cli::Arguments {
+        use clap::Parser;
+        crate::cli::Arguments::parse_from([
+            "lustre",
+            "-q",
+            "--width=12",
+            scene,
+            "--seed=7",
+            &format!("--samples={samples_per_pixel}"),
+            &format!("--save-state={}", state.display()),
+        ])
+    }
+
+    #[test]
+    fn resume_matches_uninterrupted() {
+        let rng = &mut SmallRng::seed_from_u64(0);
+        let dir = std::env::temp_dir();
+
+        for scene in SCENES {
+            let full_path = dir.join("lustre-render-full.bin");
+            RenderContext::from_arguments(&arguments(scene, 6, &full_path), rng)
+                .unwrap()
+                .render();
+
+            // interrupt after a few passes, then resume in the same state file
+            let resumed_path = dir.join("lustre-render-resumed.bin");
+            RenderContext::from_arguments(&arguments(scene, 2, &resumed_path), rng)
+                .unwrap()
+                .render();
+            let mut args = arguments(scene, 6, &resumed_path);
+            args.save_state = None;
+            args.resume = Some(resumed_path.clone());
+            RenderContext::from_arguments(&args, rng).unwrap().render();
+
+            let full = RenderState::load(full_path).unwrap();
+            let resumed = RenderState::load(resumed_path).unwrap();
+            assert!(full.sample_counts.iter().all(|&count| count == 6));
+            assert_eq!(
+                full, resumed,
+                "{scene} should resume where it was interrupted"
+            );
+        }
+    }
+
+    #[test]
+    fn passes_double_samples() {
+        let mut samples_taken = 0;
+        let totals: Vec<_> = (0..5)
+            .map(|_| {
+                samples_taken += next_pass_samples(samples_taken);
+                samples_taken
+            })
+            .collect();
+        assert_eq!(totals, [1, 2, 4, 8, 16]);
+    }
+
+    #[test]
+    fn converged_pixels_stop_at_minimum() {
+        use clap::Parser;
+        // every ray misses the sphere behind the camera, so each sample sees the same background
+        let dir = std::env::temp_dir().join("lustre-render-converged");
+        std::fs::create_dir_all(&dir).unwrap();
+        let scene_path = dir.join("scene.toml");
+        std::fs::write(
+            &scene_path,
+            "[camera]\nlook_from = [0.0, 0.0, 0.0]\nlook_at = [0.0, 0.0, -1.0]\nbg_color = [0.5, 0.5, 0.5]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 5.0]\nradius = 1.0\n\
+             material = { type = \"lambertian\", albedo = 0.5 }\n",
+        )
+        .unwrap();
+        let args = crate::cli::Arguments::parse_from([
+            "lustre",
+            "-q",
+            "--width=4",
+            &format!("--scene-file={}", scene_path.display()),
+            "--noise-threshold=0.01",
+        ]);
+        let context =
+            RenderContext::from_arguments(&args, &mut SmallRng::seed_from_u64(0)).unwrap();
+
+        // a single pass well beyond the minimum
+        let tile = Tile { x: 0..4, y: 0..2 };
+        let progress_bar = indicatif::ProgressBar::hidden();
+        let updates = context.render_tile(&context.initial_state, &tile, 100, &progress_bar);
+        assert_eq!(updates.len(), 8);
+        assert!(updates
+            .iter()
+            .all(|update| update.count == MIN_ADAPTIVE_SAMPLES));
+    }
+
+    #[test]
+    fn tiles_are_deterministic() {
+        let rng = &mut SmallRng::seed_from_u64(0);
+        let dir = std::env::temp_dir();
+
+        for scene in SCENES {
+            // tiles of 5 pixels don't evenly divide the 12 by 12 image
+            let states: Vec<_> = [1, 5, 32]
+                .into_iter()
+                .map(|tile_size| {
+                    let path = dir.join(format!("lustre-render-tiles-{tile_size}.bin"));
+                    let mut args = arguments(scene, 3, &path);
+                    args.tile_size = tile_size;
+                    RenderContext::from_arguments(&args, rng).unwrap().render();
+                    RenderState::load(path).unwrap()
+                })
+                .collect();
+
+            assert!(
+                states.windows(2).all(|pair| pair[0] == pair[1]),
+                "{scene} should render the same in any tiles"
+            );
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! On-disk checkpoints of renders in progress
+//!
+//! A [RenderState] holds everything needed to continue a render later:
+//! the per-pixel sums, counts, and luminance variance of the samples taken so far,
+//! the identity of the rendered scene, the render settings that affect its samples,
+//! and the seed used for random number generation.
+//!
+//! The file format is little-endian binary, laid out as follows:
+//! * the magic bytes `LUSTRE\0\0` followed by the format version as a `u32`
+//! * the seed as a `u64`
+//! * the scene identity as a `u32` byte length followed by UTF-8 text
+//! * the render settings, likewise
+//! * the image width and height as `u32`s
+//! * the sample sums as `f32` RGB triples in row-major order
+//! * the sample counts as `u32`s in row-major order
+//! * the sums of squared luminance differences as `f32`s in row-major order
+
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Magic bytes at the start of every state file
+const MAGIC: &[u8; 8] = b"LUSTRE\0\0";
+/// Version of the state file format
+const VERSION: u32 = 3;
+/// Longest scene identity or settings text accepted when reading a state file
+const MAX_TEXT_LEN: u32 = 16 * 1024;
+
+/// Errors produced while reading, writing, or resuming a [RenderState]
+#[derive(Debug)]
+pub enum StateError {
+    /// The state file could not be read or written
+    Io {
+        /// Path of the state file
+        path: PathBuf,
+        /// The underlying I/O error
+        source: std::io::Error,
+    },
+    /// The state file is not a valid state file
+    Format {
+        /// Path of the state file
+        path: PathBuf,
+        /// Description of the problem
+        message: String,
+    },
+    /// The state file belongs to a different render
+    Mismatch {
+        /// Path of the state file
+        path: PathBuf,
+        /// Description of the differing property
+        message: String,
+    },
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::Io { path, source } => {
+                write!(
+                    f,
+                    "failed to access state file {}: {source}",
+                    path.display()
+                )
+            }
+            StateError::Format { path, message } => {
+                write!(f, "invalid state file {}: {message}", path.display())
+            }
+            StateError::Mismatch { path, message } => write!(
+                f,
+                "state file {} belongs to another render: {message}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StateError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The accumulated samples of a render, along with what is needed to continue it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderState {
+    /// The seed all random numbers of the render derive from
+    pub seed: u64,
+    /// Identifies the rendered scene, see [RenderState::scene_identity]
+    pub scene: String,
+    /// Describes the settings that samples depend on, see [RenderState::render_settings]
+    pub settings: String,
+    /// The sum of all samples taken per pixel
+    pub sample_sums: image::Rgb32FImage,
+    /// The number of samples taken per pixel, in row-major order
+    pub sample_counts: Vec<u32>,
+    /// The sum of squared differences from the mean luminance per pixel, in row-major order
+    ///
+    /// Tracked using Welford's algorithm, see
+    /// [Wikipedia's page on computing the variance](https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm).
+    pub luminance_m2: Vec<f32>,
+}
+
+impl RenderState {
+    /// Creates a new RenderState without any samples
+    pub fn new(seed: u64, scene: String, settings: String, width: u32, height: u32) -> Self {
+        Self {
+            seed,
+            scene,
+            settings,
+            sample_sums: image::Rgb32FImage::new(width, height),
+            sample_counts: vec![0; width as usize * height as usize],
+            luminance_m2: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    /// Returns a string identifying the hardcoded scene, scene file, or model rendered with `args`
+    ///
+    /// Files are identified by their path and a hash of their contents, along with the contents of every file they refer to,
+    /// like models and textures, so that edits to any of them are noticed on resuming.
+    pub fn scene_identity(args: &crate::cli::Arguments) -> String {
+        let (path, sources) = match (&args.scene_file, &args.model) {
+            (Some(path), _) => {
+                let referenced = crate::scenes::file::SceneFile::load(path)
+                    .map(|scene_file| scene_file.referenced_files())
+                    .unwrap_or_default();
+                let mut sources = vec![path.clone()];
+                for file in referenced {
+                    sources.extend(crate::loaders::model_sources(file));
+                }
+                (path, sources)
+            }
+            (None, Some(path)) => (path, crate::loaders::model_sources(path)),
+            (None, None) => return format!("{:?}", args.scene),
+        };
+        format!("{}#{:016x}", path.display(), hash_files(&sources))
+    }
+
+    /// Returns a description of the settings in `args` that change the samples of a render, other than the scene
+    ///
+    /// Resuming with other settings would mix incompatible samples.
+    /// Settings that only change how many samples are taken, or where output goes, are left out.
+    pub fn render_settings(args: &crate::cli::Arguments) -> String {
+        let mut settings = format!(
+            "bounce depth {}, roulette depth {}",
+            args.bounce_depth, args.roulette_depth
+        );
+        if args.spectral {
+            settings += ", spectral";
+        }
+        if let Some(projection) = args.projection {
+            settings += &format!(", {projection:?} projection");
+        }
+        if let Some(path) = &args.environment {
+            settings += &format!(
+                ", environment {}#{:016x}",
+                path.display(),
+                hash_files(std::slice::from_ref(path))
+            );
+        } else if args.sky {
+            settings += &format!(
+                ", sky with the sun {} degrees up and turbidity {}",
+                args.sun_elevation, args.turbidity
+            );
+        }
+        if args.environment.is_some() || args.sky {
+            settings += &format!(
+                " rotated by {} degrees at intensity {}",
+                args.environment_rotation, args.environment_intensity
+            );
+        }
+        settings
+    }
+
+    /// Returns the width and height of the render
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.sample_sums.dimensions()
+    }
+
+    /// Checks that this state belongs to a render of `scene` with the given settings and dimensions
+    pub fn check_matches(
+        &self,
+        path: &Path,
+        scene: &str,
+        settings: &str,
+        dimensions: (u32, u32),
+    ) -> Result<(), StateError> {
+        let mismatch = |message| StateError::Mismatch {
+            path: path.to_path_buf(),
+            message,
+        };
+        if self.scene != scene {
+            return Err(mismatch(format!(
+                "rendered scene `{}`, not `{scene}`",
+                self.scene
+            )));
+        }
+        if self.settings != settings {
+            return Err(mismatch(format!(
+                "rendered with {}, not {settings}",
+                self.settings
+            )));
+        }
+        if self.dimensions() != dimensions {
+            let (width, height) = self.dimensions();
+            return Err(mismatch(format!(
+                "rendered at {width}x{height}, not {}x{}",
+                dimensions.0, dimensions.1
+            )));
+        }
+        Ok(())
+    }
+
+    /// Loads a RenderState from the file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|source| StateError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::read_from(&mut BufReader::new(file)).map_err(|source| {
+            if source.kind() == std::io::ErrorKind::InvalidData
+                || source.kind() == std::io::ErrorKind::UnexpectedEof
+            {
+                StateError::Format {
+                    path: path.to_path_buf(),
+                    message: source.to_string(),
+                }
+            } else {
+                StateError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            }
+        })
+    }
+
+    /// Saves this RenderState to the file at `path`
+    ///
+    /// The state is written to a temporary file first,
+    /// so that an interrupted save doesn't destroy an existing state file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        let path = path.as_ref();
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let result = std::fs::File::create(&tmp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            self.write_to(&mut writer)?;
+            writer.into_inner()?.sync_all()?;
+            std::fs::rename(&tmp_path, path)
+        });
+        result.map_err(|source| StateError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Writes this RenderState in the state file format
+    fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let (width, height) = self.dimensions();
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        for text in [&self.scene, &self.settings] {
+            writer.write_all(&(text.len() as u32).to_le_bytes())?;
+            writer.write_all(text.as_bytes())?;
+        }
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        for channel in self.sample_sums.as_raw() {
+            writer.write_all(&channel.to_le_bytes())?;
+        }
+        for count in &self.sample_counts {
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        for m2 in &self.luminance_m2 {
+            writer.write_all(&m2.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a RenderState in the state file format
+    fn read_from(reader: &mut impl Read) -> std::io::Result<Self> {
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a lustre state file"));
+        }
+        if read_u32(reader)? != VERSION {
+            return Err(invalid("unsupported format version"));
+        }
+
+        let mut seed = [0; 8];
+        reader.read_exact(&mut seed)?;
+        let seed = u64::from_le_bytes(seed);
+
+        let scene = read_string(reader)?;
+        let settings = read_string(reader)?;
+
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let pixel_count = (width as usize)
+            .checked_mul(height as usize)
+            .filter(|count| count.checked_mul(3).is_some())
+            .ok_or_else(|| invalid("image dimensions are too large"))?;
+
+        let sums = read_u32s(reader, pixel_count * 3)?
+            .into_iter()
+            .map(f32::from_bits)
+            .collect();
+        let sample_sums = image::Rgb32FImage::from_raw(width, height, sums)
+            .ok_or_else(|| invalid("sample sums don't match the image dimensions"))?;
+        let sample_counts = read_u32s(reader, pixel_count)?;
+        let luminance_m2 = read_u32s(reader, pixel_count)?
+            .into_iter()
+            .map(f32::from_bits)
+            .collect();
+
+        Ok(Self {
+            seed,
+            scene,
+            settings,
+            sample_sums,
+            sample_counts,
+            luminance_m2,
+        })
+    }
+}
+
+/// Reads a little-endian `u32`
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Reads `count` little-endian `u32`s
+///
+/// The buffer only grows as data arrives, so a corrupt count can't allocate more than the file holds.
+fn read_u32s(reader: &mut impl Read, count: usize) -> std::io::Result<Vec<u32>> {
+    let len = count.saturating_mul(4);
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+/// Reads a `u32` byte length followed by as many bytes of UTF-8 text, up to [MAX_TEXT_LEN] bytes
+fn read_string(reader: &mut impl Read) -> std::io::Result<String> {
+    let len = read_u32(reader)?;
+    if len > MAX_TEXT_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("text of {len} bytes is longer than {MAX_TEXT_LEN} bytes"),
+        ));
+    }
+    let mut text = vec![0; len as usize];
+    reader.read_exact(&mut text)?;
+    String::from_utf8(text).map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))
+}
+
+/// Hashes the paths and contents of `files` together; files that can't be read hash like empty files
+fn hash_files(files: &[PathBuf]) -> u64 {
+    let mut bytes = Vec::new();
+    for path in files {
+        bytes.extend(path.to_string_lossy().as_bytes());
+        bytes.push(0);
+        bytes.extend(std::fs::read(path).unwrap_or_default());
+    }
+    fnv1a(&bytes)
+}
+
+/// Hashes `bytes` with the 64-bit FNV-1a hash, which is stable across platforms and Rust versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut state = RenderState::new(42, "CornellBox".into(), "bounce depth 50".into(), 3, 2);
+        state
+            .sample_sums
+            .put_pixel(1, 1, image::Rgb([0.1, 2.5, f32::MAX]));
+        state.sample_counts[4] = 7;
+
+        let path = std::env::temp_dir().join("lustre-state-round-trip.bin");
+        state.save(&path).unwrap();
+        assert_eq!(RenderState::load(&path).unwrap(), state);
+    }
+
+    #[test]
+    fn identity_covers_referenced_files() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join("lustre-state-identity");
+        std::fs::create_dir_all(&dir).unwrap();
+        let scene_path = dir.join("scene.toml");
+        std::fs::write(
+            &scene_path,
+            "[textures.wood]\ntype = \"image\"\npath = \"wood.png\"\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let scene_file = format!("--scene-file={}", scene_path.display());
+            let mut args = vec!["lustre", scene_file.as_str()];
+            args.extend(extra);
+            crate::cli::Arguments::parse_from(args)
+        };
+
+        std::fs::write(dir.join("wood.png"), b"oak").unwrap();
+        let oak = RenderState::scene_identity(&args(&[]));
+        std::fs::write(dir.join("wood.png"), b"pine").unwrap();
+        assert_ne!(RenderState::scene_identity(&args(&[])), oak);
+
+        let settings = RenderState::render_settings(&args(&[]));
+        assert_eq!(
+            RenderState::render_settings(&args(&["-n", "1000"])),
+            settings
+        );
+        assert_ne!(
+            RenderState::render_settings(&args(&["--spectral"])),
+            settings
+        );
+        assert_ne!(
+            RenderState::render_settings(&args(&["--sky", "--turbidity=5"])),
+            RenderState::render_settings(&args(&["--sky"]))
+        );
+    }
+
+    #[test]
+    fn truncated_file() {
+        let path = std::env::temp_dir().join("lustre-state-truncated.bin");
+        std::fs::write(&path, b"LUSTRE\0\0\x01\0").unwrap();
+        match RenderState::load(&path) {
+            Err(StateError::Format { .. }) => {}
+            Err(why) => panic!("expected a format error, got {why}"),
+            Ok(_) => panic!("expected a format error"),
+        }
+    }
+
+    #[test]
+    fn corrupt_lengths() {
+        let mut header = b"LUSTRE\0\0".to_vec();
+        header.extend(VERSION.to_le_bytes());
+        header.extend(42u64.to_le_bytes());
+
+        // a huge scene identity length
+        let mut text = header.clone();
+        text.extend(u32::MAX.to_le_bytes());
+        // empty scene and settings, then huge dimensions with no pixel data behind them
+        let mut pixels = header;
+        pixels.extend(0u32.to_le_bytes());
+        pixels.extend(0u32.to_le_bytes());
+        pixels.extend(u32::MAX.to_le_bytes());
+        pixels.extend(u32::MAX.to_le_bytes());
+
+        let path = std::env::temp_dir().join("lustre-state-corrupt-lengths.bin");
+        for bytes in [text, pixels] {
+            std::fs::write(&path, bytes).unwrap();
+            match RenderState::load(&path) {
+                Err(StateError::Format { .. }) => {}
+                Err(why) => panic!("expected a format error, got {why}"),
+                Ok(_) => panic!("expected a format error"),
+            }
+        }
+    }
+}
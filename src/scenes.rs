@@ -9,11 +9,52 @@ use crate::{
     camera::Camera,
     color::{colors, Color},
     hittables::*,
-    material::Material,
+    lights::{DeltaLight, Environment},
+    material::{Ior, Material},
     textures::*,
     tree::Tree,
 };
 
+pub mod file;
+
+/// A scene ready to be rendered
+pub struct Scene {
+    /// The objects in the scene
+    pub geometry: HittableList,
+    /// The camera looking at the scene
+    pub camera: Camera,
+    /// The image dimensions
+    pub dimensions: (u32, u32),
+    /// The light surrounding the scene, seen by rays that miss every object
+    ///
+    /// Unless the scene defines another one, this is the camera's background color.
+    pub environment: Arc<dyn Environment>,
+    /// The lights in the scene without any geometry, see [DeltaLight]
+    pub lights: Vec<DeltaLight>,
+}
+
+impl Scene {
+    /// Creates a new [Scene] of the given objects, seen by `camera` in a `image_width` pixels wide image
+    ///
+    /// The scene's environment is the camera's background color, and it has no delta lights.
+    pub fn new(geometry: HittableList, camera: Camera, image_width: u32) -> Self {
+        let image_height = (image_width as f32 / camera.aspect_ratio) as u32;
+        Self {
+            geometry,
+            camera,
+            dimensions: (image_width, image_height),
+            environment: Arc::new(camera.bg_color),
+            lights: Vec::new(),
+        }
+    }
+
+    /// Returns this scene with the given delta `lights` added to it
+    pub fn with_lights(mut self, lights: impl IntoIterator<Item = DeltaLight>) -> Self {
+        self.lights.extend(lights);
+        self
+    }
+}
+
 /// Possible hard-coded scenes to choose from.
 #[derive(Debug, Clone, Copy, clap::clap_derive::ValueEnum)]
 pub enum SceneType {
@@ -29,6 +70,8 @@ pub enum SceneType {
     Earth,
     /// [SceneType::TwoPerlinSpheres] with a rectangular diffuse light
     SimpleLight,
+    /// [SceneType::TwoPerlinSpheres] lit by point, spot, and directional lights
+    DeltaLights,
     /// The famous [Cornell Box scene](https://en.wikipedia.org/wiki/Cornell_box)
     CornellBox,
     /// Cornell Box scene from the [definitive Cornell Box data](https://www.graphics.cornell.edu/online/box/data.html)
@@ -72,7 +115,7 @@ pub fn get_camera(scene_type: SceneType) -> Camera {
             aspect_ratio = 3.0 / 2.0;
         }
         SceneType::TwoSpheres | SceneType::TwoPerlinSpheres | SceneType::Earth => (),
-        SceneType::SimpleLight => {
+        SceneType::SimpleLight | SceneType::DeltaLights => {
             bg_color = colors::BLACK;
             look_from = Vec3A::new(26.0, 3.0, 6.0);
             look_at = Vec3A::new(0.0, 2.0, 0.0);
@@ -145,6 +188,7 @@ pub fn get_geometry(
         SceneType::TwoPerlinSpheres => gen_two_perlin_spheres(),
         SceneType::Earth => gen_earth(),
         SceneType::SimpleLight => gen_simple_light(),
+        SceneType::DeltaLights => gen_two_perlin_spheres(),
         SceneType::CornellBox => gen_cornell_box(),
         SceneType::CornellBox2 => gen_cornell_box2(),
         SceneType::RandomLights => gen_emissive_random_spheres(rng),
@@ -154,20 +198,87 @@ pub fn get_geometry(
     }
 }
 
-/// Returns a list of objects ([HittableList]), a [Camera], and the image dimensions.
-pub fn get_scene(
-    image_width: u32,
-    scene_type: SceneType,
-    rng: &mut impl Rng,
-) -> (HittableList, Camera, (u32, u32)) {
+/// Returns the scene-specific [DeltaLight]s, lighting the geometry from [get_geometry]
+pub fn get_lights(scene_type: SceneType) -> Vec<DeltaLight> {
+    match scene_type {
+        SceneType::DeltaLights => gen_delta_lights(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns a [Scene] of a list of objects ([HittableList]), its [DeltaLight]s, a [Camera], and the image dimensions.
+pub fn get_scene(image_width: u32, scene_type: SceneType, rng: &mut impl Rng) -> Scene {
     let cam = get_camera(scene_type);
     let shutter_time = cam.shutter_open_time..cam.shutter_close_time;
     let scene = get_geometry(scene_type, rng, shutter_time);
 
-    let image_height = (image_width as f32 / cam.aspect_ratio) as u32;
-    let dimensions = (image_width, image_height);
+    Scene::new(scene, cam, image_width).with_lights(get_lights(scene_type))
+}
+
+/// Like [get_scene], but loads the objects, lights, [Camera], and environment from the scene file at `path`.
+///
+/// See [file] for the format of scene files.
+pub fn get_scene_from_file(
+    image_width: u32,
+    path: &std::path::Path,
+) -> Result<Scene, file::SceneFileError> {
+    let scene_file = file::SceneFile::load(path)?;
+    let cam = scene_file.camera();
+    let mut scene =
+        Scene::new(scene_file.geometry()?, cam, image_width).with_lights(scene_file.lights());
+    if let Some(environment) = scene_file.environment()? {
+        scene.environment = environment;
+    }
+
+    Ok(scene)
+}
+
+/// Like [get_scene], but shows the model at `path` lit by the sky.
+///
+/// Uses the model's first camera if it defines any, otherwise a [Camera] framing the model's bounding box.
+pub fn get_scene_from_model(
+    image_width: u32,
+    path: &std::path::Path,
+) -> Result<Scene, crate::loaders::ModelError> {
+    let material = Arc::new(Material::Lambertian {
+        albedo: Arc::new(Color::splat(0.73)),
+    });
+    let (scene, cameras) = crate::loaders::load_model_with_cameras(path, &material)?;
+    let sky = Color::new(0.7, 0.8, 1.0);
+
+    let cam = match cameras.first() {
+        Some(&cam) => {
+            let mut cam = cam;
+            cam.bg_color = sky;
+            cam
+        }
+        None => {
+            // look at the bounding sphere of the model from the front and slightly above
+            let (center, radius) = match scene.bounding_box(0.0, 1.0) {
+                Some(bbox) => (
+                    0.5 * (bbox.min + bbox.max),
+                    0.5 * bbox.min.distance(bbox.max),
+                ),
+                None => (Vec3A::ZERO, 1.0),
+            };
+            let vert_fov: f32 = 30.0;
+            let distance = 1.1 * radius / (0.5 * vert_fov.to_radians()).sin();
+            let look_from = center + distance * Vec3A::new(0.4, 0.3, 1.0).normalize();
+            Camera::new(
+                look_from,
+                center,
+                Vec3A::Y,
+                vert_fov,
+                3.0 / 2.0,
+                0.0,
+                distance,
+                0.0..1.0,
+                sky,
+            )
+        }
+    };
 
-    (scene, cam, dimensions)
+    Ok(Scene::new(scene, cam, image_width))
 }
 
 /// Returns a [HittableList] containing a few spheres with unique materials
@@ -178,7 +289,9 @@ fn gen_mat_dev() -> HittableList {
     });
     let ground_sph = Sphere::new(Vec3A::new(0.0, -1000.5, 0.0), 1000.0, &ground_material);
 
-    let mat_left = Arc::new(Material::Dielectric { refract_index: 1.5 });
+    let mat_left = Arc::new(Material::Dielectric {
+        refract_index: Ior::Constant(1.5),
+    });
     let mat_right = Arc::new(Material::Metal {
         albedo: Arc::new(SolidColor::new(0.8, 0.6, 0.2)),
         roughness: 0.1,
@@ -234,7 +347,9 @@ fn gen_random_spheres(rng: &mut impl Rng) -> HittableList {
                     })
                 } else {
                     // glass
-                    Arc::new(Material::Dielectric { refract_index: 1.5 })
+                    Arc::new(Material::Dielectric {
+                        refract_index: Ior::Constant(1.5),
+                    })
                 };
 
                 // make the diffuse spheres moveable
@@ -251,7 +366,9 @@ fn gen_random_spheres(rng: &mut impl Rng) -> HittableList {
     }
 
     // The signature central spheres
-    let mat_1 = Arc::new(Material::Dielectric { refract_index: 1.5 });
+    let mat_1 = Arc::new(Material::Dielectric {
+        refract_index: Ior::Constant(1.5),
+    });
     let mat_2 = Arc::new(Material::Lambertian {
         albedo: Arc::new(SolidColor::new(0.4, 0.2, 0.1)),
     });
@@ -308,6 +425,8 @@ fn gen_simple_light() -> HittableList {
     let diff_light = Arc::new(Material::DiffuseLight {
         albedo: Arc::new(colors::WHITE),
         brightness: 4.0,
+        two_sided: true,
+        profile: None,
     });
 
     let mut world = gen_two_perlin_spheres();
@@ -324,6 +443,25 @@ fn gen_simple_light() -> HittableList {
     world
 }
 
+/// Returns the lights of [SceneType::DeltaLights]: a warm spot light on the small sphere,
+/// a blue point light behind it, and a dim moonlight
+fn gen_delta_lights() -> Vec<DeltaLight> {
+    vec![
+        DeltaLight::spot(
+            Vec3A::new(6.0, 8.0, 4.0),
+            Vec3A::new(0.0, 2.0, 0.0),
+            Color::new(1.0, 0.8, 0.6) * 150.0,
+            15.0,
+            25.0,
+        ),
+        DeltaLight::point(
+            Vec3A::new(-4.0, 3.0, -3.0),
+            Color::new(0.3, 0.5, 1.0) * 20.0,
+        ),
+        DeltaLight::directional(Vec3A::new(-1.0, -2.0, 1.0), Color::splat(0.05)),
+    ]
+}
+
 /// The Cornell Box scene as defined by the Ray Tracing in One Weekend: The Next Week
 fn gen_cornell_box() -> HittableList {
     let red_diffuse = Arc::new(Material::Lambertian {
@@ -338,6 +476,8 @@ fn gen_cornell_box() -> HittableList {
     let light = Arc::new(Material::DiffuseLight {
         albedo: Arc::new(colors::WHITE),
         brightness: 15.0,
+        two_sided: true,
+        profile: None,
     });
 
     // yz rect - zero x
@@ -399,13 +539,17 @@ fn gen_cornell_box2() -> HittableList {
     let light = Arc::new(Material::DiffuseLight {
         albedo: Arc::new(colors::WHITE),
         brightness: 12.0,
+        two_sided: true,
+        profile: None,
     });
 
     let _mirror_like = Arc::new(Material::Metal {
         albedo: Arc::new(SolidColor::splat(0.999)),
         roughness: 0.0,
     });
-    let _glass_like = Arc::new(Material::Dielectric { refract_index: 1.5 });
+    let _glass_like = Arc::new(Material::Dielectric {
+        refract_index: Ior::Constant(1.5),
+    });
 
     // walls
     let floor = Quad::new(
@@ -567,6 +711,8 @@ fn box_helper() -> HittableList {
     let light = Arc::new(Material::DiffuseLight {
         albedo: Arc::new(colors::WHITE),
         brightness: 15.0,
+        two_sided: true,
+        profile: None,
     });
 
     // yz rect - zero x
@@ -648,10 +794,14 @@ fn gen_emissive_random_spheres(rng: &mut impl Rng) -> HittableList {
                     Arc::new(Material::DiffuseLight {
                         albedo: Arc::new(rng.gen::<Color>()),
                         brightness: rng.gen_range(2.0..10.0),
+                        two_sided: true,
+                        profile: None,
                     })
                 } else {
                     // glass
-                    Arc::new(Material::Dielectric { refract_index: 1.5 })
+                    Arc::new(Material::Dielectric {
+                        refract_index: Ior::Constant(1.5),
+                    })
                 };
 
                 let sph = Sphere::new(center, 0.2, &mat);
@@ -661,7 +811,9 @@ fn gen_emissive_random_spheres(rng: &mut impl Rng) -> HittableList {
     }
 
     // The signature central spheres
-    let mat_1 = Arc::new(Material::Dielectric { refract_index: 1.5 });
+    let mat_1 = Arc::new(Material::Dielectric {
+        refract_index: Ior::Constant(1.5),
+    });
     let sphere_1 = Sphere::new(Vec3A::new(0.0, 1.0, 0.0), 1.0, &mat_1);
 
     let mat_2 = Arc::new(Material::Lambertian {
@@ -724,6 +876,8 @@ fn gen_book2(rng: &mut impl Rng, shutter_time: std::ops::Range<f32>) -> Hittable
     let light_mat = Arc::new(Material::DiffuseLight {
         albedo: Arc::new(colors::WHITE),
         brightness: 7.0,
+        two_sided: true,
+        profile: None,
     });
     all_objects.push(Quad::from_bounds_k(123.0, 423.0, 147.0, 412.0, 554.0, 1, &light_mat).wrap());
 
@@ -741,7 +895,9 @@ fn gen_book2(rng: &mut impl Rng, shutter_time: std::ops::Range<f32>) -> Hittable
         Sphere::new(
             Vec3A::new(260.0, 150.0, 45.0),
             50.0,
-            &Arc::new(Material::Dielectric { refract_index: 1.5 }),
+            &Arc::new(Material::Dielectric {
+                refract_index: Ior::Constant(1.5),
+            }),
         )
         .wrap(),
     );
@@ -763,7 +919,9 @@ fn gen_book2(rng: &mut impl Rng, shutter_time: std::ops::Range<f32>) -> Hittable
     let boundary = Sphere::new(
         Vec3A::new(360.0, 150.0, 145.0),
         70.0,
-        &Arc::new(Material::Dielectric { refract_index: 1.5 }),
+        &Arc::new(Material::Dielectric {
+            refract_index: Ior::Constant(1.5),
+        }),
     );
 
     let wrapped_boundary: Arc<dyn Hittable> = boundary.wrap();
@@ -777,7 +935,9 @@ fn gen_book2(rng: &mut impl Rng, shutter_time: std::ops::Range<f32>) -> Hittable
     let mist_boundary: Arc<dyn Hittable> = Sphere::new(
         Vec3A::ZERO,
         5000.0,
-        &Arc::new(Material::Dielectric { refract_index: 1.5 }),
+        &Arc::new(Material::Dielectric {
+            refract_index: Ior::Constant(1.5),
+        }),
     )
     .wrap();
 
@@ -827,6 +987,8 @@ fn gen_debug_book2(rng: &mut impl Rng, shutter_time: std::ops::Range<f32>) -> Hi
     let light_mat = Arc::new(Material::DiffuseLight {
         albedo: Arc::new(colors::WHITE),
         brightness: 7.0,
+        two_sided: true,
+        profile: None,
     });
     all_objects.push(Quad::from_bounds_k(123.0, 423.0, 147.0, 412.0, 554.0, 1, &light_mat).wrap());
 
@@ -0,0 +1,1730 @@
+//! Declarative scene descriptions
+//!
+//! Scenes can be described in a [TOML](https://toml.io) file instead of being hard-coded in [crate::scenes].
+//! A scene file holds a camera, a set of named textures and materials, and a list of objects:
+//!
+//! ```toml
+//! [camera]
+//! look_from = [13.0, 2.0, 3.0]
+//! look_at = [0.0, 0.0, 0.0]
+//! vert_fov = 20.0
+//! aspect_ratio = 1.5
+//!
+//! [textures.checker]
+//! type = "checkered"
+//! even = [0.9, 0.9, 0.9]
+//! odd = [0.2, 0.3, 0.1]
+//!
+//! [materials.ground]
+//! type = "lambertian"
+//! albedo = "checker"
+//!
+//! [[objects]]
+//! type = "sphere"
+//! center = [0.0, -1000.0, 0.0]
+//! radius = 1000.0
+//! material = "ground"
+//! ```
+//!
+//! Besides the default perspective projection, the camera can look through an orthographic, fisheye,
+//! equirectangular, or cube map projection:
+//!
+//! ```toml
+//! [camera.projection]
+//! type = "fisheye"
+//! fov = 180.0
+//! mapping = "equisolid"
+//! ```
+//!
+//! Textures can be referenced by name or given inline as an `[r, g, b]` color or a single gray value,
+//! and materials can be referenced by name or given inline as a table.
+//!
+//! Lights can emit a `power` in watts or lumens instead of a `brightness`, spread over the area of each object using them.
+//! One-sided lights only emit from the front face of their objects,
+//! and an IES photometric profile shapes their light like a real luminaire's:
+//!
+//! ```toml
+//! [materials.downlight]
+//! type = "diffuse_light"
+//! albedo = [1.0, 0.9, 0.8]
+//! power = 800.0
+//! unit = "lumens"
+//! two_sided = false
+//! profile = "downlight.ies"
+//! ```
+//!
+//! Objects used many times can be declared once as named geometry, then placed by `instance` objects,
+//! which share a single [Tree] over the geometry:
+//!
+//! ```toml
+//! [[geometry.pillar]]
+//! type = "quad_box"
+//! min = [-0.5, 0.0, -0.5]
+//! max = [0.5, 4.0, 0.5]
+//! material = "ground"
+//!
+//! [[objects]]
+//! type = "instance"
+//! geometry = "pillar"
+//! translate = [2.0, 0.0, 0.0]
+//! ```
+//!
+//! Instead of the camera's background color, scenes can be lit by an equirectangular environment map,
+//! turned by `rotation` degrees around the y axis:
+//!
+//! ```toml
+//! [environment]
+//! type = "image"
+//! path = "studio.exr"
+//! rotation = 90.0
+//! intensity = 2.0
+//! ```
+//!
+//! or by a procedural daylight sky, with the sun towards `sun_direction`:
+//!
+//! ```toml
+//! [environment]
+//! type = "sky"
+//! sun_direction = [1.0, 0.5, -1.0]
+//! turbidity = 3.0
+//! ground_albedo = [0.3, 0.3, 0.3]
+//! ```
+//!
+//! Lights without geometry, which are only seen by the light they cast, are listed separately from the objects.
+//! Spot lights fade out from `falloff_start` to `total_width` degrees off their axis:
+//!
+//! ```toml
+//! [[lights]]
+//! type = "spot"
+//! position = [0.0, 5.0, 0.0]
+//! look_at = [0.0, 0.0, 0.0]
+//! color = [1.0, 0.9, 0.8]
+//! intensity = 50.0
+//! falloff_start = 20.0
+//! total_width = 30.0
+//!
+//! [[lights]]
+//! type = "directional"
+//! direction = [-1.0, -2.0, 1.0]
+//! intensity = 0.5
+//! ```
+//! See `resources/scenes` for complete examples.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glam::{Vec2, Vec3, Vec3A};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    camera::{Camera, FisheyeMapping, Projection},
+    color::{colors, Color},
+    hittables::*,
+    lights::{DeltaLight, Environment, EnvironmentMap, Sky},
+    material::{ComplexIor, IesProfile, Ior, Material, Power},
+    textures::*,
+    tree::Tree,
+};
+
+/// Errors produced while loading a scene file
+#[derive(Debug)]
+pub enum SceneFileError {
+    /// The scene file could not be read
+    Io {
+        /// Path of the scene file
+        path: PathBuf,
+        /// The underlying I/O error
+        source: std::io::Error,
+    },
+    /// The scene file is malformed or describes an invalid scene
+    Parse {
+        /// Path of the scene file
+        path: PathBuf,
+        /// 1-based line of the offending item
+        line: usize,
+        /// 1-based column of the offending item
+        column: usize,
+        /// The offending key, if it could be determined
+        key: Option<String>,
+        /// Description of the problem
+        message: String,
+    },
+}
+
+impl std::fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneFileError::Io { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            SceneFileError::Parse {
+                path,
+                line,
+                column,
+                key,
+                message,
+            } => {
+                write!(f, "{}:{line}:{column}: {message}", path.display())?;
+                if let Some(key) = key {
+                    write!(f, " (at key `{key}`)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneFileError::Io { source, .. } => Some(source),
+            SceneFileError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Camera parameters, mirroring the arguments of [Camera::new]
+///
+/// Every field is optional, defaulting to the values used by the hard-coded scenes.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraDesc {
+    look_from: Vec3A,
+    look_at: Vec3A,
+    view_up: Vec3A,
+    vert_fov: f32,
+    aspect_ratio: f32,
+    aperture: f32,
+    focus_dist: f32,
+    #[serde(deserialize_with = "shutter_time")]
+    shutter_time: [f32; 2],
+    bg_color: Color,
+    projection: ProjectionDesc,
+}
+
+/// Deserializes a camera's `[open, close]` shutter times, where equal times describe a still camera
+fn shutter_time<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<[f32; 2], D::Error> {
+    let [open, close] = <[f32; 2]>::deserialize(deserializer)?;
+    if open > close {
+        return Err(serde::de::Error::custom(format!(
+            "the shutter closes at {close} before it opens at {open}"
+        )));
+    }
+    Ok([open, close])
+}
+
+/// Camera projections, see [Projection]; perspective projections use the camera's `vert_fov`
+#[derive(Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ProjectionDesc {
+    #[default]
+    Perspective,
+    Orthographic {
+        height: f32,
+    },
+    Fisheye {
+        #[serde(default = "default_fisheye_fov")]
+        fov: f32,
+        #[serde(default)]
+        mapping: FisheyeMappingDesc,
+    },
+    Equirectangular,
+    CubeMap,
+}
+
+/// See [FisheyeMapping]
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FisheyeMappingDesc {
+    #[default]
+    Equidistant,
+    Equisolid,
+}
+
+fn default_fisheye_fov() -> f32 {
+    180.0
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self {
+            look_from: Vec3A::new(13.0, 2.0, 3.0),
+            look_at: Vec3A::ZERO,
+            view_up: Vec3A::Y,
+            vert_fov: 20.0,
+            aspect_ratio: 16.0 / 9.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            shutter_time: [0.0, 1.0],
+            bg_color: Color::new(0.7, 0.8, 1.0),
+            projection: ProjectionDesc::Perspective,
+        }
+    }
+}
+
+/// A reference to a texture, either by name, as an inline solid color, or as a single gray value
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color(Color),
+    Scalar(f32),
+    Name(String),
+}
+
+/// Texture definitions
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    /// See [SolidColor]
+    Solid { color: Color },
+    /// See [Checkered]
+    Checkered { even: TextureRef, odd: TextureRef },
+    /// See [ImageMap]; relative paths are resolved against the scene file's directory
+    Image { path: PathBuf },
+    /// See [NoiseTexture]; uses Perlin noise
+    Noise {
+        scale: f32,
+        #[serde(default)]
+        seed: u32,
+    },
+}
+
+/// A refractive index, either constant, the name of a preset,
+/// or the coefficients of Cauchy's or Sellmeier's equation, see [Ior]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IorDesc {
+    Constant(f32),
+    Preset(String),
+    Cauchy { a: f32, b: f32 },
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl IorDesc {
+    /// Returns the described refractive index
+    fn to_ior(&self) -> Result<Ior, String> {
+        match self {
+            IorDesc::Constant(index) => Ok(Ior::Constant(*index)),
+            IorDesc::Preset(name) => Ior::preset(name).ok_or_else(|| {
+                format!(
+                    "unknown glass `{name}`, expected one of {}",
+                    Ior::PRESETS.join(", ")
+                )
+            }),
+            IorDesc::Cauchy { a, b } => Ok(Ior::Cauchy { a: *a, b: *b }),
+            IorDesc::Sellmeier { b, c } => Ok(Ior::Sellmeier { b: *b, c: *c }),
+        }
+    }
+}
+
+/// A reference to a material, either by name or as an inline definition
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MaterialRef {
+    Name(String),
+    Inline(Box<MaterialDesc>),
+}
+
+/// Material definitions, one per [Material] variant
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: TextureRef,
+        #[serde(default)]
+        roughness: f32,
+    },
+    /// Uses either the named `metal` preset, or the complex index of refraction `eta + i k`
+    Conductor {
+        metal: Option<String>,
+        eta: Option<Color>,
+        k: Option<Color>,
+        #[serde(default)]
+        roughness: f32,
+    },
+    Dielectric {
+        refract_index: IorDesc,
+    },
+    /// `tint` defaults to white, and `absorption` to none
+    RoughDielectric {
+        refract_index: IorDesc,
+        #[serde(default)]
+        roughness: f32,
+        tint: Option<TextureRef>,
+        #[serde(default)]
+        absorption: Color,
+    },
+    /// `roughness` and `specular` default to 0.5, and the other scalar parameters to 0
+    Principled {
+        base_color: TextureRef,
+        metallic: Option<TextureRef>,
+        roughness: Option<TextureRef>,
+        specular: Option<TextureRef>,
+        clearcoat: Option<TextureRef>,
+        sheen: Option<TextureRef>,
+        transmission: Option<TextureRef>,
+        #[serde(default = "default_refract_index")]
+        refract_index: f32,
+    },
+    /// Without `brightness`, the light emits `power` in the given `unit`, spread over the area of each object using it;
+    /// relative profile paths are resolved against the scene file's directory
+    DiffuseLight {
+        albedo: TextureRef,
+        brightness: Option<f32>,
+        power: Option<f32>,
+        #[serde(default)]
+        unit: PowerUnit,
+        #[serde(default = "default_two_sided")]
+        two_sided: bool,
+        profile: Option<PathBuf>,
+    },
+    Isotropic {
+        albedo: TextureRef,
+    },
+}
+
+/// Units of a light's `power`, see [Power]
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PowerUnit {
+    #[default]
+    Watts,
+    Lumens,
+}
+
+impl PowerUnit {
+    fn power(self, value: f32) -> Power {
+        match self {
+            PowerUnit::Watts => Power::Watts(value),
+            PowerUnit::Lumens => Power::Lumens(value),
+        }
+    }
+}
+
+fn default_two_sided() -> bool {
+    true
+}
+
+fn default_refract_index() -> f32 {
+    1.5
+}
+
+fn default_brightness() -> f32 {
+    1.0
+}
+
+fn default_time1() -> f32 {
+    1.0
+}
+
+/// Environment definitions
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDesc {
+    /// See [EnvironmentMap]; relative paths are resolved against the scene file's directory
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default = "default_brightness")]
+        intensity: f32,
+    },
+    /// See [Sky]
+    Sky {
+        sun_direction: Vec3A,
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: Color,
+        #[serde(default = "default_brightness")]
+        intensity: f32,
+    },
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+fn default_ground_albedo() -> Color {
+    Sky::DEFAULT_GROUND_ALBEDO
+}
+
+/// Delta light definitions, see [DeltaLight]
+///
+/// Each light's `color` is scaled by its `intensity`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    Point {
+        position: Vec3A,
+        #[serde(default = "default_light_color")]
+        color: Color,
+        #[serde(default = "default_brightness")]
+        intensity: f32,
+    },
+    /// Without a `falloff_start`, the cone has a sharp edge
+    Spot {
+        position: Vec3A,
+        look_at: Vec3A,
+        #[serde(default = "default_light_color")]
+        color: Color,
+        #[serde(default = "default_brightness")]
+        intensity: f32,
+        falloff_start: Option<f32>,
+        total_width: f32,
+    },
+    /// `direction` is the direction the light travels in
+    Directional {
+        direction: Vec3A,
+        #[serde(default = "default_light_color")]
+        color: Color,
+        #[serde(default = "default_brightness")]
+        intensity: f32,
+    },
+}
+
+fn default_light_color() -> Color {
+    colors::WHITE
+}
+
+/// A rotation of `degrees` around `axis`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDesc {
+    axis: Vec3,
+    degrees: f32,
+}
+
+/// Object definitions, one per primitive in [crate::hittables]
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    /// See [Sphere]
+    Sphere {
+        center: Vec3A,
+        radius: f32,
+        material: MaterialRef,
+    },
+    /// See [MovingSphere]
+    MovingSphere {
+        center0: Vec3A,
+        center1: Vec3A,
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+        radius: f32,
+        material: MaterialRef,
+    },
+    /// See [Quad]; points are given in the order `p0, p1, p2, p3`
+    Quad {
+        points: [Vec3A; 4],
+        material: MaterialRef,
+    },
+    /// See [Triangle]
+    Triangle {
+        points: [Vec3A; 3],
+        normals: Option<[Vec3A; 3]>,
+        uvs: Option<[Vec2; 3]>,
+        material: MaterialRef,
+    },
+    /// See [TriangleMesh]; `indices` holds three indices into `positions` per triangle
+    Mesh {
+        positions: Vec<Vec3A>,
+        indices: Vec<[u32; 3]>,
+        normals: Option<Vec<Vec3A>>,
+        uvs: Option<Vec<Vec2>>,
+        material: MaterialRef,
+    },
+    /// A Wavefront OBJ file, see [crate::loaders::obj]; relative paths are resolved against the scene file's directory
+    ///
+    /// `material` is used for the models that don't have a material of their own.
+    Obj {
+        path: PathBuf,
+        material: Option<MaterialRef>,
+    },
+    /// A Stanford PLY file, see [crate::loaders::ply]; relative paths are resolved against the scene file's directory
+    ///
+    /// `material` is used unless the model has vertex colors.
+    Ply {
+        path: PathBuf,
+        material: Option<MaterialRef>,
+    },
+    /// A glTF or GLB file, see [crate::loaders::gltf]; relative paths are resolved against the scene file's directory
+    ///
+    /// `material` is used for the primitives that don't have a material of their own. The file's cameras are ignored.
+    Gltf {
+        path: PathBuf,
+        material: Option<MaterialRef>,
+    },
+    /// See [QuadBox]
+    QuadBox {
+        min: Vec3A,
+        max: Vec3A,
+        material: MaterialRef,
+    },
+    /// See [Transform]; applies `scale`, then each of `rotate` in order, then `translate`
+    Transform {
+        object: Box<ObjectDesc>,
+        scale: Option<Vec3>,
+        #[serde(default)]
+        rotate: Vec<RotationDesc>,
+        translate: Option<Vec3>,
+    },
+    /// An instance of the named `geometry`, placed like a [Transform]
+    Instance {
+        geometry: String,
+        scale: Option<Vec3>,
+        #[serde(default)]
+        rotate: Vec<RotationDesc>,
+        translate: Option<Vec3>,
+    },
+    /// See [ConstantMedium]
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        albedo: TextureRef,
+        density: f32,
+    },
+}
+
+/// The top-level structure of a scene file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    geometry: BTreeMap<String, Vec<ObjectDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+    environment: Option<Spanned<EnvironmentDesc>>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+}
+
+/// A parsed scene file, ready to be turned into geometry and a [Camera]
+pub struct SceneFile {
+    /// Path of the scene file, used for error reporting and relative paths
+    path: PathBuf,
+    /// The raw contents of the file, used for error reporting
+    source: String,
+    /// The deserialized scene description
+    desc: SceneDesc,
+}
+
+/// An error found while building the scene, located at a byte offset and a key path
+struct BuildError {
+    offset: usize,
+    key: String,
+    message: String,
+}
+
+/// Caches already-built textures, materials, and geometry during scene construction
+struct Builder<'a> {
+    desc: &'a SceneDesc,
+    base_dir: &'a Path,
+    textures: BTreeMap<&'a str, Arc<dyn Texture>>,
+    materials: BTreeMap<&'a str, Arc<Material>>,
+    geometry: BTreeMap<&'a str, Arc<dyn Hittable>>,
+    /// Names of textures currently being built, to catch reference cycles
+    pending: Vec<&'a str>,
+    /// Names of geometry currently being built, to catch instancing cycles
+    pending_geometry: Vec<&'a str>,
+}
+
+impl SceneFile {
+    /// Reads and parses the scene file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneFileError> {
+        let path = path.as_ref().to_path_buf();
+        match std::fs::read_to_string(&path) {
+            Ok(source) => Self::parse(path, source),
+            Err(source) => Err(SceneFileError::Io { path, source }),
+        }
+    }
+
+    /// Parses the given scene file contents; `path` is used for error reporting and relative paths
+    pub fn parse(path: PathBuf, source: String) -> Result<Self, SceneFileError> {
+        match toml::from_str::<SceneDesc>(&source) {
+            Ok(desc) => Ok(Self { path, source, desc }),
+            Err(why) => {
+                let span = why.span().unwrap_or(0..0);
+                let (offset, key) = locate(&source, span, why.message());
+                Err(Self::error_at(&path, &source, offset, key, why.message()))
+            }
+        }
+    }
+
+    /// Returns the [Camera] described by the scene file
+    pub fn camera(&self) -> Camera {
+        let cam = &self.desc.camera;
+        let camera = Camera::new(
+            cam.look_from,
+            cam.look_at,
+            cam.view_up,
+            cam.vert_fov,
+            cam.aspect_ratio,
+            cam.aperture,
+            cam.focus_dist,
+            cam.shutter_time[0]..cam.shutter_time[1],
+            cam.bg_color,
+        );
+        let projection = match cam.projection {
+            ProjectionDesc::Perspective => return camera,
+            ProjectionDesc::Orthographic { height } => Projection::Orthographic { height },
+            ProjectionDesc::Fisheye { fov, mapping } => Projection::Fisheye {
+                fov,
+                mapping: match mapping {
+                    FisheyeMappingDesc::Equidistant => FisheyeMapping::Equidistant,
+                    FisheyeMappingDesc::Equisolid => FisheyeMapping::Equisolid,
+                },
+            },
+            ProjectionDesc::Equirectangular => Projection::Equirectangular,
+            ProjectionDesc::CubeMap => Projection::CubeMap,
+        };
+        camera.with_projection(projection)
+    }
+
+    /// Returns the paths of the files the scene refers to, resolved like when building the scene
+    ///
+    /// These are image textures, IES profiles, models, and environment maps, but not the files that models refer to themselves.
+    pub fn referenced_files(&self) -> Vec<PathBuf> {
+        let base_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let mut files = Vec::new();
+
+        for texture in self.desc.textures.values() {
+            if let TextureDesc::Image { path } = texture.get_ref() {
+                files.push(path);
+            }
+        }
+        for material in self.desc.materials.values() {
+            material_files(material.get_ref(), &mut files);
+        }
+        let objects = self.desc.objects.iter().map(Spanned::get_ref);
+        for object in objects.chain(self.desc.geometry.values().flatten()) {
+            object_files(object, &mut files);
+        }
+        if let Some(EnvironmentDesc::Image { path, .. }) =
+            self.desc.environment.as_ref().map(Spanned::get_ref)
+        {
+            files.push(path);
+        }
+
+        files.into_iter().map(|path| base_dir.join(path)).collect()
+    }
+
+    /// Returns the [DeltaLight]s described by the scene file
+    pub fn lights(&self) -> Vec<DeltaLight> {
+        self.desc
+            .lights
+            .iter()
+            .map(|desc| match *desc {
+                LightDesc::Point {
+                    position,
+                    color,
+                    intensity,
+                } => DeltaLight::point(position, color * intensity),
+                LightDesc::Spot {
+                    position,
+                    look_at,
+                    color,
+                    intensity,
+                    falloff_start,
+                    total_width,
+                } => DeltaLight::spot(
+                    position,
+                    look_at,
+                    color * intensity,
+                    falloff_start.unwrap_or(total_width),
+                    total_width,
+                ),
+                LightDesc::Directional {
+                    direction,
+                    color,
+                    intensity,
+                } => DeltaLight::directional(direction, color * intensity),
+            })
+            .collect()
+    }
+
+    /// Returns the environment described by the scene file, if any
+    ///
+    /// Fails if the environment's image cannot be loaded.
+    pub fn environment(&self) -> Result<Option<Arc<dyn Environment>>, SceneFileError> {
+        let Some(desc) = &self.desc.environment else {
+            return Ok(None);
+        };
+        let base_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+
+        match desc.get_ref() {
+            EnvironmentDesc::Image {
+                path,
+                rotation,
+                intensity,
+            } => {
+                let path = base_dir.join(path);
+                match EnvironmentMap::open(&path, *rotation, *intensity) {
+                    Ok(environment) => Ok(Some(Arc::new(environment))),
+                    Err(why) => {
+                        let message = format!("failed to open {}: {why}", path.display());
+                        let offset = locate(&self.source, desc.span(), &message).0;
+                        Err(Self::error_at(
+                            &self.path,
+                            &self.source,
+                            offset,
+                            Some("environment".to_string()),
+                            &message,
+                        ))
+                    }
+                }
+            }
+            EnvironmentDesc::Sky {
+                sun_direction,
+                turbidity,
+                ground_albedo,
+                intensity,
+            } => Ok(Some(Arc::new(Sky::new(
+                *sun_direction,
+                *turbidity,
+                *ground_albedo,
+                *intensity,
+            )))),
+        }
+    }
+
+    /// Builds the objects described by the scene file
+    ///
+    /// Fails if an object refers to a texture or material that is not defined.
+    pub fn geometry(&self) -> Result<HittableList, SceneFileError> {
+        let base_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        let mut builder = Builder {
+            desc: &self.desc,
+            base_dir,
+            textures: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            geometry: BTreeMap::new(),
+            pending: Vec::new(),
+            pending_geometry: Vec::new(),
+        };
+
+        self.desc
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(idx, object)| {
+                let key = format!("objects[{idx}]");
+                builder.objects(object.get_ref(), &key).map_err(|message| {
+                    let (offset, located) = locate(&self.source, object.span(), &message);
+                    BuildError {
+                        offset,
+                        // the offending key within the object, when the object is a table of its own
+                        key: located
+                            .filter(|located| located.starts_with(&key))
+                            .unwrap_or(key),
+                        message,
+                    }
+                })
+            })
+            .collect::<Result<Vec<HittableList>, BuildError>>()
+            .map(|objects| objects.into_iter().flatten().collect())
+            .map_err(|why| {
+                Self::error_at(
+                    &self.path,
+                    &self.source,
+                    why.offset,
+                    Some(why.key),
+                    &why.message,
+                )
+            })
+    }
+
+    /// Creates a [SceneFileError::Parse] located at the byte `offset` into `source`
+    fn error_at(
+        path: &Path,
+        source: &str,
+        offset: usize,
+        key: Option<String>,
+        message: &str,
+    ) -> SceneFileError {
+        let (line, column) = position(source, offset);
+        SceneFileError::Parse {
+            path: path.to_path_buf(),
+            line,
+            column,
+            key,
+            message: message.trim_end().to_string(),
+        }
+    }
+}
+
+impl<'a> Builder<'a> {
+    /// Builds (or retrieves) the texture referenced by `tex_ref`
+    fn texture(&mut self, tex_ref: &'a TextureRef) -> Result<Arc<dyn Texture>, String> {
+        let name = match tex_ref {
+            TextureRef::Color(color) => return Ok(Arc::new(*color)),
+            TextureRef::Scalar(value) => return Ok(Arc::new(Color::splat(*value))),
+            TextureRef::Name(name) => name.as_str(),
+        };
+
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(Arc::clone(texture));
+        }
+
+        let Some(desc) = self.desc.textures.get(name) else {
+            return Err(format!("undefined texture `{name}`"));
+        };
+
+        if self.pending.contains(&name) {
+            return Err(format!("texture `{name}` refers to itself"));
+        }
+        self.pending.push(name);
+
+        let texture: Arc<dyn Texture> = match desc.get_ref() {
+            TextureDesc::Solid { color } => Arc::new(*color),
+            TextureDesc::Checkered { even, odd } => {
+                let even = self.texture(even)?;
+                let odd = self.texture(odd)?;
+                Arc::new(Checkered::new(&Arc::new(even), &Arc::new(odd)))
+            }
+            TextureDesc::Image { path } => {
+                let path = self.base_dir.join(path);
+                match ImageMap::open(&path) {
+                    Ok(image_map) => Arc::new(image_map),
+                    Err(why) => {
+                        return Err(format!("failed to open {}: {why}", path.display()));
+                    }
+                }
+            }
+            TextureDesc::Noise { scale, seed } => {
+                Arc::new(NoiseTexture::new(::noise::Perlin::new(*seed), *scale))
+            }
+        };
+
+        self.pending.pop();
+        self.textures.insert(name, Arc::clone(&texture));
+        Ok(texture)
+    }
+
+    /// Builds the material described by `desc`
+    fn new_material(&mut self, desc: &'a MaterialDesc) -> Result<Arc<Material>, String> {
+        let material = match desc {
+            MaterialDesc::Lambertian { albedo } => Material::Lambertian {
+                albedo: self.texture(albedo)?,
+            },
+            MaterialDesc::Metal { albedo, roughness } => Material::Metal {
+                albedo: self.texture(albedo)?,
+                roughness: *roughness,
+            },
+            MaterialDesc::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                let ior = match (metal, eta, k) {
+                    (Some(metal), None, None) => ComplexIor::preset(metal).ok_or_else(|| {
+                        format!(
+                            "unknown metal `{metal}`, expected one of {}",
+                            ComplexIor::PRESETS.join(", ")
+                        )
+                    })?,
+                    (None, Some(eta), Some(k)) => ComplexIor::new(*eta, *k),
+                    _ => return Err("expected either `metal`, or both `eta` and `k`".into()),
+                };
+                Material::Conductor {
+                    ior,
+                    roughness: *roughness,
+                }
+            }
+            MaterialDesc::Dielectric { refract_index } => Material::Dielectric {
+                refract_index: refract_index.to_ior()?,
+            },
+            MaterialDesc::RoughDielectric {
+                refract_index,
+                roughness,
+                tint,
+                absorption,
+            } => Material::RoughDielectric {
+                refract_index: refract_index.to_ior()?,
+                roughness: *roughness,
+                tint: match tint {
+                    Some(tint) => self.texture(tint)?,
+                    None => Arc::new(crate::color::colors::WHITE),
+                },
+                absorption: *absorption,
+            },
+            MaterialDesc::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                sheen,
+                transmission,
+                refract_index,
+            } => {
+                let mut scalar = |tex_ref: &'a Option<TextureRef>, default: f32| match tex_ref {
+                    Some(tex_ref) => self.texture(tex_ref),
+                    None => Ok(Arc::new(Color::splat(default)) as Arc<dyn Texture>),
+                };
+                Material::Principled {
+                    metallic: scalar(metallic, 0.0)?,
+                    roughness: scalar(roughness, 0.5)?,
+                    specular: scalar(specular, 0.5)?,
+                    clearcoat: scalar(clearcoat, 0.0)?,
+                    sheen: scalar(sheen, 0.0)?,
+                    transmission: scalar(transmission, 0.0)?,
+                    base_color: self.texture(base_color)?,
+                    refract_index: *refract_index,
+                }
+            }
+            MaterialDesc::DiffuseLight {
+                albedo,
+                brightness,
+                power,
+                two_sided,
+                profile,
+                ..
+            } => {
+                if brightness.is_some() && power.is_some() {
+                    return Err("expected either `brightness` or `power`, not both".into());
+                }
+                let profile = match profile {
+                    Some(path) => {
+                        let path = self.base_dir.join(path);
+                        match IesProfile::open(&path) {
+                            Ok(profile) => Some(Arc::new(profile)),
+                            Err(why) => {
+                                return Err(format!("failed to open {}: {why}", path.display()));
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                Material::DiffuseLight {
+                    albedo: self.texture(albedo)?,
+                    // lights with a power get their brightness from each object's area
+                    brightness: brightness.unwrap_or_else(default_brightness),
+                    two_sided: *two_sided,
+                    profile,
+                }
+            }
+            MaterialDesc::Isotropic { albedo } => Material::Isotropic {
+                albedo: self.texture(albedo)?,
+            },
+        };
+        Ok(Arc::new(material))
+    }
+
+    /// Builds (or retrieves) the material referenced by `mat_ref`
+    fn material(&mut self, mat_ref: &'a MaterialRef) -> Result<Arc<Material>, String> {
+        let name = match mat_ref {
+            MaterialRef::Inline(desc) => return self.new_material(desc),
+            MaterialRef::Name(name) => name.as_str(),
+        };
+
+        if let Some(material) = self.materials.get(name) {
+            return Ok(Arc::clone(material));
+        }
+
+        let Some(desc) = self.desc.materials.get(name) else {
+            return Err(format!("undefined material `{name}`"));
+        };
+
+        let material = self
+            .new_material(desc.get_ref())
+            .map_err(|why| format!("in material `{name}`: {why}"))?;
+        self.materials.insert(name, Arc::clone(&material));
+        Ok(material)
+    }
+
+    /// Builds (or retrieves) the material referenced by `mat_ref`, for the object described by `desc`
+    ///
+    /// Lights given a `power` instead of a brightness get their own material, emitting that power over the object's area.
+    fn surface_material(
+        &mut self,
+        mat_ref: &'a MaterialRef,
+        desc: &ObjectDesc,
+    ) -> Result<Arc<Material>, String> {
+        let material = self.material(mat_ref)?;
+        let mat_desc = match mat_ref {
+            MaterialRef::Inline(mat_desc) => mat_desc.as_ref(),
+            MaterialRef::Name(name) => self.desc.materials[name].get_ref(),
+        };
+        let (
+            MaterialDesc::DiffuseLight {
+                power: Some(power),
+                unit,
+                ..
+            },
+            Material::DiffuseLight {
+                albedo,
+                two_sided,
+                profile,
+                ..
+            },
+        ) = (mat_desc, material.as_ref())
+        else {
+            return Ok(material);
+        };
+
+        let Some(area) = object_area(desc).filter(|&area| area > 0.0) else {
+            return Err(
+                "a light's `power` needs a sphere, quad, triangle, mesh, or box to spread over"
+                    .to_string(),
+            );
+        };
+        Ok(Arc::new(Material::DiffuseLight {
+            albedo: Arc::clone(albedo),
+            brightness: unit.power(*power).brightness(area, *two_sided),
+            two_sided: *two_sided,
+            profile: profile.clone(),
+        }))
+    }
+
+    /// Builds the object described by `desc` as a single [Hittable]
+    ///
+    /// Objects made of several primitives, like meshes, are gathered into a [Tree].
+    fn object(&mut self, desc: &'a ObjectDesc, key: &str) -> Result<Arc<dyn Hittable>, String> {
+        let objects = self.objects(desc, key)?;
+        Ok(self.gather(objects))
+    }
+
+    /// Returns the only one of `objects`, or a [Tree] over all of them
+    fn gather(&self, mut objects: HittableList) -> Arc<dyn Hittable> {
+        if objects.len() == 1 {
+            objects.remove(0)
+        } else {
+            let [time0, time1] = self.desc.camera.shutter_time;
+            Tree::new(objects, time0, time1).wrap()
+        }
+    }
+
+    /// Builds (or retrieves) the named geometry, shared by all of its instances
+    fn geometry(&mut self, name: &'a str) -> Result<Arc<dyn Hittable>, String> {
+        if let Some(geometry) = self.geometry.get(name) {
+            return Ok(Arc::clone(geometry));
+        }
+
+        let Some((name, descs)) = self.desc.geometry.get_key_value(name) else {
+            return Err(format!("undefined geometry `{name}`"));
+        };
+        if descs.is_empty() {
+            return Err(format!("geometry `{name}` has no objects"));
+        }
+        if self.pending_geometry.contains(&name.as_str()) {
+            return Err(format!("geometry `{name}` instances itself"));
+        }
+
+        self.pending_geometry.push(name);
+        let objects = descs
+            .iter()
+            .enumerate()
+            .map(|(idx, desc)| self.objects(desc, &format!("geometry.{name}[{idx}]")))
+            .collect::<Result<Vec<_>, _>>();
+        self.pending_geometry.pop();
+
+        let objects = objects.map_err(|why| format!("in geometry `{name}`: {why}"))?;
+        let geometry = self.gather(objects.into_iter().flatten().collect());
+        self.geometry.insert(name, Arc::clone(&geometry));
+        Ok(geometry)
+    }
+
+    /// Builds the primitives making up the object described by `desc`; `key` locates the object in error messages
+    fn objects(&mut self, desc: &'a ObjectDesc, key: &str) -> Result<HittableList, String> {
+        let object: Arc<dyn Hittable> = match desc {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => Sphere::new(*center, *radius, &self.surface_material(material, desc)?).wrap(),
+            ObjectDesc::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => MovingSphere::new(
+                *center0,
+                *center1,
+                *time0,
+                *time1,
+                *radius,
+                &self.surface_material(material, desc)?,
+            )
+            .wrap(),
+            ObjectDesc::Quad {
+                points: [p0, p1, p2, p3],
+                material,
+            } => Quad::new(*p0, *p1, *p2, *p3, &self.surface_material(material, desc)?).wrap(),
+            ObjectDesc::Triangle {
+                points: [p0, p1, p2],
+                normals,
+                uvs,
+                material,
+            } => {
+                let mut triangle =
+                    Triangle::new(*p0, *p1, *p2, &self.surface_material(material, desc)?);
+                if let Some(normals) = normals {
+                    triangle = triangle.with_normals(*normals);
+                }
+                if let Some(uvs) = uvs {
+                    triangle = triangle.with_uvs(*uvs);
+                }
+                triangle.wrap()
+            }
+            ObjectDesc::Mesh {
+                positions,
+                indices,
+                normals,
+                uvs,
+                material,
+            } => {
+                if indices.is_empty() {
+                    return Err("mesh has no triangles".to_string());
+                }
+                if let Some(index) = indices
+                    .iter()
+                    .flatten()
+                    .find(|&&i| i as usize >= positions.len())
+                {
+                    return Err(format!(
+                        "mesh index {index} is out of bounds of {} positions",
+                        positions.len()
+                    ));
+                }
+                let attr_lens = [
+                    ("normals", normals.as_ref().map(Vec::len)),
+                    ("uvs", uvs.as_ref().map(Vec::len)),
+                ];
+                for (attr, len) in attr_lens {
+                    if len.is_some_and(|len| len != positions.len()) {
+                        return Err(format!("mesh needs exactly one of `{attr}` per position"));
+                    }
+                }
+
+                let mut mesh = TriangleMesh::new(
+                    positions.clone(),
+                    indices.clone(),
+                    &self.surface_material(material, desc)?,
+                );
+                if let Some(normals) = normals {
+                    mesh = mesh.with_normals(normals.clone());
+                }
+                if let Some(uvs) = uvs {
+                    mesh = mesh.with_uvs(uvs.clone());
+                }
+                return Ok(Arc::new(mesh).triangles());
+            }
+            ObjectDesc::Obj { path, material } => {
+                let default_material = match material {
+                    Some(material) => self.surface_material(material, desc)?,
+                    None => Arc::new(Material::Lambertian {
+                        albedo: Arc::new(Color::splat(0.73)),
+                    }),
+                };
+                let path = self.base_dir.join(path);
+                let objects = crate::loaders::load_obj(path, &default_material)
+                    .map_err(|why| why.to_string())?;
+                if objects.is_empty() {
+                    return Err("OBJ file has no triangles".to_string());
+                }
+                return Ok(objects);
+            }
+            ObjectDesc::Ply { path, material } => {
+                let default_material = match material {
+                    Some(material) => self.surface_material(material, desc)?,
+                    None => Arc::new(Material::Lambertian {
+                        albedo: Arc::new(Color::splat(0.73)),
+                    }),
+                };
+                let path = self.base_dir.join(path);
+                return crate::loaders::load_ply(path, &default_material)
+                    .map_err(|why| why.to_string());
+            }
+            ObjectDesc::Gltf { path, material } => {
+                let default_material = match material {
+                    Some(material) => self.surface_material(material, desc)?,
+                    None => Arc::new(Material::Lambertian {
+                        albedo: Arc::new(Color::splat(0.73)),
+                    }),
+                };
+                let path = self.base_dir.join(path);
+                return crate::loaders::load_gltf(path, &default_material)
+                    .map(|scene| scene.hittables)
+                    .map_err(|why| why.to_string());
+            }
+            ObjectDesc::QuadBox { min, max, material } => {
+                QuadBox::new(*min, *max, &self.surface_material(material, desc)?).wrap()
+            }
+            ObjectDesc::Transform {
+                object,
+                scale,
+                rotate,
+                translate,
+            } => {
+                let object = self.object(object, &format!("{key}.object"))?;
+                transformed(&object, *scale, rotate, *translate).wrap()
+            }
+            ObjectDesc::Instance {
+                geometry,
+                scale,
+                rotate,
+                translate,
+            } => {
+                let geometry = self.geometry(geometry)?;
+                transformed(&geometry, *scale, rotate, *translate).wrap()
+            }
+            ObjectDesc::ConstantMedium {
+                boundary,
+                albedo,
+                density,
+            } => {
+                let boundary = self.object(boundary, &format!("{key}.boundary"))?;
+                ConstantMedium::new(&boundary, &self.texture(albedo)?, *density).wrap()
+            }
+        };
+        Ok(vec![object])
+    }
+}
+
+/// Adds the files referred to by the material described by `desc` to `files`
+fn material_files<'a>(desc: &'a MaterialDesc, files: &mut Vec<&'a PathBuf>) {
+    if let MaterialDesc::DiffuseLight {
+        profile: Some(profile),
+        ..
+    } = desc
+    {
+        files.push(profile);
+    }
+}
+
+/// Adds the files referred to by the object described by `desc`, and by its inline materials, to `files`
+fn object_files<'a>(desc: &'a ObjectDesc, files: &mut Vec<&'a PathBuf>) {
+    let material = match desc {
+        ObjectDesc::Sphere { material, .. }
+        | ObjectDesc::MovingSphere { material, .. }
+        | ObjectDesc::Quad { material, .. }
+        | ObjectDesc::Triangle { material, .. }
+        | ObjectDesc::Mesh { material, .. }
+        | ObjectDesc::QuadBox { material, .. } => Some(material),
+        ObjectDesc::Obj { path, material }
+        | ObjectDesc::Ply { path, material }
+        | ObjectDesc::Gltf { path, material } => {
+            files.push(path);
+            material.as_ref()
+        }
+        ObjectDesc::Transform { object, .. } => return object_files(object, files),
+        ObjectDesc::ConstantMedium { boundary, .. } => return object_files(boundary, files),
+        ObjectDesc::Instance { .. } => None,
+    };
+    if let Some(MaterialRef::Inline(material)) = material {
+        material_files(material, files);
+    }
+}
+
+/// Returns the surface area of the object described by `desc`, if it has a simple surface
+///
+/// Quads are split into two triangles, so the area of a non-planar quad is approximate.
+fn object_area(desc: &ObjectDesc) -> Option<f32> {
+    let triangle = |p0: Vec3A, p1: Vec3A, p2: Vec3A| 0.5 * (p1 - p0).cross(p2 - p0).length();
+    match desc {
+        ObjectDesc::Sphere { radius, .. } | ObjectDesc::MovingSphere { radius, .. } => {
+            Some(4.0 * std::f32::consts::PI * radius * radius)
+        }
+        ObjectDesc::Quad {
+            points: [p0, p1, p2, p3],
+            ..
+        } => Some(triangle(*p0, *p1, *p2) + triangle(*p0, *p2, *p3)),
+        ObjectDesc::Triangle {
+            points: [p0, p1, p2],
+            ..
+        } => Some(triangle(*p0, *p1, *p2)),
+        ObjectDesc::Mesh {
+            positions, indices, ..
+        } => Some(
+            indices
+                .iter()
+                .filter_map(|&[i0, i1, i2]| {
+                    let point = |idx: u32| positions.get(idx as usize).copied();
+                    Some(triangle(point(i0)?, point(i1)?, point(i2)?))
+                })
+                .sum(),
+        ),
+        ObjectDesc::QuadBox { min, max, .. } => {
+            let size = (*max - *min).abs();
+            Some(2.0 * (size.x * size.y + size.y * size.z + size.z * size.x))
+        }
+        _ => None,
+    }
+}
+
+/// Places `object` by applying `scale`, then each of `rotate` in order, then `translate`
+fn transformed(
+    object: &Arc<dyn Hittable>,
+    scale: Option<Vec3>,
+    rotate: &[RotationDesc],
+    translate: Option<Vec3>,
+) -> Transform {
+    let mut transform = Transform::new(object);
+    if let Some(scale) = scale {
+        transform.with_scale_factor(scale);
+    }
+    for RotationDesc { axis, degrees } in rotate {
+        transform.with_axis_angle_degrees(axis.normalize(), *degrees);
+    }
+    if let Some(translate) = translate {
+        transform.with_translation(translate);
+    }
+    transform.finalize()
+}
+
+/// Converts a byte offset into `source` into a 1-based (line, column) pair
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(offset, |nl| offset - nl - 1) + 1;
+    (line, column)
+}
+
+/// Finds the byte offset and key of the item an error message refers to
+///
+/// Errors inside tagged tables (objects, materials, ...) span the whole table,
+/// so this narrows the span down to the line holding a key or value quoted in the message.
+fn locate(source: &str, span: std::ops::Range<usize>, message: &str) -> (usize, Option<String>) {
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+
+    // keys and values quoted in the message, e.g. `radius` or "big"
+    let quoted: Vec<&str> = message
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .chain(message.split('"').skip(1).step_by(2))
+        .filter(|quote| !quote.is_empty())
+        .collect();
+
+    let mut line_offset = start;
+    for line in source[start..end].split_inclusive('\n') {
+        if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim();
+            if quoted
+                .iter()
+                .any(|&quote| key == quote || value.contains(quote))
+            {
+                let indent = line.len() - line.trim_start().len();
+                let offset = line_offset + indent;
+                return (offset, key_at(source, offset));
+            }
+        }
+        line_offset += line.len();
+    }
+
+    // a missing field has no line of its own, so point at its table instead
+    let key = key_at(source, start);
+    match message.strip_prefix("missing field `") {
+        Some(rest) => {
+            let field = rest.split('`').next().unwrap_or_default();
+            (start, key.map(|key| format!("{key}.{field}")))
+        }
+        None => (start, key),
+    }
+}
+
+/// Guesses the dotted key at the byte `offset` into `source`
+///
+/// Combines the nearest table header at or above the offset with the key assigned on the offending line, if any.
+fn key_at(source: &str, offset: usize) -> Option<String> {
+    let offset = offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |nl| nl + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |nl| offset + nl);
+    let line = source[line_start..line_end].trim();
+
+    // the key assigned on this line, e.g. `radius` in `radius = 1.0`
+    let local_key = line
+        .split_once('=')
+        .map(|(key, _)| key.trim().to_string())
+        .filter(|key| !key.starts_with('['));
+
+    // the nearest header, e.g. `materials.ground` in `[materials.ground]`
+    let header = table_path(&source[..line_end]);
+
+    match (header, local_key) {
+        (Some(header), Some(key)) => Some(format!("{header}.{key}")),
+        (header, key) => header.or(key),
+    }
+}
+
+/// Returns the key of the table opened by the last header in `source`, if any
+///
+/// Tables in arrays of tables are indexed, e.g. `objects[1]` for the second `[[objects]]`.
+fn table_path(source: &str) -> Option<String> {
+    let mut counts = BTreeMap::new();
+    let mut path = None;
+
+    for line in source.lines().map(str::trim) {
+        let name = line.trim_matches(|c| c == '[' || c == ']').trim();
+        // values of multi-line arrays also start with `[`, but aren't keys
+        if !line.starts_with('[') || !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            continue;
+        }
+        if line.starts_with("[[") {
+            counts
+                .entry(name)
+                .and_modify(|count| *count += 1)
+                .or_insert(0);
+        }
+
+        let mut indexed = String::new();
+        for (end, _) in name.match_indices('.').chain([(name.len(), "")]) {
+            let start = name[..end].rfind('.').map_or(0, |dot| dot + 1);
+            if !indexed.is_empty() {
+                indexed.push('.');
+            }
+            indexed.push_str(&name[start..end]);
+            if let Some(count) = counts.get(&name[..end]) {
+                indexed.push_str(&format!("[{count}]"));
+            }
+        }
+        path = Some(indexed);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn parse(source: &str) -> Result<SceneFile, SceneFileError> {
+        SceneFile::parse(PathBuf::from("test.toml"), source.to_string())
+    }
+
+    #[test]
+    fn example_scenes() {
+        for entry in std::fs::read_dir("resources/scenes").unwrap() {
+            let path = entry.unwrap().path();
+            let scene = SceneFile::load(&path).unwrap_or_else(|why| panic!("{why}"));
+            let geometry = scene.geometry().unwrap_or_else(|why| panic!("{why}"));
+            assert!(!geometry.is_empty(), "{path:?} should contain objects");
+            scene.environment().unwrap_or_else(|why| panic!("{why}"));
+        }
+    }
+
+    #[test]
+    fn light_power() {
+        let scene = parse(
+            "[materials.bulb]\ntype = \"diffuse_light\"\nalbedo = 1.0\npower = 100.0\ntwo_sided = false\n\n[[objects]]\ntype = \"quad\"\npoints = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [2.0, 0.0, 2.0], [0.0, 0.0, 2.0]]\nmaterial = \"bulb\"\n\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 5.0, 0.0]\nradius = 1.0\nmaterial = \"bulb\"\n",
+        )
+        .unwrap();
+        let geometry = scene.geometry().unwrap();
+
+        // each object spreads the same power over its own area
+        let brightness = |object: &Arc<dyn Hittable>| match object.hit(
+            &Ray::new(Vec3A::new(0.5, 10.0, 0.5), -Vec3A::Y, 0.0),
+            0.001,
+            f32::INFINITY,
+        ) {
+            Some(rec) => match rec.material.as_ref() {
+                Material::DiffuseLight { brightness, .. } => *brightness,
+                _ => panic!("expected a light"),
+            },
+            None => panic!("expected a hit"),
+        };
+        assert_eq!(
+            brightness(&geometry[0]),
+            Power::Watts(100.0).brightness(4.0, false)
+        );
+        assert_eq!(
+            brightness(&geometry[1]),
+            Power::Watts(100.0).brightness(4.0 * std::f32::consts::PI, false)
+        );
+
+        let both = parse("[materials.bulb]\ntype = \"diffuse_light\"\nalbedo = 1.0\npower = 1.0\nbrightness = 1.0\n\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"bulb\"\n");
+        assert!(both.unwrap().geometry().is_err());
+    }
+
+    #[test]
+    fn delta_lights() {
+        let scene = parse(
+            "[[lights]]\ntype = \"point\"\nposition = [0.0, 2.0, 0.0]\nintensity = 8.0\n\n[[lights]]\ntype = \"spot\"\nposition = [0.0, 1.0, 0.0]\nlook_at = [0.0, 0.0, 0.0]\ntotal_width = 30.0\n",
+        )
+        .unwrap();
+        let lights = scene.lights();
+        assert_eq!(lights.len(), 2);
+
+        let point = lights[0].sample_towards(Vec3A::ZERO).unwrap();
+        assert_eq!(point.irradiance, Color::splat(2.0));
+        // without a falloff, the spot light's edge is sharp
+        let spot = |x: f32| lights[1].sample_towards(Vec3A::new(x, 0.0, 0.0)).is_some();
+        assert!(spot(29f32.to_radians().tan()));
+        assert!(!spot(31f32.to_radians().tan()));
+
+        assert!(parse("[[lights]]\ntype = \"spot\"\nposition = [0.0, 1.0, 0.0]\n").is_err());
+    }
+
+    #[test]
+    fn camera_projection() {
+        assert_eq!(
+            parse("").unwrap().camera().projection(),
+            Projection::Perspective { vert_fov: 20.0 }
+        );
+        let scene =
+            parse("[camera.projection]\ntype = \"fisheye\"\nmapping = \"equisolid\"\n").unwrap();
+        assert_eq!(
+            scene.camera().projection(),
+            Projection::Fisheye {
+                fov: 180.0,
+                mapping: FisheyeMapping::Equisolid
+            }
+        );
+        let scene = parse("[camera.projection]\ntype = \"cube_map\"\n").unwrap();
+        assert_eq!(scene.camera().projection(), Projection::CubeMap);
+
+        assert!(parse("[camera.projection]\ntype = \"orthographic\"\n").is_err());
+    }
+
+    #[test]
+    fn shutter_times() {
+        // a still camera renders a single instant
+        let camera = parse("[camera]\nshutter_time = [0.5, 0.5]\n")
+            .unwrap()
+            .camera();
+        let mut rng = rand::thread_rng();
+        assert_eq!(camera.get_ray(0.5, 0.5, &mut rng).unwrap().time, 0.5);
+
+        match parse("[camera]\nvert_fov = 40.0\nshutter_time = [1.0, 0.0]\n") {
+            Err(SceneFileError::Parse { line, key, .. }) => {
+                assert_eq!(line, 3);
+                assert_eq!(key.as_deref(), Some("camera.shutter_time"));
+            }
+            Err(why) => panic!("expected a parse error, got {why}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let res = parse(
+            "[camera]\nvert_fov = 40.0\n\n[[objects]]\ntype = \"sphere\"\nradius = \"big\"\n",
+        );
+        match res {
+            Err(SceneFileError::Parse { line, key, .. }) => {
+                assert_eq!(line, 6, "expected the error on the `radius` line");
+                assert_eq!(key.as_deref(), Some("objects[0].radius"));
+            }
+            Err(why) => panic!("expected a parse error, got {why}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+
+        // later tables of an array are indexed past the sub-tables of earlier ones
+        let res = parse(
+            "[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\n[objects.material]\ntype = \"lambertian\"\nalbedo = 0.5\n\n[[objects]]\ntype = \"sphere\"\nradius = \"big\"\n",
+        );
+        match res {
+            Err(SceneFileError::Parse { line, key, .. }) => {
+                assert_eq!(line, 12, "expected the error on the `radius` line");
+                assert_eq!(key.as_deref(), Some("objects[1].radius"));
+            }
+            Err(why) => panic!("expected a parse error, got {why}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn missing_environment() {
+        let scene =
+            parse("[environment]\ntype = \"image\"\npath = \"missing.hdr\"\nrotation = 90.0\n")
+                .unwrap();
+        match scene.environment() {
+            Err(SceneFileError::Parse { line, key, .. }) => {
+                assert_eq!(line, 1, "expected the error at the `environment` table");
+                assert_eq!(key.as_deref(), Some("environment"));
+            }
+            Err(why) => panic!("expected a parse error, got {why}"),
+            Ok(_) => panic!("expected a parse error"),
+        }
+        assert!(parse("").unwrap().environment().unwrap().is_none());
+
+        let sky =
+            parse("[environment]\ntype = \"sky\"\nsun_direction = [1.0, 1.0, 0.0]\n").unwrap();
+        let sky = sky
+            .environment()
+            .unwrap()
+            .expect("the sky should be parsed");
+        assert!(sky.is_light());
+    }
+
+    #[test]
+    fn shared_instances() {
+        let scene = parse(
+            "[[geometry.box]]\ntype = \"quad_box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\nmaterial = { type = \"lambertian\", albedo = 0.5 }\n\n[[objects]]\ntype = \"instance\"\ngeometry = \"box\"\nrotate = [{ axis = [0.0, 1.0, 0.0], degrees = 45.0 }]\n\n[[objects]]\ntype = \"instance\"\ngeometry = \"box\"\ntranslate = [5.0, 0.0, 0.0]\n",
+        )
+        .unwrap();
+        let objects = scene.geometry().unwrap();
+        assert_eq!(objects.len(), 2);
+
+        // the rotated box reaches past the transformed min and max corners, z = 0 for both
+        let bbox = objects[0].bounding_box(0.0, 1.0).unwrap();
+        assert!((bbox.max.z - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+        let tree = Tree::new(objects, 0.0, 1.0);
+        let ray = crate::ray::Ray::new(Vec3A::new(-5.0, 0.5, 0.5), Vec3A::X, 0.0);
+        let rec = tree.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((rec.point.x - 0.5).abs() < 1e-4, "hit at {}", rec.point);
+        assert!(rec
+            .normal
+            .abs_diff_eq(Vec3A::new(-1.0, 0.0, 1.0).normalize(), 1e-4));
+
+        // both instances share the geometry built once
+        let mut builder = Builder {
+            desc: &scene.desc,
+            base_dir: Path::new(""),
+            textures: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            geometry: BTreeMap::new(),
+            pending: Vec::new(),
+            pending_geometry: Vec::new(),
+        };
+        let instances: Vec<_> = scene
+            .desc
+            .objects
+            .iter()
+            .map(|object| builder.objects(object.get_ref(), "objects").unwrap())
+            .collect();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(Arc::strong_count(&builder.geometry["box"]), 3);
+    }
+
+    #[test]
+    fn undefined_material() {
+        let scene = parse(
+            "[materials.red]\ntype = \"lambertian\"\nalbedo = [1.0, 0.0, 0.0]\n\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"blue\"\n",
+        )
+        .unwrap();
+        match scene.geometry() {
+            Err(SceneFileError::Parse {
+                line, key, message, ..
+            }) => {
+                assert_eq!(line, 9, "expected the error on the `material` line");
+                assert_eq!(key.as_deref(), Some("objects[0].material"));
+                assert!(message.contains("blue"), "unexpected message: {message}");
+            }
+            Err(why) => panic!("expected a parse error, got {why}"),
+            Ok(_) => panic!("expected an undefined material error"),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Spectral rendering with hero wavelength sampling
+//!
+//! In spectral mode, each camera sample carries three [Wavelengths], spread evenly over the visible range
+//! from a randomly chosen hero wavelength, see [Wilkie et al.'s "Hero Wavelength Spectral Sampling"](https://cgg.mff.cuni.cz/~wilkie/Website/EGSR_14_files/WNDWH14HWSS.pdf).
+//! The channels of a [Color] then hold spectral quantities at those wavelengths instead of RGB values,
+//! so that materials work the same in both modes once their RGB inputs are [upsampled](Wavelengths::upsample).
+//!
+//! RGB colors are upsampled with [Smits' "An RGB-to-Spectrum Conversion for Reflectances"](https://www.cs.utah.edu/~bes/papers/color/),
+//! and samples are converted back to RGB at the film through the CIE 1931 color matching functions,
+//! approximated following [Wyman et al.'s "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"](https://jcgt.org/published/0002/02/01/).
+
+use std::sync::OnceLock;
+
+use glam::{Mat3A, Vec3A};
+
+use crate::color::{colors, Color};
+
+/// Shortest sampled wavelength, in nanometers
+pub const LAMBDA_MIN: f32 = 380.0;
+/// Longest sampled wavelength, in nanometers
+pub const LAMBDA_MAX: f32 = 720.0;
+/// Wavelength at which quantities are evaluated outside of spectral mode, the Fraunhofer d line
+pub const REFERENCE_WAVELENGTH: f32 = 587.6;
+
+/// Number of bins in the Smits basis spectra
+const SMITS_BINS: usize = 10;
+
+/// Smits' basis spectra, sampled in equal bins from [LAMBDA_MIN] to [LAMBDA_MAX]
+mod smits {
+    pub const WHITE: [f32; 10] = [
+        1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+    ];
+    pub const CYAN: [f32; 10] = [
+        0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+    ];
+    pub const MAGENTA: [f32; 10] = [
+        1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+    ];
+    pub const YELLOW: [f32; 10] = [
+        0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+    ];
+    pub const RED: [f32; 10] = [
+        0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+    ];
+    pub const GREEN: [f32; 10] = [
+        0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+    ];
+    pub const BLUE: [f32; 10] = [
+        1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+    ];
+}
+
+/// Converts CIE XYZ to linear sRGB
+pub(crate) const XYZ_TO_SRGB: Mat3A = Mat3A::from_cols_array(&[
+    3.240_454_2,
+    -0.969_266,
+    0.055_643_4,
+    -1.537_138_5,
+    1.876_010_8,
+    -0.204_025_9,
+    -0.498_531_4,
+    0.041_556,
+    1.057_225_2,
+]);
+
+/// A piecewise Gaussian with separate widths below and above its `center`
+#[inline]
+fn gaussian(lambda: f32, center: f32, below: f32, above: f32) -> f32 {
+    let t = (lambda - center) / if lambda < center { below } else { above };
+    (-0.5 * t * t).exp()
+}
+
+/// Returns the CIE 1931 color matching functions at `lambda` nanometers
+fn color_matching(lambda: f32) -> Vec3A {
+    Vec3A::new(
+        1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+/// Returns the linear sRGB color of the constant unit spectrum, used to white balance the film
+///
+/// Without it, upsampled white would come out slightly pink,
+/// as the constant spectrum's white point differs from sRGB's D65.
+fn white_point() -> Color {
+    static WHITE_POINT: OnceLock<Color> = OnceLock::new();
+    *WHITE_POINT.get_or_init(|| {
+        let steps = 1000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f32;
+        let xyz: Vec3A = (0..steps)
+            .map(|i| color_matching(LAMBDA_MIN + (i as f32 + 0.5) * step) * step)
+            .sum();
+        XYZ_TO_SRGB * xyz
+    })
+}
+
+/// The wavelengths, in nanometers, that a camera sample carries in spectral mode
+///
+/// The first is the hero wavelength; the others are offset by a third of the sampled range each.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wavelengths {
+    lambda: Vec3A,
+    /// Whether only the hero wavelength is left
+    hero_only: bool,
+}
+
+impl Wavelengths {
+    /// Samples wavelengths from a uniform random number in `[0, 1)`
+    pub fn sample(u: f32) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let lambda = (u + Vec3A::new(0.0, 1.0, 2.0) / 3.0).fract() * range + LAMBDA_MIN;
+        Self {
+            lambda,
+            hero_only: false,
+        }
+    }
+
+    /// Returns the hero wavelength
+    #[inline]
+    pub fn hero(self) -> f32 {
+        self.lambda.x
+    }
+
+    /// Drops all but the hero wavelength, for interactions whose direction depends on the wavelength
+    ///
+    /// Returns the remaining wavelengths, and the weight to multiply the spectral throughput with,
+    /// which makes up for the dropped wavelengths.
+    pub fn terminate_secondary(self) -> (Self, Color) {
+        if self.hero_only {
+            (self, colors::WHITE)
+        } else {
+            let wavelengths = Self {
+                hero_only: true,
+                ..self
+            };
+            (wavelengths, Color::new(3.0, 0.0, 0.0))
+        }
+    }
+
+    /// Returns the spectrum of the linear RGB color `rgb` at these wavelengths
+    ///
+    /// The conversion is linear, so it also applies to emitted light brighter than white.
+    pub fn upsample(self, rgb: Color) -> Color {
+        if rgb == colors::BLACK {
+            return colors::BLACK;
+        }
+        Color::from_array(self.lambda.to_array().map(|lambda| smits(rgb, lambda)))
+    }
+
+    /// Returns the values at these wavelengths of a quantity sampled at 650, 550, and 450 nm,
+    /// given as the red, green, and blue channels of `rgb`
+    ///
+    /// The samples are interpolated linearly, and extended as constants beyond either end.
+    pub fn interpolate(self, rgb: Color) -> Color {
+        Color::from_array(self.lambda.to_array().map(|lambda| {
+            let t = ((650.0 - lambda) / 100.0).clamp(0.0, 2.0);
+            if t < 1.0 {
+                rgb.x + t * (rgb.y - rgb.x)
+            } else {
+                rgb.y + (t - 1.0) * (rgb.z - rgb.y)
+            }
+        }))
+    }
+
+    /// Converts the spectral `radiance` at these wavelengths into a linear sRGB color
+    ///
+    /// Saturated colors outside of the sRGB gamut come out with negative channels.
+    pub fn to_rgb(self, radiance: Color) -> Color {
+        // each wavelength is sampled with a uniform density over the range
+        let weight = (LAMBDA_MAX - LAMBDA_MIN) / 3.0;
+        let xyz: Vec3A = (0..3)
+            .map(|i| radiance[i] * color_matching(self.lambda[i]) * weight)
+            .sum();
+        XYZ_TO_SRGB * xyz / white_point()
+    }
+}
+
+/// Returns the value at `lambda` nanometers of the Smits spectrum of `rgb`
+fn smits(rgb: Color, lambda: f32) -> f32 {
+    let bin = ((lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * SMITS_BINS as f32) as usize;
+    let bin = bin.min(SMITS_BINS - 1);
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+
+    // the smallest channel sets the white part, the middle one the secondary color,
+    // and the remainder is made up of the largest primary
+    if r <= g && r <= b {
+        r * smits::WHITE[bin]
+            + if g <= b {
+                (g - r) * smits::CYAN[bin] + (b - g) * smits::BLUE[bin]
+            } else {
+                (b - r) * smits::CYAN[bin] + (g - b) * smits::GREEN[bin]
+            }
+    } else if g <= r && g <= b {
+        g * smits::WHITE[bin]
+            + if r <= b {
+                (r - g) * smits::MAGENTA[bin] + (b - r) * smits::BLUE[bin]
+            } else {
+                (b - g) * smits::MAGENTA[bin] + (r - b) * smits::RED[bin]
+            }
+    } else {
+        b * smits::WHITE[bin]
+            + if r <= g {
+                (r - b) * smits::YELLOW[bin] + (g - r) * smits::GREEN[bin]
+            } else {
+                (g - b) * smits::YELLOW[bin] + (r - g) * smits::RED[bin]
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Returns the mean RGB color of the upsampled `rgb` over many wavelength samples
+    fn round_trip(rgb: Color) -> Color {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let samples = 20_000;
+        let sum: Vec3A = (0..samples)
+            .map(|_| {
+                let wavelengths = Wavelengths::sample(rng.gen());
+                wavelengths.to_rgb(wavelengths.upsample(rgb))
+            })
+            .sum();
+        sum / samples as f32
+    }
+
+    #[test]
+    fn white_round_trip() {
+        let white = round_trip(Color::splat(0.5));
+        assert!(
+            white.abs_diff_eq(Color::splat(0.5), 0.02),
+            "expected gray, found {white}"
+        );
+    }
+
+    #[test]
+    fn primaries_keep_their_hue() {
+        for (index, primary) in [Vec3A::X, Vec3A::Y, Vec3A::Z].into_iter().enumerate() {
+            let rgb = round_trip(primary);
+            assert_eq!(rgb[index], rgb.max_element(), "{primary} came out as {rgb}");
+        }
+    }
+
+    #[test]
+    fn secondary_wavelengths_terminate_once() {
+        let (wavelengths, weight) = Wavelengths::sample(0.5).terminate_secondary();
+        assert_eq!(weight, Color::new(3.0, 0.0, 0.0));
+        assert_eq!(wavelengths.terminate_secondary().1, colors::WHITE);
+    }
+}
@@ -5,20 +5,29 @@
 use glam::Vec3A;
 
 pub use crate::color::Color as SolidColor;
-use crate::color::Color;
+use crate::{color::Color, hittables::HitRecord};
 
 pub use self::image::*;
 pub use self::noise::*;
 pub use checkered::*;
+pub use vertex::*;
 
 pub mod checkered;
 pub mod image;
 pub mod noise;
+pub mod vertex;
 
 /// Behavior of a texture
 pub trait Texture: Send + Sync {
     /// Returns the color value at the uv coordinates or point for the texture
     fn color(&self, u: f32, v: f32, point: Vec3A) -> Color;
+
+    /// Returns the color value at the surface hit by `rec`
+    ///
+    /// Textures using other attributes of the hit than its uv coordinates and point override this.
+    fn color_at(&self, rec: &HitRecord) -> Color {
+        self.color(rec.u, rec.v, rec.point)
+    }
 }
 
 impl std::fmt::Debug for dyn Texture {
@@ -37,3 +46,13 @@ impl Texture for SolidColor {
         *self
     }
 }
+
+impl Texture for std::sync::Arc<dyn Texture> {
+    fn color(&self, u: f32, v: f32, point: Vec3A) -> Color {
+        self.as_ref().color(u, v, point)
+    }
+
+    fn color_at(&self, rec: &HitRecord) -> Color {
+        self.as_ref().color_at(rec)
+    }
+}
@@ -24,14 +24,66 @@ impl ImageMap {
     /// Missing texture sourced from [The GMod fandom wiki](https://gmod.fandom.com/wiki/Missing_textures),
     /// available under CC-BY-SA
     pub fn new(file_path: std::path::PathBuf) -> Self {
-        match image::open(&file_path) {
-            Ok(image) => Self { image },
+        match Self::open(&file_path) {
+            Ok(image_map) => image_map,
             Err(why) => {
                 eprintln!("Failed to open {file_path:?}: {why}");
                 Self::default()
             }
         }
     }
+
+    /// Creates a new [ImageMap] from the image located at `file_path`
+    ///
+    /// Unlike [ImageMap::new], returns the error instead of falling back to the "missing" texture.
+    /// HDR images keep their full range, see [ImageMap::image].
+    pub fn open(file_path: impl AsRef<std::path::Path>) -> image::ImageResult<Self> {
+        let file_path = file_path.as_ref();
+
+        // the generic decoder tone maps Radiance HDR images down to 8 bits
+        #[cfg(feature = "hdr")]
+        if image::ImageFormat::from_path(file_path).ok() == Some(image::ImageFormat::Hdr) {
+            return Self::open_radiance_hdr(file_path);
+        }
+
+        image::open(file_path).map(|image| Self { image })
+    }
+
+    /// Decodes the Radiance HDR image located at `file_path` into 32-bit floats
+    #[cfg(feature = "hdr")]
+    fn open_radiance_hdr(file_path: &std::path::Path) -> image::ImageResult<Self> {
+        let file = std::fs::File::open(file_path).map_err(image::ImageError::IoError)?;
+        let decoder = image::codecs::hdr::HdrDecoder::new(std::io::BufReader::new(file))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?;
+
+        let buffer = image::Rgb32FImage::from_raw(
+            metadata.width,
+            metadata.height,
+            pixels.into_iter().flat_map(|pixel| pixel.0).collect(),
+        );
+        match buffer {
+            Some(buffer) => Ok(Self {
+                image: DynamicImage::ImageRgb32F(buffer),
+            }),
+            None => Err(image::ImageError::Decoding(
+                image::error::DecodingError::new(
+                    image::ImageFormat::Hdr.into(),
+                    "image data doesn't match its dimensions",
+                ),
+            )),
+        }
+    }
+
+    /// Creates a new [ImageMap] from an already decoded `image`
+    pub fn from_image(image: DynamicImage) -> Self {
+        Self { image }
+    }
+
+    /// Returns the decoded image, at its original precision
+    pub fn image(&self) -> &DynamicImage {
+        &self.image
+    }
 }
 
 impl Default for ImageMap {
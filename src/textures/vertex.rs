@@ -0,0 +1,22 @@
+//! A texture interpolating colors given at the vertices of a mesh.
+
+use glam::Vec3A;
+
+use crate::{color::Color, hittables::HitRecord, textures::Texture};
+
+/// The colors at the vertices of a [TriangleMesh](crate::hittables::TriangleMesh), interpolated across its triangles
+///
+/// The colors are stored in the mesh, see [TriangleMesh::with_colors](crate::hittables::TriangleMesh::with_colors),
+/// so a single texture serves every triangle. Surfaces without vertex colors are white.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VertexColors;
+
+impl Texture for VertexColors {
+    fn color(&self, _u: f32, _v: f32, _point: Vec3A) -> Color {
+        Color::ONE
+    }
+
+    fn color_at(&self, rec: &HitRecord) -> Color {
+        rec.vertex_color.unwrap_or(Color::ONE)
+    }
+}
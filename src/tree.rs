@@ -1,65 +1,190 @@
-//! A SAH-based Bounding Volume Hierarchy
+//! A SAH-based, 4-wide Bounding Volume Hierarchy
 
 use std::sync::Arc;
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use glam::{Vec3A, Vec4};
+
 use crate::{
     bounds::BoundingBox,
-    hittables::{HitRecord, Hittable},
+    hittables::{HitRecord, Hittable, HittableList},
     ray::Ray,
     utils::arena::{Arena, ArenaIndex},
 };
 
+/// The number of children of each [TreeNode], one per SIMD lane of a [Vec4]
+const WIDTH: usize = 4;
+
+/// Traversal stacks up to this size live on the call stack, deeper trees allocate theirs per ray
+const INLINE_STACK_SIZE: usize = 64;
+
+/// A child slot of a [TreeNode]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeChild {
+    /// An unused slot
+    Empty,
+    /// A terminal child holding the items in the range `start..end` of the [Tree]'s items
+    Leaf { start: usize, end: usize },
+    /// An interior child, by its index in the tree's [Arena]
+    Node(ArenaIndex),
+}
+
 /// The discrete element making up the [Tree].
 ///
+/// Each node has up to [WIDTH] children, whose bounding boxes are stored as a structure of arrays
+/// so that a ray is tested against all of them at once.
 /// [TreeNode]s do not directly contain their children nodes, instead holding indices into the [Tree]'s [Arena].
 /// This removes the [Box]-related complications from typical recursive graph structures in Rust.
-pub enum TreeNode {
-    /// A terminal node containing one or more [Hittable]s
-    Leaf {
-        /// The combined [BoundingBox] of all contained items
-        ///
-        /// is `None` when every contained item has no bounding box
-        bbox: Option<BoundingBox>,
-        /// The set of items with which to later intersect
-        items: Vec<Arc<dyn Hittable>>,
-    },
-    /// A node that holds the indices into its Tree's [Arena]
-    Interior {
-        /// The combined [BoundingBox] of the two child nodes
-        ///
-        /// is `None` when both children have no bounding box
-        bbox: Option<BoundingBox>,
-        /// The left child's index in the tree's [Arena]
-        left: ArenaIndex,
-        /// The right child's index in the tree's [Arena]
-        right: ArenaIndex,
-    },
+#[derive(Debug)]
+pub struct TreeNode {
+    /// The minimum corners of the children's bounding boxes, one lane per child for each axis
+    ///
+    /// Unbounded children span all of space, and empty slots are masked out by `occupied`
+    min: [Vec4; 3],
+    /// The maximum corners of the children's bounding boxes, one lane per child for each axis
+    max: [Vec4; 3],
+    /// A bitmask of the lanes holding children
+    occupied: u32,
+    /// The children themselves
+    children: [TreeChild; WIDTH],
 }
 
 impl TreeNode {
-    /// Returns the [BoundingBox] of the [TreeNode]
+    /// Tests the ray against the bounding boxes of all children at once
     ///
-    /// Since both enum variants have a bounding box, this functions helps reduce the amount of code duplication
+    /// Returns the distances at which the ray enters each child, and a bitmask of the children it hits.
+    /// The slab test is the same as [BoundingBox::hit_with_inv], with each lane holding a different box.
     #[inline]
-    fn get_bbox(&self) -> Option<BoundingBox> {
-        *match self {
-            TreeNode::Leaf { bbox, .. } => bbox,
-            TreeNode::Interior { bbox, .. } => bbox,
+    fn hit_children(
+        &self,
+        origin: &[Vec4; 3],
+        inv_dir: &[Vec4; 3],
+        t_min: f32,
+        t_max: f32,
+    ) -> (Vec4, u32) {
+        let mut t_near = Vec4::splat(t_min);
+        let mut t_far = Vec4::splat(t_max);
+        for axis_idx in 0..3 {
+            let t0 = (self.min[axis_idx] - origin[axis_idx]) * inv_dir[axis_idx];
+            let t1 = (self.max[axis_idx] - origin[axis_idx]) * inv_dir[axis_idx];
+            t_near = t_near.max(t0).min(t_near.max(t1));
+            t_far = t_far.min(t0).max(t_far.min(t1));
         }
+        (t_near, t_near.cmple(t_far).bitmask() & self.occupied)
     }
 }
 
 /// An acceleration structure using arena allocation and the surface area hueristic (SAH) splitting method.
 ///
+/// The tree is built as a binary tree, then collapsed into a [WIDTH]-wide tree for faster traversal.
 /// See [Arena] for more information on the allocator.
 pub struct Tree {
     /// The arena allocator that contains the tree's [TreeNode]s
     arena: Arena<TreeNode>,
     /// The index into the arena of the root [TreeNode]
     root: ArenaIndex,
+    /// The items of all leaves, each leaf referencing a contiguous range
+    items: Vec<Arc<dyn Hittable>>,
+    /// The combined [BoundingBox] of all items
+    bbox: Option<BoundingBox>,
+    /// The lights among the items, collected before the items are split into leaves
+    lights: HittableList,
+    /// The traversal stack size needed to visit every node at once
+    stack_size: usize,
+}
+
+/// Options for building a [Tree]
+///
+/// By default, nodes are split by binning their items' centroids along the longest axis
+/// and choosing the split with the lowest surface area heuristic (SAH) cost.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TreeConfig {
+    /// The overlap threshold for trying spatial splits, if enabled
+    spatial_split_alpha: Option<f32>,
+}
+
+impl TreeConfig {
+    /// Enables spatial splits, as in the [SBVH by Stich et al.](https://www.nvidia.com/docs/IO/77714/sbvh.pdf)
+    ///
+    /// When the children of a node's best centroid split overlap by more than `alpha` times the root's surface area,
+    /// the node also tries splitting space itself: items straddling the split plane are referenced by both children,
+    /// with their bounds clipped to each side. This helps with large, thin, or heavily overlapping items.
+    /// Smaller values try more spatial splits; the paper suggests `1e-5`, while `1.0` only allows them near the root.
+    pub fn with_spatial_splits(mut self, alpha: f32) -> Self {
+        self.spatial_split_alpha = Some(alpha);
+        self
+    }
+}
+
+/// State shared by all nodes while building a [Tree]
+struct BuildContext {
+    config: TreeConfig,
+    time0: f32,
+    time1: f32,
+    /// The surface area of the root's bounding box, which spatial split overlaps are relative to
+    root_area: f32,
+}
+
+/// Nodes with at least this many items fold their bins in parallel chunks, if enabled
+#[cfg(feature = "parallel")]
+const PARALLEL_BINNING_THRESHOLD: usize = 8192;
+/// Nodes with at least this many items build their children as parallel tasks, if enabled
+///
+/// Smaller subtrees are built serially, since spawning a task would cost more than it saves.
+const SUBTREE_TASK_THRESHOLD: usize = 256;
+
+/// Folds `items` into an accumulator, in parallel chunks for big nodes if enabled
+///
+/// `merge` must give the same result in any order for the build to be deterministic,
+/// which holds for [BoundingBox] unions and item counts.
+fn fold_items<T: Copy + Send + Sync>(
+    items: &[ItemInfo],
+    init: T,
+    fold: impl Fn(T, &ItemInfo) -> T + Sync,
+    merge: impl Fn(T, T) -> T + Sync,
+) -> T {
+    #[cfg(feature = "parallel")]
+    if items.len() >= PARALLEL_BINNING_THRESHOLD {
+        return items
+            .par_chunks(PARALLEL_BINNING_THRESHOLD / 8)
+            .map(|chunk| chunk.iter().fold(init, &fold))
+            .reduce(|| init, &merge);
+    }
+    let _ = merge;
+    items.iter().fold(init, fold)
+}
+
+/// Runs both closures, as parallel tasks when `parallel` is true and enabled
+fn join<A: Send, B: Send>(
+    parallel: bool,
+    a: impl FnOnce() -> A + Send,
+    b: impl FnOnce() -> B + Send,
+) -> (A, B) {
+    #[cfg(feature = "parallel")]
+    if parallel {
+        return rayon::join(a, b);
+    }
+    let _ = parallel;
+    (a(), b())
+}
+
+/// A node of a [Tree] under construction, owning its children
+///
+/// Subtrees are built independently, possibly in parallel, then flattened into the [Arena] in a fixed order.
+enum BuildNode {
+    Leaf(Vec<ItemInfo>),
+    Interior {
+        bbox: BoundingBox,
+        left: Box<BuildNode>,
+        right: Box<BuildNode>,
+    },
 }
 
 /// Holds the precomputed information of an item necessary to calculate the SAH
+///
+/// With spatial splits, the same item may be referenced several times, each with its bounding box clipped to a node.
 #[derive(Clone)]
 struct ItemInfo {
     /// The bounding box of the contained item
@@ -70,6 +195,20 @@ struct ItemInfo {
     item: Arc<dyn Hittable>,
 }
 
+impl ItemInfo {
+    /// Creates a reference to `item`, with its bounding box clipped to `bbox`
+    fn clipped(item: &Arc<dyn Hittable>, bbox: BoundingBox) -> Self {
+        Self {
+            bbox: Some(bbox),
+            centroid: Some(bbox.centroid()),
+            item: Arc::clone(item),
+        }
+    }
+}
+
+/// The number of bins used for both centroid and spatial splits
+const NUM_BINS: usize = 16;
+
 /// Holds the metadata of items being binned for SAH splitting
 #[derive(Debug, Default, Clone, Copy)]
 struct Bin {
@@ -79,198 +218,514 @@ struct Bin {
     count: usize,
 }
 
+impl Bin {
+    /// Returns the bin holding the items of both bins
+    fn merge(self, other: Bin) -> Bin {
+        Bin {
+            bbox: self.bbox.union(other.bbox),
+            count: self.count + other.count,
+        }
+    }
+
+    /// Returns the bin's contribution to the SAH cost, its surface area times its item count
+    fn cost(&self) -> f32 {
+        self.count as f32 * surface_area(&self.bbox)
+    }
+}
+
 impl std::fmt::Display for Bin {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} items in {:?}", self.count, self.bbox)
     }
 }
 
-impl Tree {
-    /// Adds a new leaf node to the Tree, returning the index for use in creation and intersection
-    #[inline]
-    fn new_leaf(&mut self, info: Vec<ItemInfo>) -> ArenaIndex {
-        self.arena.add(TreeNode::Leaf {
-            items: info.iter().map(|info| info.item.clone()).collect(),
-            bbox: info
-                .iter()
-                .filter_map(|info| info.bbox)
-                .reduce(|acc, b| acc.union(b)),
-        })
-    }
+/// Merges each pair of bins from two binnings of disjoint items
+fn merge_bins(a: [Bin; NUM_BINS], b: [Bin; NUM_BINS]) -> [Bin; NUM_BINS] {
+    std::array::from_fn(|idx| a[idx].merge(b[idx]))
+}
 
-    /// Returns the [BoundingBox] of the node at the given index `idx`, if it has one
-    #[inline]
-    fn get_bbox(&self, idx: ArenaIndex) -> Option<BoundingBox> {
-        self.arena[idx].get_bbox()
+/// Returns the surface area of `bbox`, or zero if it's empty, like the default [BoundingBox]
+fn surface_area(bbox: &BoundingBox) -> f32 {
+    if bbox.min.cmple(bbox.max).all() {
+        bbox.surface_area()
+    } else {
+        0.0
     }
+}
 
-    /// Creates a new interior node by splitting the given items into child nodes.
-    ///
-    /// If the SAH-computed split cost is less than simply going through all the items, do a SAH-based split and recurse.
-    /// Otherwise, do a simpler sort-and-half then recurse. If all items are unbounded, make a leaf node directly.
-    /// This function short-circuits directly into creating a leaf node with less than 4 items.
-    fn new_interior(&mut self, mut items: Vec<ItemInfo>) -> ArenaIndex {
-        let num_items = items.len();
-
-        // given few items, make leaf
-        if num_items <= 4 {
-            return self.new_leaf(items);
-        }
+/// Returns the SAH cost of splitting a node of the given surface area into the `left` and `right` bins
+///
+/// Traversing a node costs half as much as intersecting an item.
+fn split_cost(left: &Bin, right: &Bin, area: f32) -> f32 {
+    0.5 + (left.cost() + right.cost()) / area
+}
 
-        // Get bounding_box for all item under this node
-        let total_bbox = items
-            .iter()
-            .filter_map(|item| item.bbox)
-            .reduce(|acc, b| acc.union(b));
+/// Scans the bins forwards and backwards, returning the best plane to split them at
+///
+/// The plane at index `i` lies between bins `i` and `i + 1`. The items entering the left side of a plane are counted by
+/// `left_bins`, and those leaving the right side by `right_bins`, which differ only when items span several bins.
+/// Returns the index of the plane, the cost of splitting there, and the bins of each side.
+fn best_plane(left_bins: &[Bin], right_bins: &[Bin], area: f32) -> Option<(usize, f32, Bin, Bin)> {
+    // forward scan accumulates the bins left of each plane
+    let mut lefts = [Bin::default(); NUM_BINS - 1];
+    let mut acc = Bin::default();
+    for (plane, bin) in left_bins[..NUM_BINS - 1].iter().enumerate() {
+        acc = acc.merge(*bin);
+        lefts[plane] = acc;
+    }
 
-        if let Some(total_bbox) = total_bbox {
-            // If we have some bounding box, then we have some centroids
-            let centroid_bbox = items
-                .iter()
-                .filter_map(|item| item.centroid)
-                .fold(BoundingBox::default(), |bbox, centroid| {
-                    bbox.add_point(centroid)
-                });
+    // backward scan accumulates the bins right of each plane
+    let mut rights = [Bin::default(); NUM_BINS - 1];
+    acc = Bin::default();
+    for (plane, bin) in right_bins[1..].iter().enumerate().rev() {
+        acc = acc.merge(*bin);
+        rights[plane] = acc;
+    }
 
-            // choose axis based on lengths of the surrounding bbox
-            let axis_idx = total_bbox.longest_axis();
+    // splits leaving one side empty make no progress
+    (0..NUM_BINS - 1)
+        .filter(|&plane| lefts[plane].count > 0 && rights[plane].count > 0)
+        .map(|plane| {
+            let cost = split_cost(&lefts[plane], &rights[plane], area);
+            (plane, cost, lefts[plane], rights[plane])
+        })
+        .min_by(|(_, a_cost, ..), (_, b_cost, ..)| a_cost.total_cmp(b_cost))
+}
 
-            // set up bins
-            const NUM_BINS: usize = 16;
-            let mut bins = [Bin::default(); NUM_BINS];
+/// A split of a node's items by which side of a plane their centroids lie on
+struct ObjectSplit {
+    axis_idx: usize,
+    centroid_bbox: BoundingBox,
+    /// The index of the last bin going to the left child
+    bin_idx: usize,
+    cost: f32,
+    /// The surface area of the overlap between the children's bounding boxes
+    overlap: f32,
+}
 
-            /// helper functions to correctly compute index into bins
-            fn comp_bin_idx(off: f32) -> usize {
-                let idx = (NUM_BINS as f32 * off) as usize;
-                idx.clamp(0, NUM_BINS - 1)
+impl ObjectSplit {
+    /// Returns the index of the bin that the item's centroid falls in; unbounded items are kept in the first bin
+    fn bin_idx(centroid_bbox: &BoundingBox, axis_idx: usize, item: &ItemInfo) -> usize {
+        match item.centroid {
+            Some(centroid) => {
+                let off = centroid_bbox.offset(centroid)[axis_idx];
+                ((NUM_BINS as f32 * off) as usize).clamp(0, NUM_BINS - 1)
             }
+            None => 0,
+        }
+    }
 
-            // Compute bin based on how far the item's centroid
-            // is from the min of the bbox of centroids
-            for item in items.iter() {
-                let off = match item.centroid {
-                    Some(centroid) => centroid_bbox.offset(centroid)[axis_idx],
-                    None => (NUM_BINS / 2) as f32,
-                };
-
-                let bin_idx = comp_bin_idx(off);
-                let bin = &mut bins[bin_idx];
+    /// Finds the centroid split with the lowest SAH cost along the given axis, if any split separates the items
+    fn find(items: &[ItemInfo], total_bbox: &BoundingBox, axis_idx: usize) -> Option<Self> {
+        let centroid_bbox = fold_items(
+            items,
+            BoundingBox::default(),
+            |bbox, item| {
+                item.centroid
+                    .map_or(bbox, |centroid| bbox.add_point(centroid))
+            },
+            |a, b| a.union(b),
+        );
+
+        // Compute bin based on how far the item's centroid
+        // is from the min of the bbox of centroids
+        let bins = fold_items(
+            items,
+            [Bin::default(); NUM_BINS],
+            |mut bins, item| {
+                let bin = &mut bins[Self::bin_idx(&centroid_bbox, axis_idx, item)];
                 bin.count += 1;
-                // bin.bbox = bin.bbox.union(item.bbox.unwrap_or_default());
                 if let Some(bbox) = item.bbox {
                     bin.bbox = bin.bbox.union(bbox);
                 }
-            }
+                bins
+            },
+            merge_bins,
+        );
+
+        let (bin_idx, cost, left, right) = best_plane(&bins, &bins, total_bbox.surface_area())?;
+        let overlap = left
+            .bbox
+            .intersection(&right.bbox)
+            .map_or(0.0, |overlap| overlap.surface_area());
+        Some(Self {
+            axis_idx,
+            centroid_bbox,
+            bin_idx,
+            cost,
+            overlap,
+        })
+    }
+
+    /// Splits the items into those going to the left and right children
+    fn partition(&self, items: Vec<ItemInfo>) -> (Vec<ItemInfo>, Vec<ItemInfo>) {
+        items.into_iter().partition(|item| {
+            Self::bin_idx(&self.centroid_bbox, self.axis_idx, item) <= self.bin_idx
+        })
+    }
+}
 
-            // set up costs
-            let mut costs = [f32::MAX; NUM_BINS - 1];
-
-            // Using two scans of the items, we can compute the SAH cost
-            // `SurfaceArea_Left * Num_Left + SurfaceArea_Right * Num_Right`
-            // by splitting on the addition, such that the left operand of
-            // the addition is computed on the forward scan, and the right
-            // operand using the backward scan. We reuse the [Bin] struct
-            // as it holds exactly the info needed for cost computation
-
-            let mut acc = Bin::default();
-            // forward scan uses the first bin up to second-to-last bin
-            for bin_idx in 0..(NUM_BINS - 1) {
-                let bin = bins[bin_idx];
-                if bin.count > 0 {
-                    acc.bbox = acc.bbox.union(bin.bbox);
-                    acc.count += bin.count;
-                    costs[bin_idx] += bin.count as f32 * bin.bbox.surface_area();
+/// A split of a node's space by a plane, referencing items straddling the plane from both children
+struct SpatialSplit {
+    axis_idx: usize,
+    /// The position of the plane along the axis
+    plane: f32,
+    cost: f32,
+    /// The items and bounds of the left child, if no items were split or moved
+    left: Bin,
+    /// The items and bounds of the right child, if no items were split or moved
+    right: Bin,
+}
+
+impl SpatialSplit {
+    /// Finds the spatial split with the lowest SAH cost along the given axis, if any split separates the items
+    ///
+    /// Each item is clipped to every bin it spans, so the bins tightly bound the parts of items inside them.
+    fn find(
+        items: &[ItemInfo],
+        total_bbox: &BoundingBox,
+        axis_idx: usize,
+        ctx: &BuildContext,
+    ) -> Option<Self> {
+        let min = total_bbox.min[axis_idx];
+        let bin_width = (total_bbox.max[axis_idx] - min) / NUM_BINS as f32;
+        if bin_width <= 0.0 {
+            return None;
+        }
+        let bin_idx = |x: f32| (((x - min) / bin_width) as usize).clamp(0, NUM_BINS - 1);
+
+        // the bins' bounds are shared by both scans, but items are counted
+        // where they enter the left side, and where they leave the right side
+        let (entries, exits) = fold_items(
+            items,
+            ([Bin::default(); NUM_BINS], [Bin::default(); NUM_BINS]),
+            |(mut entries, mut exits), info| {
+                let Some(bbox) = info.bbox else {
+                    // unbounded items can't be clipped, and go to the left child
+                    entries[0].count += 1;
+                    exits[0].count += 1;
+                    return (entries, exits);
+                };
+
+                let first = bin_idx(bbox.min[axis_idx]);
+                let last = bin_idx(bbox.max[axis_idx]);
+                for idx in first..=last {
+                    let mut clip = bbox;
+                    clip.min[axis_idx] = clip.min[axis_idx].max(min + idx as f32 * bin_width);
+                    if idx < NUM_BINS - 1 {
+                        clip.max[axis_idx] =
+                            clip.max[axis_idx].min(min + (idx + 1) as f32 * bin_width);
+                    }
+                    if let Some(clipped) =
+                        info.item.clipped_bounding_box(&clip, ctx.time0, ctx.time1)
+                    {
+                        entries[idx].bbox = entries[idx].bbox.union(clipped);
+                        exits[idx].bbox = entries[idx].bbox;
+                    }
+                }
+                entries[first].count += 1;
+                exits[last].count += 1;
+                (entries, exits)
+            },
+            |(entries_a, exits_a), (entries_b, exits_b)| {
+                (
+                    merge_bins(entries_a, entries_b),
+                    merge_bins(exits_a, exits_b),
+                )
+            },
+        );
+
+        let (plane_idx, cost, left, right) =
+            best_plane(&entries, &exits, total_bbox.surface_area())?;
+        Some(Self {
+            axis_idx,
+            plane: min + (plane_idx + 1) as f32 * bin_width,
+            cost,
+            left,
+            right,
+        })
+    }
+
+    /// Splits the items into those going to the left and right children, clipping items straddling the plane
+    ///
+    /// Straddling items are moved entirely into one child instead when that's cheaper, as in the paper's reference unsplitting.
+    fn partition(&self, items: &[ItemInfo], ctx: &BuildContext) -> (Vec<ItemInfo>, Vec<ItemInfo>) {
+        let axis_idx = self.axis_idx;
+        let (mut left_items, mut right_items) = (Vec::new(), Vec::new());
+        let (left_area, right_area) = (
+            surface_area(&self.left.bbox),
+            surface_area(&self.right.bbox),
+        );
+        let (left_count, right_count) = (self.left.count as f32, self.right.count as f32);
+
+        for info in items {
+            let bbox = match info.bbox {
+                Some(bbox)
+                    if bbox.min[axis_idx] < self.plane && bbox.max[axis_idx] > self.plane =>
+                {
+                    bbox
                 }
+                Some(bbox) if bbox.min[axis_idx] >= self.plane => {
+                    right_items.push(info.clone());
+                    continue;
+                }
+                _ => {
+                    left_items.push(info.clone());
+                    continue;
+                }
+            };
+
+            let split = left_area * left_count + right_area * right_count;
+            let to_left = surface_area(&self.left.bbox.union(bbox)) * left_count
+                + right_area * (right_count - 1.0);
+            let to_right = left_area * (left_count - 1.0)
+                + surface_area(&self.right.bbox.union(bbox)) * right_count;
+            if to_left < split && to_left <= to_right {
+                left_items.push(info.clone());
+                continue;
+            } else if to_right < split {
+                right_items.push(info.clone());
+                continue;
             }
 
-            acc = Bin::default();
-            // backward scan uses the last bin down to second bin
-            for bin_idx in (1..=(NUM_BINS - 1)).rev() {
-                let bin = bins[bin_idx];
-                if bin.count > 0 {
-                    acc.bbox = acc.bbox.union(bin.bbox);
-                    acc.count += bin.count;
-                    costs[bin_idx - 1] += bin.count as f32 * bin.bbox.surface_area();
+            let (mut left_clip, mut right_clip) = (bbox, bbox);
+            left_clip.max[axis_idx] = self.plane;
+            right_clip.min[axis_idx] = self.plane;
+            let left_bbox = info
+                .item
+                .clipped_bounding_box(&left_clip, ctx.time0, ctx.time1);
+            let right_bbox = info
+                .item
+                .clipped_bounding_box(&right_clip, ctx.time0, ctx.time1);
+            match (left_bbox, right_bbox) {
+                (Some(left_bbox), Some(right_bbox)) => {
+                    left_items.push(ItemInfo::clipped(&info.item, left_bbox));
+                    right_items.push(ItemInfo::clipped(&info.item, right_bbox));
                 }
+                (Some(left_bbox), None) => {
+                    left_items.push(ItemInfo::clipped(&info.item, left_bbox))
+                }
+                (None, Some(right_bbox)) => {
+                    right_items.push(ItemInfo::clipped(&info.item, right_bbox))
+                }
+                // the item's surface only grazes its bounding box, keep it whole
+                (None, None) => left_items.push(info.clone()),
             }
+        }
 
-            // Find smallest split cost and its index into the bins array
-            let (min_bin_idx, min_cost) = costs
-                .iter()
-                .enumerate()
-                .min_by(|(_, a_cost), (_, b_cost)| a_cost.total_cmp(b_cost))
-                .unwrap();
+        (left_items, right_items)
+    }
+}
+
+impl BuildNode {
+    /// Creates a new node by splitting the given items into child nodes.
+    ///
+    /// If the SAH-computed split cost is less than simply going through all the items, do a SAH-based split and recurse.
+    /// Otherwise, do a simpler sort-and-half then recurse. If all items are unbounded, make a leaf node directly.
+    /// This function short-circuits directly into creating a leaf node with less than 4 items.
+    ///
+    /// With spatial splits enabled (see [TreeConfig::with_spatial_splits]), the SAH-based split may split space instead of items.
+    /// Big nodes build their children in parallel, if enabled.
+    fn new(mut items: Vec<ItemInfo>, ctx: &BuildContext) -> Self {
+        let num_items = items.len();
+
+        // given few items, make leaf
+        if num_items <= 4 {
+            return Self::Leaf(items);
+        }
+
+        // Get bounding_box for all item under this node
+        let total_bbox = fold_items(
+            &items,
+            None,
+            |acc: Option<BoundingBox>, item| match (acc, item.bbox) {
+                (Some(acc), Some(bbox)) => Some(acc.union(bbox)),
+                (acc, bbox) => acc.or(bbox),
+            },
+            |a, b| match (a, b) {
+                (Some(a), Some(b)) => Some(a.union(b)),
+                (a, b) => a.or(b),
+            },
+        );
+
+        let Some(total_bbox) = total_bbox else {
+            // full of unbounded objects, make leaf
+            return Self::Leaf(items);
+        };
 
-            // cost to make a node with all items is the # of items
-            let leaf_cost = num_items as f32;
+        // choose axis based on lengths of the surrounding bbox
+        let axis_idx = total_bbox.longest_axis() as usize;
 
-            // normalize cost
-            let min_cost = 0.5 + min_cost / total_bbox.surface_area();
+        // cost to make a node with all items is the # of items
+        let leaf_cost = num_items as f32;
+        let object_split =
+            ObjectSplit::find(&items, &total_bbox, axis_idx).filter(|split| split.cost < leaf_cost);
 
-            // init arena space before children
-            let new_idx = self.arena.add(TreeNode::Interior {
-                bbox: None,
-                left: 0,
-                right: 0,
-            });
+        // only split space when the children of the object split overlap a lot
+        let spatial_split = ctx.config.spatial_split_alpha.and_then(|alpha| {
+            let overlap = object_split
+                .as_ref()
+                .map_or(f32::INFINITY, |split| split.overlap);
+            if overlap <= alpha * ctx.root_area {
+                return None;
+            }
+            SpatialSplit::find(&items, &total_bbox, axis_idx, ctx).filter(|split| {
+                split.cost
+                    < object_split
+                        .as_ref()
+                        .map_or(leaf_cost, |object| object.cost)
+            })
+        });
+
+        // a spatial split must leave fewer items on each side, or the recursion may never end
+        let spatial_halves = spatial_split
+            .map(|split| split.partition(&items, ctx))
+            .filter(|(left, right)| left.len() < num_items && right.len() < num_items);
 
+        let (left_items, right_items) = match (spatial_halves, object_split) {
+            (Some(halves), _) => halves,
             // if its better to split, do SAH split
-            let (left_items, right_items) = if min_cost < leaf_cost {
-                items.into_iter().partition(|item| match item.centroid {
-                    Some(centroid) => {
-                        let off = centroid_bbox.offset(centroid)[axis_idx];
-                        let bin_idx = comp_bin_idx(off);
-                        bin_idx <= min_bin_idx
-                    }
-                    None => true,
-                })
-            } else {
+            (None, Some(split)) => split.partition(items),
+            (None, None) => {
                 // otherwise split items based on total_bbox cmp
                 items.sort_by(|a, b| match (a.bbox, b.bbox) {
-                    (None, None) => unreachable!(),
+                    (None, None) => std::cmp::Ordering::Equal,
                     (None, Some(_)) => std::cmp::Ordering::Less,
                     (Some(_), None) => std::cmp::Ordering::Greater,
                     (Some(a), Some(b)) => a.min[axis_idx].total_cmp(&(b.min[axis_idx])),
                 });
 
-                let halves = items.split_at(num_items / 2);
-                (halves.0.to_owned(), halves.1.to_owned())
+                let right = items.split_off(num_items / 2);
+                (items, right)
+            }
+        };
+
+        let (left, right) = join(
+            num_items >= SUBTREE_TASK_THRESHOLD,
+            || Self::new(left_items, ctx),
+            || Self::new(right_items, ctx),
+        );
+        Self::Interior {
+            bbox: total_bbox,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// Returns the [BoundingBox] of the node, if any of its items are bounded
+    fn bbox(&self) -> Option<BoundingBox> {
+        match self {
+            BuildNode::Leaf(info) => info
+                .iter()
+                .filter_map(|info| info.bbox)
+                .reduce(|acc, b| acc.union(b)),
+            BuildNode::Interior { bbox, .. } => Some(*bbox),
+        }
+    }
+}
+
+impl Tree {
+    /// Adds a node holding the given subtrees to the Tree, returning its index and the stack size needed to traverse it
+    ///
+    /// This is the collapse pass from binary to wide nodes: the interior subtree with the largest surface area
+    /// is repeatedly replaced by its two children until the node is full, pulling grandchildren up a level.
+    /// Nodes are added parent first, then each child's subtree in order,
+    /// so the arena layout only depends on the built subtrees and not on the order they finished in.
+    fn add_node(&mut self, mut children: Vec<BuildNode>) -> (ArenaIndex, usize) {
+        while children.len() < WIDTH {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, child)| match child {
+                    BuildNode::Interior { bbox, .. } => Some((idx, surface_area(bbox))),
+                    BuildNode::Leaf(_) => None,
+                })
+                .max_by(|(_, a), (_, b)| a.total_cmp(b));
+            let Some((idx, _)) = largest else {
+                // only leaves left
+                break;
             };
+            if let BuildNode::Interior { left, right, .. } = children.remove(idx) {
+                children.splice(idx..idx, [*left, *right]);
+            }
+        }
 
-            let left_idx = self.new_interior(left_items);
-            let right_idx = self.new_interior(right_items);
+        // init arena space before children
+        let new_idx = self.arena.add(TreeNode {
+            min: [Vec4::splat(f32::INFINITY); 3],
+            max: [Vec4::splat(f32::NEG_INFINITY); 3],
+            occupied: 0,
+            children: [TreeChild::Empty; WIDTH],
+        });
+
+        let num_children = children.len();
+        let mut mins = [Vec4::splat(f32::INFINITY); 3];
+        let mut maxs = [Vec4::splat(f32::NEG_INFINITY); 3];
+        let mut tree_children = [TreeChild::Empty; WIDTH];
+        // the deepest stack needed below any one child
+        let mut child_stack_size = 1;
+        for (lane, child) in children.into_iter().enumerate() {
+            // unbounded children span all of space, so they're always entered
+            let (min, max) = child.bbox().map_or(
+                (Vec3A::splat(f32::NEG_INFINITY), Vec3A::splat(f32::INFINITY)),
+                |bbox| (bbox.min, bbox.max),
+            );
+            for axis_idx in 0..3 {
+                mins[axis_idx][lane] = min[axis_idx];
+                maxs[axis_idx][lane] = max[axis_idx];
+            }
 
-            self.arena[new_idx] = TreeNode::Interior {
-                bbox: Some(total_bbox),
-                left: left_idx,
-                right: right_idx,
+            tree_children[lane] = match child {
+                BuildNode::Leaf(info) => {
+                    let start = self.items.len();
+                    self.items.extend(info.into_iter().map(|info| info.item));
+                    TreeChild::Leaf {
+                        start,
+                        end: self.items.len(),
+                    }
+                }
+                interior @ BuildNode::Interior { .. } => {
+                    let (idx, stack_size) = self.add_node(vec![interior]);
+                    child_stack_size = child_stack_size.max(stack_size);
+                    TreeChild::Node(idx)
+                }
             };
-            new_idx
-        } else {
-            // full of unbounded objects, make leaf
-            self.new_leaf(items)
         }
+
+        self.arena[new_idx] = TreeNode {
+            min: mins,
+            max: maxs,
+            occupied: (1 << num_children) - 1,
+            children: tree_children,
+        };
+        // the other children wait on the stack while one is traversed
+        (new_idx, num_children - 1 + child_stack_size)
     }
 
     /// Creates a new Tree using the given items
     ///
     /// This function serves as convenient wrapper for the Tree functionality;
-    /// it computes the [ItemInfo] for all items in the scene then calls [Tree::new_interior]
+    /// it computes the [ItemInfo] for all items in the scene then builds the nodes with [BuildNode::new]
     pub fn new(items: Vec<Arc<dyn Hittable>>, time0: f32, time1: f32) -> Self {
+        Self::with_config(items, time0, time1, TreeConfig::default())
+    }
+
+    /// Creates a new Tree using the given items, built according to `config`
+    pub fn with_config(
+        items: Vec<Arc<dyn Hittable>>,
+        time0: f32,
+        time1: f32,
+        config: TreeConfig,
+    ) -> Self {
         debug_assert!(!items.is_empty(), "Given empty scene!");
-        // TODO find way to create Tree without making an empty one first
-        let mut tree = Self {
-            arena: Arena::new(),
-            root: 0,
-        };
-        // We hope for a best case full binary tree and allocate enough space
-        // for such a case, minimizing allocations per-insertion
-        // See [Properties of binary trees from the Binary Tree Wikipedia article](https://en.wikipedia.org/wiki/Binary_tree#Properties_of_binary_trees)
-        tree.arena = Arena::with_capacity((items.len() * 2) - 1);
+        let lights = items.lights();
 
         // Compute info per item
+        #[cfg(feature = "parallel")]
+        let items = items.into_par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let items = items.into_iter();
         let added_info: Vec<ItemInfo> = items
-            .into_iter()
             .map(|item| {
                 let bbox = item.bounding_box(time0, time1);
                 let centroid = bbox.map(|bbox| bbox.centroid());
@@ -282,57 +737,239 @@ impl Tree {
             })
             .collect();
 
-        // create tree and get root index
-        tree.root = tree.new_interior(added_info);
+        let root_area = added_info
+            .iter()
+            .filter_map(|info| info.bbox)
+            .reduce(|acc, b| acc.union(b))
+            .map_or(0.0, |bbox| surface_area(&bbox));
+        let ctx = BuildContext {
+            config,
+            time0,
+            time1,
+            root_area,
+        };
+
+        let root = BuildNode::new(added_info, &ctx);
+
+        let mut tree = Self {
+            arena: Arena::new(),
+            root: 0,
+            items: Vec::new(),
+            bbox: root.bbox(),
+            lights,
+            stack_size: 0,
+        };
+        // collapse tree and get root index
+        (tree.root, tree.stack_size) = tree.add_node(vec![root]);
         tree.arena.shrink_to_fit();
+        tree.items.shrink_to_fit();
         tree
     }
 
     /// The underlying intersection routine for use in the [Hittable] trait implementation
     ///
-    /// Since [Hittable::hit] doesn't use tree indices, we have to call this routine
-    /// in order to recursively build the acceleration structure
+    /// Traverses the tree with an explicit `stack` of children to visit and the distances at which the ray enters them,
+    /// which must hold at least [Tree::stack_size] entries.
+    /// Children of a node are visited front-to-back, so farther children can be skipped once a closer hit is found.
     fn hit_impl(
         &self,
-        idx: ArenaIndex,
+        stack: &mut [(TreeChild, f32)],
         ray: &Ray,
-        ray_inv_dir: glam::Vec3A,
         t_min: f32,
         t_max: f32,
     ) -> Option<HitRecord> {
-        let node = &self.arena[idx];
-
-        // if there's a box, check against it first
-        if let Some(bbox) = node.get_bbox() {
-            if !bbox.hit_with_inv(ray, ray_inv_dir, t_min, t_max) {
-                return None;
+        let origin = [
+            Vec4::splat(ray.origin.x),
+            Vec4::splat(ray.origin.y),
+            Vec4::splat(ray.origin.z),
+        ];
+        let ray_inv_dir = ray.direction.recip();
+        let inv_dir = [
+            Vec4::splat(ray_inv_dir.x),
+            Vec4::splat(ray_inv_dir.y),
+            Vec4::splat(ray_inv_dir.z),
+        ];
+
+        let mut closest = t_max;
+        let mut hit = None;
+        stack[0] = (TreeChild::Node(self.root), t_min);
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let (child, t_enter) = stack[len];
+            // skip children behind a hit found since they were pushed
+            if t_enter > closest {
+                continue;
             }
-        }
 
-        match node {
-            // a leaf node delegates to its contained item
-            TreeNode::Leaf { items, .. } => items.hit(ray, t_min, t_max),
-            TreeNode::Interior { left, right, .. } => {
-                // recurse into children
-                let left_hit = self.hit_impl(*left, ray, ray_inv_dir, t_min, t_max);
-
-                let t_max = left_hit.as_ref().map_or(t_max, |rec| rec.t);
+            match child {
+                TreeChild::Empty => {}
+                // a leaf delegates to its contained items
+                TreeChild::Leaf { start, end } => {
+                    for item in &self.items[start..end] {
+                        if let Some(rec) = item.hit(ray, t_min, closest) {
+                            closest = rec.t;
+                            hit = Some(rec);
+                        }
+                    }
+                }
+                TreeChild::Node(idx) => {
+                    let node = &self.arena[idx];
+                    let (t_near, mut mask) = node.hit_children(&origin, &inv_dir, t_min, closest);
+
+                    let mut order = [(TreeChild::Empty, 0.0); WIDTH];
+                    let mut count = 0;
+                    while mask != 0 {
+                        let lane = mask.trailing_zeros() as usize;
+                        mask &= mask - 1;
+                        order[count] = (node.children[lane], t_near[lane]);
+                        count += 1;
+                    }
 
-                match self.hit_impl(*right, ray, ray_inv_dir, t_min, t_max) {
-                    Some(right_hit) => Some(right_hit),
-                    None => left_hit,
+                    // push the farthest child first, so the nearest is visited next
+                    order[..count].sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+                    stack[len..len + count].copy_from_slice(&order[..count]);
+                    len += count;
                 }
             }
         }
+
+        hit
     }
 }
 
 impl Hittable for Tree {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        self.hit_impl(self.root, ray, ray.direction.recip(), t_min, t_max)
+        if self.stack_size <= INLINE_STACK_SIZE {
+            let mut stack = [(TreeChild::Empty, 0.0); INLINE_STACK_SIZE];
+            self.hit_impl(&mut stack, ray, t_min, t_max)
+        } else {
+            let mut stack = vec![(TreeChild::Empty, 0.0); self.stack_size];
+            self.hit_impl(&mut stack, ray, t_min, t_max)
+        }
     }
 
     fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<BoundingBox> {
-        self.get_bbox(self.root)
+        self.bbox
+    }
+
+    fn lights(&self) -> HittableList {
+        self.lights.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::colors,
+        hittables::{HittableList, Triangle},
+        material::Material,
+    };
+    use glam::Vec3A;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    /// Long, thin triangles crossing the whole scene at random angles, the worst case for centroid splits
+    fn slivers(rng: &mut SmallRng) -> HittableList {
+        let material = Arc::new(Material::Lambertian {
+            albedo: Arc::new(colors::WHITE),
+        });
+        let mut point = || Vec3A::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+        (0..200)
+            .map(|_| {
+                let (a, b) = (point(), point());
+                let c = a + (b - a).cross(Vec3A::Y).normalize_or_zero() * 0.1;
+                Arc::new(Triangle::new(a, b, c, &material)) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    fn num_references(tree: &Tree) -> usize {
+        tree.items.len()
+    }
+
+    #[test]
+    fn spatial_splits_match_hits() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let items = slivers(&mut rng);
+        let plain = Tree::new(items.clone(), 0.0, 1.0);
+        let split = Tree::with_config(
+            items.clone(),
+            0.0,
+            1.0,
+            TreeConfig::default().with_spatial_splits(1e-5),
+        );
+
+        assert_eq!(num_references(&plain), items.len());
+        assert!(
+            num_references(&split) > items.len(),
+            "straddling slivers should be referenced by several leaves"
+        );
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Vec3A::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+            let direction = Vec3A::new(rng.gen(), rng.gen(), rng.gen()) - 0.5;
+            let ray = Ray::new(origin, direction, 0.0);
+
+            let expected = items.hit(&ray, 0.001, f32::INFINITY).map(|rec| rec.t);
+            assert_eq!(
+                plain.hit(&ray, 0.001, f32::INFINITY).map(|rec| rec.t),
+                expected
+            );
+            assert_eq!(
+                split.hit(&ray, 0.001, f32::INFINITY).map(|rec| rec.t),
+                expected
+            );
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 100, "too few rays hit the scene to be meaningful");
+    }
+
+    /// Describes every node in the arena, with items identified by their address
+    #[cfg(feature = "parallel")]
+    fn layout(tree: &Tree) -> Vec<String> {
+        let nodes = (0..tree.arena.len()).map(|idx| format!("{:?}", tree.arena[idx]));
+        let items = tree
+            .items
+            .iter()
+            .map(|item| format!("{:?}", Arc::as_ptr(item) as *const ()));
+        nodes.chain(items).collect()
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn deterministic_parallel_build() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut items = slivers(&mut rng);
+        // enough small triangles to bin and build in parallel
+        let material = Arc::new(Material::Lambertian {
+            albedo: Arc::new(colors::WHITE),
+        });
+        items.extend((0..2 * PARALLEL_BINNING_THRESHOLD).map(|_| {
+            let a = Vec3A::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+            Arc::new(Triangle::new(
+                a,
+                a + Vec3A::X * 0.05,
+                a + Vec3A::Y * 0.05,
+                &material,
+            )) as Arc<dyn Hittable>
+        }));
+
+        for config in [
+            TreeConfig::default(),
+            TreeConfig::default().with_spatial_splits(1e-5),
+        ] {
+            let build = |threads| {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                pool.install(|| layout(&Tree::with_config(items.clone(), 0.0, 1.0, config)))
+            };
+            let serial = build(1);
+            assert_eq!(serial, build(4), "layout changed with the thread count");
+        }
     }
 }
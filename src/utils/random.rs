@@ -3,7 +3,7 @@
 //! Relies on the [rand] and [rand_distr] crates
 
 use glam::Vec3A;
-use rand::Rng;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 use rand_distr::{Distribution, UnitDisc, UnitSphere};
 
 /// Generates a random [Vec3A] within the unit sphere (radius 1).
@@ -32,6 +32,45 @@ pub fn rand_vec3_in_unit_disk(rng: &mut impl Rng) -> Vec3A {
     Vec3A::new(x, y, 0.0)
 }
 
+/// Generates a random unit [Vec3A] within the cone around `axis` with the given cosine of its half-angle.
+///
+/// Directions are distributed uniformly over the cone's solid angle.
+/// See [Shirley's RT: The Rest of Your Life's section on sampling a sphere](https://raytracing.github.io/books/RayTracingTheRestOfYourLife.html#samplinglightsdirectly)
+pub fn rand_vec3_in_cone(rng: &mut impl Rng, axis: Vec3A, cos_theta_max: f32) -> Vec3A {
+    let cos_theta = 1.0 - rng.gen::<f32>() * (1.0 - cos_theta_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = std::f32::consts::TAU * rng.gen::<f32>();
+
+    let (tangent, bitangent) = axis.any_orthonormal_pair();
+    (tangent * phi.cos() * sin_theta + bitangent * phi.sin() * sin_theta + axis * cos_theta)
+        .normalize()
+}
+
+/// Mixes the bits of `value` using the finalizer of [SplitMix64](https://prng.di.unimi.it/splitmix64.c)
+#[inline]
+fn mix64(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+/// Creates the generator for sample number `sample` of the pixel at (`x`, `y`).
+///
+/// The generator only depends on its arguments, so a render can be repeated or continued
+/// regardless of the order in which pixels and samples are computed.
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: u32) -> SmallRng {
+    let pixel = (x as u64) << 32 | y as u64;
+    SmallRng::seed_from_u64(mix64(mix64(seed ^ mix64(pixel)) ^ sample as u64))
+}
+
+/// Creates the generator for the random decisions made while intersecting a ray with the given `seed`,
+/// see [crate::ray::Ray::seed]
+///
+/// Mixing in a `salt` specific to the intersected object keeps the decisions of objects along the same ray independent.
+pub fn ray_rng(seed: u64, salt: u64) -> SmallRng {
+    SmallRng::seed_from_u64(mix64(seed ^ mix64(salt)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -47,6 +86,20 @@ mod tests {
         )
     }
 
+    #[test]
+    fn rand_cone() {
+        let mut rng = rand::thread_rng();
+        let axis = Vec3A::new(1.0, 2.0, 3.0).normalize();
+        let cos_theta_max = 0.9;
+        for _ in 0..100 {
+            let res = rand_vec3_in_cone(&mut rng, axis, cos_theta_max);
+            assert!(
+                res.is_normalized() && res.dot(axis) >= cos_theta_max - 1e-5,
+                "expected a unit vector within the cone, found {res}"
+            )
+        }
+    }
+
     #[test]
     fn rand_unit_disk() {
         let mut rng = rand::thread_rng();